@@ -1,14 +1,8 @@
-use std::{
-    collections::HashMap,
-    fs,
-    io::{self, Seek},
-    path::{Path, PathBuf},
-    sync,
+use pyo3::{
+    exceptions::{PyIOError, PyValueError},
+    prelude::*,
 };
 
-use pyo3::prelude::*;
-use rayon::prelude::*;
-
 #[pyclass(module = "tsar.tsar")]
 struct Writer {
     w: Option<tsar::Builder<std::fs::File>>,
@@ -45,6 +39,7 @@ impl Writer {
         let opt = tsar::BlobWriteOption {
             error_limit,
             target_file,
+            ..Default::default()
         };
         let ty = match ty {
             "f32" => Some(tsar::DataType::Float32),
@@ -59,6 +54,13 @@ impl Writer {
             "u32" => Some(tsar::DataType::Uint32),
             "i64" => Some(tsar::DataType::Int64),
             "u64" => Some(tsar::DataType::Uint64),
+            "bool" => Some(tsar::DataType::Bool),
+            "c64" => Some(tsar::DataType::Complex64),
+            "c128" => Some(tsar::DataType::Complex128),
+            "i4" => Some(tsar::DataType::Int4),
+            "u4" => Some(tsar::DataType::Uint4),
+            "f8e4m3" => Some(tsar::DataType::Float8E4M3),
+            "f8e5m2" => Some(tsar::DataType::Float8E5M2),
             _ => None,
         };
 
@@ -88,7 +90,6 @@ impl Writer {
 #[pyclass(module = "tsar.tsar")]
 struct Reader {
     r: tsar::Archive<std::fs::File>,
-    lk: sync::Mutex<()>,
 }
 
 #[pymethods]
@@ -96,118 +97,33 @@ impl Reader {
     #[new]
     fn new(src: &str) -> PyResult<Self> {
         Ok(Self {
-            r: tsar::Archive::new(std::fs::File::open(src)?).unwrap(),
-            lk: sync::Mutex::new(()),
+            r: tsar::Archive::new(std::fs::File::open(src)?)
+                .map_err(|e| PyIOError::new_err(e.to_string()))?,
         })
     }
 
-    fn extract_files(&mut self, dst: &str) -> PyResult<()> {
-        let files = self
-            .r
-            .file_names()
-            .map(|s| s.to_owned())
-            .collect::<Vec<_>>();
-        let base = PathBuf::from(dst);
-        for f in files.iter() {
-            write_all(base.join(f), self.r.file_by_name(f).unwrap())?;
-        }
-        Ok(())
-    }
-
-    fn extract_blobs(&mut self, dst: &str) -> PyResult<()> {
-        let base = PathBuf::from(dst);
-        let mut blobs = self
-            .r
-            .blob_names()
-            .map(|s| s.to_owned())
-            .collect::<Vec<_>>()
-            .into_iter()
-            .map(|b| self.r.blob_by_name(b))
-            .collect::<tsar::Result<Vec<_>>>()
-            .unwrap();
-
-        let mut m = HashMap::<&str, u64>::new();
-
-        for (k, v) in blobs.iter().flat_map(|b| {
-            if let Some((target_file, offset)) = b.target_file() {
-                Some((
-                    target_file,
-                    offset + b.byte_len().expect("unknown blob length") as u64,
-                ))
-            } else {
-                None
-            }
-        }) {
-            let vv = m.entry(k).or_default();
-            *vv = v.max(*vv);
-        }
-        for (k, v) in m.iter() {
-            create_file(base.join(k), *v)?;
-        }
-
-        blobs.par_iter_mut().for_each(|b| {
-            if let Some((target_file, offset)) = b.target_file() {
-                let mut tmp = Vec::<u8>::new();
-                let p = base.join(target_file);
-                std::io::copy(b, &mut tmp).unwrap();
-
-                let _lk = self.lk.lock();
-                write_to(p, std::io::Cursor::new(tmp), offset).unwrap();
+    #[pyo3(signature = (dst, threads=0, overwrite="replace"))]
+    fn extract(
+        &mut self,
+        py: Python<'_>,
+        dst: &str,
+        threads: usize,
+        overwrite: &str,
+    ) -> PyResult<()> {
+        let overwrite = match overwrite {
+            "error" => tsar::Overwrite::Error,
+            "skip" => tsar::Overwrite::Skip,
+            "replace" => tsar::Overwrite::Replace,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "invalid overwrite policy: {overwrite}"
+                )))
             }
-        });
-        Ok(())
-    }
-}
-
-fn write_all(p: impl AsRef<Path>, mut r: impl io::Read) -> std::io::Result<()> {
-    let outpath = p.as_ref();
-    if let Some(p) = outpath.parent() {
-        if !p.exists() {
-            fs::create_dir_all(p)?;
-        }
-    }
-    let mut outfile = fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(outpath)?;
-    io::copy(&mut r, &mut outfile)?;
-    Ok(())
-}
-
-fn write_to(p: impl AsRef<Path>, mut r: impl io::Read, offset: u64) -> std::io::Result<()> {
-    let outpath = p.as_ref();
-    if let Some(p) = outpath.parent() {
-        if !p.exists() {
-            fs::create_dir_all(p)?;
-        }
-    }
-    let mut outfile = fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(outpath)?;
-    if offset > 0 {
-        outfile.seek(io::SeekFrom::Start(offset))?;
-    }
-    io::copy(&mut r, &mut outfile)?;
-    Ok(())
-}
-
-fn create_file(p: impl AsRef<Path>, sz: u64) -> std::io::Result<()> {
-    let outpath = p.as_ref();
-    if let Some(p) = outpath.parent() {
-        if !p.exists() {
-            fs::create_dir_all(p)?;
-        }
+        };
+        let r = &mut self.r;
+        py.detach(|| r.extract_to(dst, tsar::ExtractOption { threads, overwrite }))
+            .map_err(|e| PyIOError::new_err(e.to_string()))
     }
-    let outfile = fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(outpath)?;
-    outfile.set_len(sz)?;
-    Ok(())
 }
 
 /// A Python module implemented in Rust.
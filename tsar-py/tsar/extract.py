@@ -3,7 +3,6 @@ import pathlib
 from tsar.tsar import Reader
 
 
-def extract(src: pathlib.Path, dst: pathlib.Path):
+def extract(src: pathlib.Path, dst: pathlib.Path, overwrite: str = "replace"):
     rdr = Reader(str(src))
-    rdr.extract_files(str(dst))
-    rdr.extract_blobs(str(dst))
+    rdr.extract(str(dst), overwrite=overwrite)
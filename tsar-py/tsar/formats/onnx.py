@@ -1,5 +1,4 @@
 import pathlib
-import sys
 import itertools
 import array
 import json
@@ -55,6 +54,22 @@ def _get_attribute_tensors_from_graph(
             )
 
 
+# tensor types that are only packed from raw_data, by tsar dtype string
+_RAW_DATA_TYPES = {
+    getattr(onnx.TensorProto, k): v
+    for k, v in {
+        "BOOL": "bool",
+        "COMPLEX64": "c64",
+        "COMPLEX128": "c128",
+        "INT4": "i4",
+        "UINT4": "u4",
+        "FLOAT8E4M3FN": "f8e4m3",
+        "FLOAT8E5M2": "f8e5m2",
+    }.items()
+    if hasattr(onnx.TensorProto, k)
+}
+
+
 def _num_elements(tensor: onnx.TensorProto) -> int:
     """Return the number of elements in a tensor."""
     p = 1
@@ -72,6 +87,7 @@ def save(
     size_limit: int = 16 * 1024,
     progress_fn: Optional[Callable[[int, int], None]] = None,
 ):
+    # size_limit counts payload bytes, the same as the Rust packer
     model = onnx.load(str(src))
     tensors = sorted(_get_all_tensors(model), key=_num_elements)
     blob_list = {}
@@ -87,7 +103,7 @@ def save(
         if tensor.data_type == onnx.TensorProto.FLOAT:
             if (
                 tensor.HasField("raw_data")
-                and sys.getsizeof(tensor.raw_data) >= size_limit
+                and len(tensor.raw_data) >= size_limit
             ):
                 save_external = ("f32", tensor.raw_data)
                 tensor.ClearField("raw_data")
@@ -101,7 +117,7 @@ def save(
         elif tensor.data_type == onnx.TensorProto.DOUBLE:
             if (
                 tensor.HasField("raw_data")
-                and sys.getsizeof(tensor.raw_data) >= size_limit
+                and len(tensor.raw_data) >= size_limit
             ):
                 save_external = ("f64", tensor.raw_data)
                 tensor.ClearField("raw_data")
@@ -115,7 +131,7 @@ def save(
         elif tensor.data_type == onnx.TensorProto.BFLOAT16:
             if (
                 tensor.HasField("raw_data")
-                and sys.getsizeof(tensor.raw_data) >= size_limit
+                and len(tensor.raw_data) >= size_limit
             ):
                 save_external = ("bf16", tensor.raw_data)
                 tensor.ClearField("raw_data")
@@ -123,7 +139,7 @@ def save(
         elif tensor.data_type == onnx.TensorProto.FLOAT16:
             if (
                 tensor.HasField("raw_data")
-                and sys.getsizeof(tensor.raw_data) >= size_limit
+                and len(tensor.raw_data) >= size_limit
             ):
                 save_external = ("f16", tensor.raw_data)
                 tensor.ClearField("raw_data")
@@ -137,7 +153,7 @@ def save(
         elif tensor.data_type == onnx.TensorProto.INT8:
             if (
                 tensor.HasField("raw_data")
-                and sys.getsizeof(tensor.raw_data) >= size_limit
+                and len(tensor.raw_data) >= size_limit
             ):
                 save_external = ("i8", tensor.raw_data)
                 tensor.ClearField("raw_data")
@@ -151,7 +167,7 @@ def save(
         elif tensor.data_type == onnx.TensorProto.UINT8:
             if (
                 tensor.HasField("raw_data")
-                and sys.getsizeof(tensor.raw_data) >= size_limit
+                and len(tensor.raw_data) >= size_limit
             ):
                 save_external = ("u8", tensor.raw_data)
                 tensor.ClearField("raw_data")
@@ -165,7 +181,7 @@ def save(
         elif tensor.data_type == onnx.TensorProto.INT16:
             if (
                 tensor.HasField("raw_data")
-                and sys.getsizeof(tensor.raw_data) >= size_limit
+                and len(tensor.raw_data) >= size_limit
             ):
                 save_external = ("i16", tensor.raw_data)
                 tensor.ClearField("raw_data")
@@ -179,7 +195,7 @@ def save(
         elif tensor.data_type == onnx.TensorProto.UINT16:
             if (
                 tensor.HasField("raw_data")
-                and sys.getsizeof(tensor.raw_data) >= size_limit
+                and len(tensor.raw_data) >= size_limit
             ):
                 save_external = ("u16", tensor.raw_data)
                 tensor.ClearField("raw_data")
@@ -193,7 +209,7 @@ def save(
         elif tensor.data_type == onnx.TensorProto.INT32:
             if (
                 tensor.HasField("raw_data")
-                and sys.getsizeof(tensor.raw_data) >= size_limit
+                and len(tensor.raw_data) >= size_limit
             ):
                 save_external = ("i32", tensor.raw_data)
                 tensor.ClearField("raw_data")
@@ -207,7 +223,7 @@ def save(
         elif tensor.data_type == onnx.TensorProto.UINT32:
             if (
                 tensor.HasField("raw_data")
-                and sys.getsizeof(tensor.raw_data) >= size_limit
+                and len(tensor.raw_data) >= size_limit
             ):
                 save_external = ("u32", tensor.raw_data)
                 tensor.ClearField("raw_data")
@@ -221,7 +237,7 @@ def save(
         elif tensor.data_type == onnx.TensorProto.INT64:
             if (
                 tensor.HasField("raw_data")
-                and sys.getsizeof(tensor.raw_data) >= size_limit
+                and len(tensor.raw_data) >= size_limit
             ):
                 save_external = ("i64", tensor.raw_data)
                 tensor.ClearField("raw_data")
@@ -235,7 +251,7 @@ def save(
         elif tensor.data_type == onnx.TensorProto.UINT64:
             if (
                 tensor.HasField("raw_data")
-                and sys.getsizeof(tensor.raw_data) >= size_limit
+                and len(tensor.raw_data) >= size_limit
             ):
                 save_external = ("u64", tensor.raw_data)
                 tensor.ClearField("raw_data")
@@ -246,11 +262,19 @@ def save(
                 save_external = ("u64", data_u64.tobytes())
                 tensor.ClearField("uint64_data")
 
+        elif tensor.data_type in _RAW_DATA_TYPES:
+            if (
+                tensor.HasField("raw_data")
+                and len(tensor.raw_data) >= size_limit
+            ):
+                save_external = (_RAW_DATA_TYPES[tensor.data_type], tensor.raw_data)
+                tensor.ClearField("raw_data")
+
         else:
             # unknown data type
             if (
                 tensor.HasField("raw_data")
-                and sys.getsizeof(tensor.raw_data) >= size_limit
+                and len(tensor.raw_data) >= size_limit
             ):
                 save_external = ("", tensor.raw_data)
                 tensor.ClearField("raw_data")
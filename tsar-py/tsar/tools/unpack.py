@@ -8,5 +8,11 @@ def main() -> None:
     parser = argparse.ArgumentParser()
     parser.add_argument("src", metavar="INPUT", type=pathlib.Path)
     parser.add_argument("dst", metavar="OUTPUT", type=pathlib.Path)
+    parser.add_argument(
+        "--overwrite",
+        default="replace",
+        choices=["error", "skip", "replace"],
+        help="what to do with files that already exist",
+    )
     args = parser.parse_args()
-    extract(args.src, args.dst)
+    extract(args.src, args.dst, overwrite=args.overwrite)
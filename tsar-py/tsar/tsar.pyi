@@ -22,5 +22,4 @@ class Writer:
 
 class Reader:
     def __init__(self, src: str): ...
-    def extract_files(self, dst: str): ...
-    def extract_blobs(self, dst: str): ...
+    def extract(self, dst: str, threads: int = 0, overwrite: str = "replace"): ...
@@ -1,7 +1,37 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+//! Codec throughput, run with `cargo bench --features bench`.
 
-fn criterion_benchmark(c: &mut Criterion) {
-    const N: usize = 1024 * 1024;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tsar::codec::{simd, BufferList, Codec, Compress, Convert, Fpc, Split, Zfp};
+
+const N: usize = 1024 * 1024;
+
+fn f32_data() -> Vec<u8> {
+    (0..N / 4)
+        .flat_map(|i| ((i as f32) * 0.001).sin().to_le_bytes())
+        .collect()
+}
+
+fn f64_data() -> Vec<u8> {
+    (0..N / 8)
+        .flat_map(|i| ((i as f64) * 0.001).sin().to_le_bytes())
+        .collect()
+}
+
+fn f16_data() -> Vec<u8> {
+    (0..N / 2)
+        .flat_map(|i| half::f16::from_f32(((i as f32) * 0.001).sin()).to_le_bytes())
+        .collect()
+}
+
+fn bf16_data() -> Vec<u8> {
+    (0..N / 2)
+        .flat_map(|i| half::bf16::from_f32(((i as f32) * 0.001).sin()).to_le_bytes())
+        .collect()
+}
+
+fn diff(c: &mut Criterion) {
     let mut group = c.benchmark_group("diff");
     group.throughput(Throughput::Bytes(N as u64));
     group.bench_function("byte", |b| {
@@ -20,12 +50,154 @@ fn criterion_benchmark(c: &mut Criterion) {
         b.iter(|| tsar::DataType::Uint32.max_difference(black_box(&src), black_box(&targ)))
     });
     group.bench_function("f32", |b| {
-        let src = vec![0; N];
+        let src = f32_data();
         let targ = vec![0; N];
         b.iter(|| tsar::DataType::Float32.max_difference(black_box(&src), black_box(&targ)))
     });
+    group.bench_function("f32_scalar", |b| {
+        let src = f32_data();
+        let targ = vec![0; N];
+        b.iter(|| simd::scalar::max_diff_f32(black_box(&src), black_box(&targ)))
+    });
+    group.bench_function("f64", |b| {
+        let src = f64_data();
+        let targ = vec![0; N];
+        b.iter(|| tsar::DataType::Float64.max_difference(black_box(&src), black_box(&targ)))
+    });
+    group.bench_function("f64_scalar", |b| {
+        let src = f64_data();
+        let targ = vec![0; N];
+        b.iter(|| simd::scalar::max_diff_f64(black_box(&src), black_box(&targ)))
+    });
+    group.bench_function("f16", |b| {
+        let src = f16_data();
+        let targ = vec![0; N];
+        b.iter(|| tsar::DataType::Float16.max_difference(black_box(&src), black_box(&targ)))
+    });
+    group.bench_function("f16_scalar", |b| {
+        let src = f16_data();
+        let targ = vec![0; N];
+        b.iter(|| simd::scalar::max_diff_f16(black_box(&src), black_box(&targ)))
+    });
+    group.bench_function("bf16", |b| {
+        let src = bf16_data();
+        let targ = vec![0; N];
+        b.iter(|| tsar::DataType::Bfloat16.max_difference(black_box(&src), black_box(&targ)))
+    });
+    group.bench_function("i32_scalar", |b| {
+        let src = vec![0; N];
+        let targ = (0..N).map(|i| i as u8).collect::<Vec<_>>();
+        b.iter(|| simd::scalar::max_diff_i32(black_box(&src), black_box(&targ)))
+    });
+    group.finish();
+}
+
+/// Bench encoding and decoding `data` with one codec stage.
+fn stage(c: &mut Criterion, name: &str, codec: &impl Codec, data: &[u8]) {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    let mut out = BufferList::new();
+    group.bench_function("encode", |b| {
+        b.iter(|| codec.encode([black_box(data)], &mut out).unwrap())
+    });
+    codec.encode([data], &mut out).unwrap();
+    let mut back = BufferList::new();
+    group.bench_function("decode", |b| {
+        b.iter(|| {
+            codec
+                .decode(black_box(out.iter_slice()), &mut back)
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn stages(c: &mut Criterion) {
+    let f32_data = f32_data();
+    let f64_data = f64_data();
+    stage(c, "split_f32", &Split::Float32, &f32_data);
+    stage(c, "split_f64", &Split::Float64, &f64_data);
+    stage(c, "split_f16", &Split::Float16, &f16_data());
+    stage(c, "split_bf16", &Split::Bfloat16, &bf16_data());
+    stage(c, "fpc_f32", &Fpc::Float32, &f32_data);
+    stage(c, "fpc_f64", &Fpc::Float64, &f64_data);
+    stage(
+        c,
+        "convert_f32_bf16",
+        &Convert::Float32ToBfloat16,
+        &f32_data,
+    );
+    stage(c, "convert_f64_f32", &Convert::Float64ToFloat32, &f64_data);
+    stage(
+        c,
+        "convert_f64_bf16",
+        &Convert::Float64ToBfloat16,
+        &f64_data,
+    );
+    stage(c, "zstd", &Compress::Zstd(9), &f32_data);
+    let shape = [N / 4];
+    stage(
+        c,
+        "zfp_f32",
+        &Zfp::new(tsar::DataType::Float32, 1, &shape, 1e-3),
+        &f32_data,
+    );
+
+    let mut group = c.benchmark_group("split_f32_scalar");
+    group.throughput(Throughput::Bytes(N as u64));
+    group.bench_function("encode", |b| {
+        let (mut e, mut m) = (vec![], vec![]);
+        b.iter(|| {
+            e.clear();
+            m.clear();
+            simd::scalar::split_f32(black_box(&f32_data), &mut e, &mut m)
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("split_f64_scalar");
+    group.throughput(Throughput::Bytes(N as u64));
+    group.bench_function("encode", |b| {
+        let (mut e, mut m) = (vec![], vec![]);
+        b.iter(|| {
+            e.clear();
+            m.clear();
+            simd::scalar::split_f64(black_box(&f64_data), &mut e, &mut m)
+        })
+    });
+    group.finish();
+}
+
+/// Bench a lossless stage followed by zstd, with the compression ratio as
+/// the benchmark parameter.
+fn lossless(c: &mut Criterion, name: &str, codec: &impl Codec, data: &[u8]) {
+    let zstd = Compress::Zstd(9);
+    let (mut tmp, mut out) = (BufferList::new(), BufferList::new());
+    codec.encode([data], &mut tmp).unwrap();
+    zstd.encode(tmp.iter_slice(), &mut out).unwrap();
+    let len = out.iter().map(Vec::len).sum::<usize>();
+    assert!(len < data.len(), "{name} does not compress");
+    let ratio = format!("ratio {:.3}", len as f64 / data.len() as f64);
+
+    let mut group = c.benchmark_group("lossless");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function(BenchmarkId::new(name, ratio), |b| {
+        b.iter(|| {
+            codec.encode([black_box(data)], &mut tmp).unwrap();
+            zstd.encode(tmp.iter_slice(), &mut out).unwrap();
+        })
+    });
     group.finish();
 }
 
-criterion_group!(benches, criterion_benchmark);
+fn pipelines(c: &mut Criterion) {
+    let f32_data = f32_data();
+    let f64_data = f64_data();
+    lossless(c, "split_zstd_f32", &Split::Float32, &f32_data);
+    lossless(c, "fpc_zstd_f32", &Fpc::Float32, &f32_data);
+    lossless(c, "split_zstd_f64", &Split::Float64, &f64_data);
+    lossless(c, "fpc_zstd_f64", &Fpc::Float64, &f64_data);
+}
+
+criterion_group!(benches, diff, stages, pipelines);
 criterion_main!(benches);
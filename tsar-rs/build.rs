@@ -2,7 +2,7 @@ fn main() {
     protobuf_codegen::Codegen::new()
         .pure()
         .include("src")
-        .inputs(["src/tsar.proto"])
+        .inputs(["src/tsar.proto", "src/onnx.proto"])
         .cargo_out_dir("pb")
         .run_from_script();
 }
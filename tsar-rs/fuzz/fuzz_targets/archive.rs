@@ -0,0 +1,26 @@
+//! Open arbitrary bytes as an archive and read everything in it.
+#![no_main]
+
+use std::io::{self, Cursor, Read};
+
+use libfuzzer_sys::fuzz_target;
+use tsar::Archive;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut a) = Archive::new(Cursor::new(data)) else {
+        return;
+    };
+    let files = a.file_names().map(str::to_owned).collect::<Vec<_>>();
+    for f in files {
+        if let Ok(mut r) = a.file_by_name(&f) {
+            let _ = io::copy(&mut r, &mut io::sink());
+        }
+    }
+    let blobs = a.blob_names().map(str::to_owned).collect::<Vec<_>>();
+    for b in blobs {
+        if let Ok(mut blob) = a.blob_by_name(&b) {
+            let _ = (blob.byte_len(), blob.target_file(), blob.metadata());
+            let _ = blob.read_to_end(&mut Vec::new());
+        }
+    }
+});
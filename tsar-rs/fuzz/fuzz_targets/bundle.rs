@@ -0,0 +1,40 @@
+//! Arbitrary bundle metadata in a well-formed zip, with chunks named `0`,
+//! `1`, ... so that blobs can find them.
+#![no_main]
+
+use std::io::{Cursor, Read, Write};
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use tsar::Archive;
+use zip::write::SimpleFileOptions;
+
+#[derive(Arbitrary, Debug)]
+struct Input<'a> {
+    bundle: &'a [u8],
+    chunks: Vec<&'a [u8]>,
+}
+
+fuzz_target!(|input: Input| {
+    let mut z = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let opt = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    z.start_file(".tsar/bundle", opt).unwrap();
+    z.write_all(input.bundle).unwrap();
+    for (i, c) in input.chunks.iter().enumerate() {
+        z.start_file(format!(".tsar/chunks/{i}"), opt).unwrap();
+        z.write_all(c).unwrap();
+    }
+    let buf = z.finish().unwrap().into_inner();
+
+    let Ok(mut a) = Archive::new(Cursor::new(buf)) else {
+        return;
+    };
+    let _ = a.metadata();
+    let blobs = a.blob_names().map(str::to_owned).collect::<Vec<_>>();
+    for b in blobs {
+        if let Ok(mut blob) = a.blob_by_name(&b) {
+            let _ = (blob.byte_len(), blob.target_file(), blob.metadata());
+            let _ = blob.read_to_end(&mut Vec::new());
+        }
+    }
+});
@@ -0,0 +1,70 @@
+//! Decode arbitrary chunks with each codec stage.
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use tsar::{
+    codec::{BufferList, ByteSwap, Codec, Compress, Convert, Fpc, Split, Zfp},
+    DataType,
+};
+
+#[derive(Arbitrary, Debug)]
+enum Stage {
+    Zstd,
+    Convert(u8),
+    Split(u8),
+    Fpc(u8),
+    Zfp { double: bool, shape: Vec<u16> },
+    ByteSwap(u8),
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input<'a> {
+    stage: Stage,
+    inputs: Vec<&'a [u8]>,
+}
+
+fuzz_target!(|input: Input| {
+    let data = input.inputs.iter().copied();
+    let mut out = BufferList::new();
+    let _ = match input.stage {
+        Stage::Zstd => Compress::Zstd(9).decode(data, &mut out),
+        Stage::Convert(i) => [
+            Convert::Float32ToBfloat16,
+            Convert::Float32ToFloat16,
+            Convert::Float64ToBfloat16,
+            Convert::Float64ToFloat16,
+            Convert::Float64ToFloat32,
+        ][usize::from(i) % 5]
+            .decode(data, &mut out),
+        Stage::Split(i) => match i % 4 {
+            0 => Split::Bfloat16,
+            1 => Split::Float16,
+            2 => Split::Float32,
+            _ => Split::Float64,
+        }
+        .decode(data, &mut out),
+        Stage::Fpc(i) => match i % 4 {
+            0 => Fpc::Bfloat16,
+            1 => Fpc::Float16,
+            2 => Fpc::Float32,
+            _ => Fpc::Float64,
+        }
+        .decode(data, &mut out),
+        Stage::Zfp { double, shape } => {
+            let shape = shape.iter().map(|&d| usize::from(d)).collect::<Vec<_>>();
+            // the field is allocated up front; archives bound it separately
+            let n = shape.iter().try_fold(1usize, |n, &d| n.checked_mul(d));
+            if n.is_none_or(|n| n > 1 << 20) {
+                return;
+            }
+            let dt = if double {
+                DataType::Float64
+            } else {
+                DataType::Float32
+            };
+            Zfp::new(dt, 1, &shape, 0.0).decode(data, &mut out)
+        }
+        Stage::ByteSwap(n) => ByteSwap(usize::from(n % 9)).decode(data, &mut out),
+    };
+});
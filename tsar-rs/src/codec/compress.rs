@@ -1,14 +1,21 @@
-use std::io::Write;
+use std::io::{Read, Write};
 
 use super::Codec;
-use crate::result::Result;
+use crate::result::{Error, Result};
 
 pub enum Compress {
     Zstd(i32),
 }
 
-impl Codec for Compress {
-    fn encode<'a, I>(&self, data: I, out: &mut super::BufferList) -> Result<()>
+impl Compress {
+    /// Decode like [`Codec::decode`], failing when an output would be longer
+    /// than `limit` bytes.
+    pub fn decode_bounded<'a, I>(
+        &self,
+        data: I,
+        limit: usize,
+        out: &mut super::BufferList,
+    ) -> Result<()>
     where
         I: IntoIterator<Item = &'a [u8]>,
         I::IntoIter: ExactSizeIterator,
@@ -17,17 +24,26 @@ impl Codec for Compress {
         out.reset(data.len());
         for (i, o) in data.zip(out) {
             match self {
-                &Compress::Zstd(level) => {
-                    let mut z = zstd::Encoder::new(o, level)?;
-                    z.write_all(i)?;
-                    z.finish()?;
+                &Compress::Zstd(_) => {
+                    let i = std::io::Cursor::new(i);
+                    let z = zstd::Decoder::new(i)?;
+                    let limit = (limit as u64).saturating_add(1);
+                    if std::io::copy(&mut z.take(limit), o)? == limit {
+                        return Err(Error::Corrupt("zstd data longer than its blob"));
+                    }
                 }
             }
         }
         Ok(())
     }
+}
 
-    fn decode<'a, I>(&self, data: I, out: &mut super::BufferList) -> Result<()>
+impl Codec for Compress {
+    fn lossless(&self) -> bool {
+        true
+    }
+
+    fn encode<'a, I>(&self, data: I, out: &mut super::BufferList) -> Result<()>
     where
         I: IntoIterator<Item = &'a [u8]>,
         I::IntoIter: ExactSizeIterator,
@@ -36,15 +52,23 @@ impl Codec for Compress {
         out.reset(data.len());
         for (i, o) in data.zip(out) {
             match self {
-                &Compress::Zstd(_) => {
-                    let i = std::io::Cursor::new(i);
-                    let mut z = zstd::Decoder::new(i)?;
-                    std::io::copy(&mut z, o)?;
+                &Compress::Zstd(level) => {
+                    let mut z = zstd::Encoder::new(o, level)?;
+                    z.write_all(i)?;
+                    z.finish()?;
                 }
             }
         }
         Ok(())
     }
+
+    fn decode<'a, I>(&self, data: I, out: &mut super::BufferList) -> Result<()>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        self.decode_bounded(data, usize::MAX, out)
+    }
 }
 
 #[cfg(test)]
@@ -63,5 +87,12 @@ mod tests {
             .decode(out.iter_slice(), &mut decomp)
             .unwrap();
         assert_eq!(decomp[0], "hello world".as_bytes());
+
+        let zeros = vec![0; 1 << 20];
+        Compress::Zstd(9).encode([&zeros[..]], &mut out).unwrap();
+        let mut bounded =
+            |limit| Compress::Zstd(9).decode_bounded(out.iter_slice(), limit, &mut decomp);
+        assert!(bounded(1 << 20).is_ok());
+        assert!(bounded((1 << 20) - 1).is_err());
     }
 }
@@ -1,8 +1,10 @@
 use half::prelude::HalfFloatSliceExt;
-use num_traits::AsPrimitive;
 
-use super::Codec;
-use crate::result::Result;
+use super::{simd, Codec};
+use crate::{
+    data_type::float_diff,
+    result::{Error, Result},
+};
 
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum Convert {
@@ -57,11 +59,52 @@ macro_rules! cvt_type_blk {
     };
 }
 
-fn cvt<const N: usize, S: AsPrimitive<D>, D: Copy + 'static>(dst: &mut [D; N], src: &[S; N]) {
-    *dst = src.map(S::as_);
+macro_rules! round_trip_error {
+    ($src:ty, $data:expr, $rt:expr) => {{
+        const N: usize = std::mem::size_of::<$src>();
+        $data.chunks_exact(N).fold(0.0, |err: f64, b| {
+            let s = <$src>::from_le_bytes(b.try_into().unwrap());
+            let t: $src = $rt(s);
+            err.max(float_diff(s.into(), t.into(), t.to_le_bytes() == b))
+        })
+    }};
+}
+
+impl Convert {
+    /// Bytes per converted value.
+    fn encoded_width(&self) -> usize {
+        match self {
+            Self::Float32ToBfloat16
+            | Self::Float32ToFloat16
+            | Self::Float64ToBfloat16
+            | Self::Float64ToFloat16 => 2,
+            Self::Float64ToFloat32 => 4,
+        }
+    }
+
+    /// Largest error of converting `data` and back, by the rules of
+    /// `DataType::max_difference`, computed without buffering the result.
+    pub fn max_error(&self, data: &[u8]) -> f64 {
+        use half::{bf16, f16};
+        match self {
+            Self::Float32ToBfloat16 => {
+                round_trip_error!(f32, data, |s| bf16::from_f32(s).to_f32())
+            }
+            Self::Float32ToFloat16 => round_trip_error!(f32, data, |s| f16::from_f32(s).to_f32()),
+            Self::Float64ToBfloat16 => {
+                round_trip_error!(f64, data, |s| bf16::from_f64(s).to_f64())
+            }
+            Self::Float64ToFloat16 => round_trip_error!(f64, data, |s| f16::from_f64(s).to_f64()),
+            Self::Float64ToFloat32 => round_trip_error!(f64, data, |s| s as f32 as f64),
+        }
+    }
 }
 
 impl Codec for Convert {
+    fn lossless(&self) -> bool {
+        false
+    }
+
     fn encode<'a, I>(&self, data: I, out: &mut super::BufferList) -> Result<()>
     where
         I: IntoIterator<Item = &'a [u8]>,
@@ -71,16 +114,7 @@ impl Codec for Convert {
         out.reset(data.len());
         for (buf, out) in data.zip(out.iter_mut()) {
             match self {
-                Self::Float32ToBfloat16 => {
-                    cvt_type_blk!(
-                        f32,
-                        half::bf16,
-                        <[half::bf16]>::convert_from_f32_slice,
-                        16,
-                        buf,
-                        out
-                    );
-                }
+                Self::Float32ToBfloat16 => simd::f32_to_bf16(buf, out),
                 Self::Float64ToBfloat16 => {
                     cvt_type_blk!(
                         f64,
@@ -111,9 +145,7 @@ impl Codec for Convert {
                         out
                     );
                 }
-                Self::Float64ToFloat32 => {
-                    cvt_type_blk!(f64, f32, cvt::<16, f64, f32>, 16, buf, out);
-                }
+                Self::Float64ToFloat32 => simd::f64_to_f32(buf, out),
             }
         }
         Ok(())
@@ -127,27 +159,12 @@ impl Codec for Convert {
         let data = data.into_iter();
         out.reset(data.len());
         for (buf, out) in data.zip(out.iter_mut()) {
+            if !buf.len().is_multiple_of(self.encoded_width()) {
+                return Err(Error::Corrupt("converted data is not whole words"));
+            }
             match self {
-                Self::Float32ToBfloat16 => {
-                    cvt_type_blk!(
-                        half::bf16,
-                        f32,
-                        |d, s: &[half::bf16]| s.convert_to_f32_slice(d),
-                        16,
-                        buf,
-                        out
-                    );
-                }
-                Self::Float64ToBfloat16 => {
-                    cvt_type_blk!(
-                        half::bf16,
-                        f64,
-                        |d, s: &[half::bf16]| s.convert_to_f64_slice(d),
-                        16,
-                        buf,
-                        out
-                    );
-                }
+                Self::Float32ToBfloat16 => simd::bf16_to_f32(buf, out),
+                Self::Float64ToBfloat16 => simd::bf16_to_f64(buf, out),
                 Self::Float32ToFloat16 => {
                     cvt_type_blk!(
                         half::f16,
@@ -168,9 +185,7 @@ impl Codec for Convert {
                         out
                     );
                 }
-                Self::Float64ToFloat32 => {
-                    cvt_type_blk!(f32, f64, cvt::<16, f32, f64>, 16, buf, out);
-                }
+                Self::Float64ToFloat32 => simd::f32_to_f64(buf, out),
             }
         }
         Ok(())
@@ -182,7 +197,10 @@ mod tests {
     use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
     use super::*;
-    use crate::codec::{test_util, BufferList};
+    use crate::{
+        codec::{test_util, BufferList},
+        DataType,
+    };
 
     #[test]
     fn f32_to_bf16() {
@@ -267,4 +285,38 @@ mod tests {
             assert_eq!(fp64.to_le_bytes(), (f as f32 as f64).to_le_bytes());
         }
     }
+
+    #[test]
+    fn max_error() {
+        let f64s = test_util::F32_DATA
+            .iter()
+            .map(|&f| f as f64 * 1.000_000_1)
+            .chain([f64::NAN, f64::INFINITY, 1e300, -1e-300, 0.0])
+            .collect::<Vec<_>>();
+        let f32s = f64s.iter().map(|&f| f as f32).collect::<Vec<_>>();
+        let f32_bytes = f32s
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect::<Vec<_>>();
+        let f64_bytes = f64s
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect::<Vec<_>>();
+        for (c, dt, data) in [
+            (Convert::Float32ToBfloat16, DataType::Float32, &f32_bytes),
+            (Convert::Float32ToFloat16, DataType::Float32, &f32_bytes),
+            (Convert::Float64ToBfloat16, DataType::Float64, &f64_bytes),
+            (Convert::Float64ToFloat16, DataType::Float64, &f64_bytes),
+            (Convert::Float64ToFloat32, DataType::Float64, &f64_bytes),
+        ] {
+            for data in [&data[..], &data[..data.len() - 5 * dt.byte_len()]] {
+                let mut enc = BufferList::new();
+                let mut dec = BufferList::new();
+                c.encode([data], &mut enc).unwrap();
+                c.decode(enc.iter_slice(), &mut dec).unwrap();
+                let err = dt.max_difference(data, &dec[0]).unwrap();
+                assert_eq!(c.max_error(data).to_bits(), err.to_bits());
+            }
+        }
+    }
 }
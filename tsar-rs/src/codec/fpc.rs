@@ -0,0 +1,282 @@
+use std::cell::RefCell;
+
+use super::Codec;
+use crate::result::{Error, Result};
+
+/// Lossless predictive coder for floats, after FPC (Burtscher & Ratanaworabhan).
+///
+/// Each value is predicted by a finite context model and a differential one,
+/// and XORed with the closer prediction. Every input buffer becomes two
+/// buffers: a header holding the value count followed by one nibble per value
+/// (predictor and count of leading zero bytes), and the remaining low bytes of
+/// the residuals.
+pub enum Fpc {
+    Bfloat16,
+    Float16,
+    Float32,
+    Float64,
+}
+
+const TABLE_BITS: u32 = 16;
+const TABLE_MASK: usize = (1 << TABLE_BITS) - 1;
+
+thread_local! {
+    /// Both predictor tables, kept between streams so each one only clears
+    /// them instead of allocating a megabyte.
+    static TABLES: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+struct Predictor<'t, const W: usize> {
+    fcm: &'t mut [u64],
+    dfcm: &'t mut [u64],
+    fcm_hash: usize,
+    dfcm_hash: usize,
+    last: u64,
+}
+
+impl<'t, const W: usize> Predictor<'t, W> {
+    const BITS: u32 = W as u32 * 8;
+    const MASK: u64 = u64::MAX >> (64 - Self::BITS);
+
+    /// Run `f` with a fresh predictor over this thread's tables.
+    fn with<T>(f: impl FnOnce(&mut Predictor<W>) -> T) -> T {
+        TABLES.with_borrow_mut(|t| {
+            t.clear();
+            t.resize(2 << TABLE_BITS, 0);
+            f(&mut Predictor::new(t))
+        })
+    }
+
+    fn new(tables: &'t mut [u64]) -> Self {
+        let (fcm, dfcm) = tables.split_at_mut(1 << TABLE_BITS);
+        Self {
+            fcm,
+            dfcm,
+            fcm_hash: 0,
+            dfcm_hash: 0,
+            last: 0,
+        }
+    }
+
+    fn predict(&self) -> [u64; 2] {
+        [
+            self.fcm[self.fcm_hash],
+            self.dfcm[self.dfcm_hash].wrapping_add(self.last) & Self::MASK,
+        ]
+    }
+
+    fn update(&mut self, v: u64) {
+        self.fcm[self.fcm_hash] = v;
+        self.fcm_hash =
+            ((self.fcm_hash << 6) ^ (v >> Self::BITS.saturating_sub(16)) as usize) & TABLE_MASK;
+        let delta = v.wrapping_sub(self.last) & Self::MASK;
+        self.dfcm[self.dfcm_hash] = delta;
+        self.dfcm_hash = ((self.dfcm_hash << 2)
+            ^ (delta >> Self::BITS.saturating_sub(24)) as usize)
+            & TABLE_MASK;
+        self.last = v;
+    }
+}
+
+/// Leading zero bytes of `r` as a `W`-byte word, capped to fit three bits.
+fn zero_bytes<const W: usize>(r: u64) -> usize {
+    let lz = (r.leading_zeros() as usize / 8).saturating_sub(8 - W);
+    lz.min(7)
+}
+
+fn encode_words<const W: usize>(data: &[u8], hdr: &mut Vec<u8>, res: &mut Vec<u8>) {
+    assert_eq!(data.len() % W, 0);
+    let n = data.len() / W;
+    hdr.reserve(8 + n.div_ceil(2));
+    hdr.extend_from_slice(&(n as u64).to_le_bytes());
+    res.reserve(data.len());
+
+    Predictor::<W>::with(|p| {
+        let mut pending = None;
+        for c in data.chunks_exact(W) {
+            let mut b = [0; 8];
+            b[..W].copy_from_slice(c);
+            let v = u64::from_le_bytes(b);
+            let [a, d] = p.predict();
+            let (sel, r) = if v ^ a <= v ^ d {
+                (0, v ^ a)
+            } else {
+                (8, v ^ d)
+            };
+            let lz = zero_bytes::<W>(r);
+            res.extend_from_slice(&r.to_le_bytes()[..W - lz]);
+            let nib = sel | lz as u8;
+            match pending.take() {
+                None => pending = Some(nib),
+                Some(lo) => hdr.push(lo | (nib << 4)),
+            }
+            p.update(v);
+        }
+        hdr.extend(pending);
+    })
+}
+
+fn decode_words<const W: usize>(hdr: &[u8], mut res: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    let corrupt = || Error::Corrupt("truncated FPC stream");
+    let (n, nibs) = hdr.split_first_chunk::<8>().ok_or_else(corrupt)?;
+    let n = usize::try_from(u64::from_le_bytes(*n)).map_err(|_| corrupt())?;
+    if nibs.len() != n.div_ceil(2) {
+        return Err(corrupt());
+    }
+    out.reserve(n.saturating_mul(W));
+
+    Predictor::<W>::with(|p| {
+        for i in 0..n {
+            let nib = (nibs[i / 2] >> (i % 2 * 4)) & 0xf;
+            let len = W.checked_sub(usize::from(nib & 7)).ok_or_else(corrupt)?;
+            let (r, rest) = res.split_at_checked(len).ok_or_else(corrupt)?;
+            res = rest;
+            let mut b = [0; 8];
+            b[..len].copy_from_slice(r);
+            let v = u64::from_le_bytes(b) ^ p.predict()[usize::from(nib >> 3)];
+            out.extend_from_slice(&v.to_le_bytes()[..W]);
+            p.update(v);
+        }
+        if !res.is_empty() {
+            return Err(corrupt());
+        }
+        Ok(())
+    })
+}
+
+impl Fpc {
+    fn width(&self) -> usize {
+        match self {
+            Fpc::Bfloat16 | Fpc::Float16 => 2,
+            Fpc::Float32 => 4,
+            Fpc::Float64 => 8,
+        }
+    }
+}
+
+impl Codec for Fpc {
+    fn lossless(&self) -> bool {
+        true
+    }
+
+    fn encode<'a, I>(&self, data: I, out: &mut super::BufferList) -> Result<()>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let data = data.into_iter();
+        out.reset(data.len() * 2);
+        for (i, data) in data.enumerate() {
+            let (hdr, res) = out.split_at_mut(i * 2 + 1);
+            let (hdr, res) = (&mut hdr[i * 2], &mut res[0]);
+            match self.width() {
+                2 => encode_words::<2>(data, hdr, res),
+                4 => encode_words::<4>(data, hdr, res),
+                _ => encode_words::<8>(data, hdr, res),
+            }
+        }
+        Ok(())
+    }
+
+    fn decode<'a, I>(&self, data: I, out: &mut super::BufferList) -> Result<()>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let mut data = data.into_iter();
+        if !data.len().is_multiple_of(2) {
+            return Err(Error::Corrupt("FPC takes pairs of inputs"));
+        }
+        out.reset(data.len() / 2);
+        for out in out.iter_mut() {
+            let (hdr, res) = (data.next().unwrap(), data.next().unwrap());
+            match self.width() {
+                2 => decode_words::<2>(hdr, res, out)?,
+                4 => decode_words::<4>(hdr, res, out)?,
+                _ => decode_words::<8>(hdr, res, out)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{test_util, BufferList};
+
+    fn roundtrip(fpc: Fpc, data: &[u8]) -> usize {
+        let mut out = BufferList::new();
+        let mut back = BufferList::new();
+        fpc.encode([data], &mut out).unwrap();
+        fpc.decode(out.iter_slice(), &mut back).unwrap();
+        assert_eq!(back.len(), 1);
+        assert!(back[0] == data);
+        out.iter().map(Vec::len).sum()
+    }
+
+    #[test]
+    fn fpc() {
+        let f32s = test_util::F32_DATA
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect::<Vec<_>>();
+        roundtrip(Fpc::Float32, &f32s);
+        roundtrip(Fpc::Float32, &f32s[..4 * 7]);
+        roundtrip(Fpc::Float32, &[]);
+        let f64s = test_util::F64_DATA
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect::<Vec<_>>();
+        roundtrip(Fpc::Float64, &f64s);
+        let bf16s = test_util::BF16_DATA
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect::<Vec<_>>();
+        roundtrip(Fpc::Bfloat16, &bf16s);
+        let f16s = test_util::F16_DATA
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect::<Vec<_>>();
+        roundtrip(Fpc::Float16, &f16s);
+        roundtrip(Fpc::Float16, &f16s[..2 * 7]);
+
+        // smooth data is mostly predicted
+        let smooth = (0..65536)
+            .flat_map(|i| (i as f64 * 0.25).to_le_bytes())
+            .collect::<Vec<_>>();
+        assert!(roundtrip(Fpc::Float64, &smooth) < smooth.len() / 4);
+    }
+
+    #[test]
+    fn reused_tables() {
+        // a stream must not see the tables the previous one left behind
+        let data = (0..1000)
+            .flat_map(|i| (i as f32 * 0.5).to_le_bytes())
+            .collect::<Vec<_>>();
+        let mut first = BufferList::new();
+        Fpc::Float32.encode([data.as_slice()], &mut first).unwrap();
+        let mut again = BufferList::new();
+        Fpc::Float32
+            .encode([data.as_slice(), data.as_slice()], &mut again)
+            .unwrap();
+        assert!(again[0] == first[0] && again[2] == first[0]);
+        assert!(again[1] == first[1] && again[3] == first[1]);
+        roundtrip(Fpc::Float32, &data);
+    }
+
+    #[test]
+    fn truncated() {
+        let data = (0..100)
+            .flat_map(|i| (i as f32).sqrt().to_le_bytes())
+            .collect::<Vec<_>>();
+        let mut out = BufferList::new();
+        Fpc::Float32.encode([data.as_slice()], &mut out).unwrap();
+        let mut back = BufferList::new();
+        let (hdr, res) = (&out[0][..], &out[1][..]);
+        assert!(Fpc::Float32.decode([hdr, &res[1..]], &mut back).is_err());
+        assert!(Fpc::Float32
+            .decode([&hdr[..hdr.len() - 1], res], &mut back)
+            .is_err());
+    }
+}
@@ -4,14 +4,19 @@ use smallvec::SmallVec;
 
 mod compress;
 mod convert;
+mod fpc;
+pub mod simd;
 mod split;
+mod swap;
 #[cfg(test)]
 mod test_util;
 mod zfp;
 
 pub use compress::Compress;
 pub use convert::Convert;
+pub use fpc::Fpc;
 pub use split::Split;
+pub use swap::ByteSwap;
 pub use zfp::Zfp;
 
 use crate::result::Result;
@@ -54,6 +59,11 @@ impl BufferList {
         }
     }
 
+    /// Bytes allocated by all buffers, including ones past the current length.
+    pub fn capacity(&self) -> usize {
+        self.inner.iter().map(Vec::capacity).sum()
+    }
+
     pub fn iter_slice(&self) -> impl ExactSizeIterator<Item = &[u8]> {
         self.iter().map(|f| f.as_slice())
     }
@@ -148,6 +158,9 @@ impl DerefMut for BufferList {
 }
 
 pub trait Codec {
+    /// Whether `decode` reproduces the input of `encode` bit for bit.
+    fn lossless(&self) -> bool;
+
     fn encode<'a, I>(&self, data: I, out: &mut BufferList) -> Result<()>
     where
         I: IntoIterator<Item = &'a [u8]>,
@@ -0,0 +1,1152 @@
+//! Vectorized kernels for the per-element loops of the split, convert and
+//! diff stages.
+//!
+//! Converting to and from float16 is left to the `half` crate, which already
+//! uses F16C when the CPU has it. Rounding float64 to bfloat16 and comparing
+//! 64-bit integers, complex numbers and packed or 8-bit float types still run
+//! the scalar loops of their stages.
+//!
+//! Each kernel picks an AVX2 implementation at runtime when the CPU has it
+//! and otherwise runs its scalar twin, which is also the reference the
+//! vector paths are tested against bit for bit.
+
+use crate::data_type::float_diff;
+
+#[cfg(target_arch = "x86_64")]
+fn has_avx2() -> bool {
+    std::arch::is_x86_feature_detected!("avx2")
+}
+
+#[cfg(target_arch = "x86_64")]
+fn has_avx2_f16c() -> bool {
+    has_avx2() && std::arch::is_x86_feature_detected!("f16c")
+}
+
+/// Return the AVX2 kernel `$name` when `$detect` finds the CPU features it
+/// enables, or else its scalar twin.
+macro_rules! dispatch {
+    ($detect:ident, $name:ident($($arg:expr),*)) => {{
+        #[cfg(target_arch = "x86_64")]
+        if $detect() {
+            // SAFETY: the CPU supports the features the kernel enables
+            return unsafe { avx2::$name($($arg),*) };
+        }
+        scalar::$name($($arg),*)
+    }};
+}
+
+/// Append the exponent byte and the 3 sign/mantissa bytes of every float.
+pub fn split_f32(src: &[u8], exp: &mut Vec<u8>, man: &mut Vec<u8>) {
+    assert_eq!(src.len() % 4, 0);
+    dispatch!(has_avx2, split_f32(src, exp, man))
+}
+
+/// Inverse of [`split_f32`].
+pub fn merge_f32(exp: &[u8], man: &[u8], out: &mut Vec<u8>) {
+    dispatch!(has_avx2, merge_f32(exp, man, out))
+}
+
+/// Append the 2 exponent bytes and the 7 sign/mantissa bytes of every double.
+pub fn split_f64(src: &[u8], exp: &mut Vec<u8>, man: &mut Vec<u8>) {
+    assert_eq!(src.len() % 8, 0);
+    dispatch!(has_avx2, split_f64(src, exp, man))
+}
+
+/// Inverse of [`split_f64`].
+pub fn merge_f64(exp: &[u8], man: &[u8], out: &mut Vec<u8>) {
+    dispatch!(has_avx2, merge_f64(exp, man, out))
+}
+
+/// Append the exponent byte and the 2 sign/mantissa bytes of every float16.
+pub fn split_f16(src: &[u8], exp: &mut Vec<u8>, man: &mut Vec<u8>) {
+    assert_eq!(src.len() % 2, 0);
+    dispatch!(has_avx2, split_f16(src, exp, man))
+}
+
+/// Inverse of [`split_f16`].
+pub fn merge_f16(exp: &[u8], man: &[u8], out: &mut Vec<u8>) {
+    dispatch!(has_avx2, merge_f16(exp, man, out))
+}
+
+/// Append the exponent byte and the sign/mantissa byte of every bfloat16.
+pub fn split_bf16(src: &[u8], exp: &mut Vec<u8>, man: &mut Vec<u8>) {
+    assert_eq!(src.len() % 2, 0);
+    dispatch!(has_avx2, split_bf16(src, exp, man))
+}
+
+/// Inverse of [`split_bf16`].
+pub fn merge_bf16(exp: &[u8], man: &[u8], out: &mut Vec<u8>) {
+    dispatch!(has_avx2, merge_bf16(exp, man, out))
+}
+
+/// Append every float rounded to bfloat16, as `half::bf16::from_f32` does.
+pub fn f32_to_bf16(src: &[u8], out: &mut Vec<u8>) {
+    assert_eq!(src.len() % 4, 0);
+    dispatch!(has_avx2, f32_to_bf16(src, out))
+}
+
+/// Append every bfloat16 widened to a float, as `half::bf16::to_f32` does.
+pub fn bf16_to_f32(src: &[u8], out: &mut Vec<u8>) {
+    assert_eq!(src.len() % 2, 0);
+    dispatch!(has_avx2, bf16_to_f32(src, out))
+}
+
+/// Append every bfloat16 widened to a double, as `half::bf16::to_f64` does.
+pub fn bf16_to_f64(src: &[u8], out: &mut Vec<u8>) {
+    assert_eq!(src.len() % 2, 0);
+    dispatch!(has_avx2, bf16_to_f64(src, out))
+}
+
+/// Append every double rounded to a float, as `as f32` does.
+pub fn f64_to_f32(src: &[u8], out: &mut Vec<u8>) {
+    assert_eq!(src.len() % 8, 0);
+    dispatch!(has_avx2, f64_to_f32(src, out))
+}
+
+/// Append every float widened to a double.
+pub fn f32_to_f64(src: &[u8], out: &mut Vec<u8>) {
+    assert_eq!(src.len() % 4, 0);
+    dispatch!(has_avx2, f32_to_f64(src, out))
+}
+
+/// Define the diffs of a float type, with the special value rules of
+/// `DataType::max_difference`.
+macro_rules! float_diffs {
+    ($($name:ident: $ty:ty, $detect:ident;)*) => {$(
+        #[doc = concat!(
+            "Largest difference between two `", stringify!($ty),
+            "` arrays of equal length, with the special value rules of ",
+            "`DataType::max_difference`."
+        )]
+        pub fn $name(src: &[u8], targ: &[u8]) -> f64 {
+            assert_eq!(src.len(), targ.len());
+            assert_eq!(src.len() % size_of::<$ty>(), 0);
+            dispatch!($detect, $name(src, targ))
+        }
+    )*};
+}
+
+float_diffs! {
+    max_diff_f32: f32, has_avx2;
+    max_diff_f64: f64, has_avx2;
+    max_diff_f16: half::f16, has_avx2_f16c;
+    max_diff_bf16: half::bf16, has_avx2;
+}
+
+/// Define the diffs of integer types no wider than 32 bits.
+macro_rules! int_diffs {
+    ($($name:ident: $ty:ty;)*) => {$(
+        #[doc = concat!(
+            "Largest absolute difference between two `", stringify!($ty),
+            "` arrays of equal length."
+        )]
+        pub fn $name(src: &[u8], targ: &[u8]) -> f64 {
+            assert_eq!(src.len(), targ.len());
+            assert_eq!(src.len() % size_of::<$ty>(), 0);
+            dispatch!(has_avx2, $name(src, targ))
+        }
+    )*};
+}
+
+int_diffs! {
+    max_diff_i8: i8;
+    max_diff_u8: u8;
+    max_diff_i16: i16;
+    max_diff_u16: u16;
+    max_diff_i32: i32;
+    max_diff_u32: u32;
+}
+
+pub mod scalar {
+    use super::float_diff;
+
+    fn f32_at(b: &[u8]) -> f32 {
+        f32::from_le_bytes(b.try_into().unwrap())
+    }
+
+    fn u16_at(b: &[u8]) -> u16 {
+        u16::from_le_bytes(b.try_into().unwrap())
+    }
+
+    pub fn split_f32(src: &[u8], exp: &mut Vec<u8>, man: &mut Vec<u8>) {
+        exp.reserve(src.len() / 4);
+        man.reserve(src.len() / 4 * 3);
+        for v in src.chunks_exact(4) {
+            let b = f32_at(v).to_bits();
+            let tmp = (((b & 0x8000_0000) >> 8) | (b & 0x007f_ffff)).to_le_bytes();
+            exp.push((b >> 23) as u8);
+            man.extend_from_slice(&tmp[..3]);
+        }
+    }
+
+    pub fn merge_f32(exp: &[u8], man: &[u8], out: &mut Vec<u8>) {
+        out.reserve(exp.len() * 4);
+        for (&e, m) in exp.iter().zip(man.chunks_exact(3)) {
+            let e = u32::from(e);
+            let m = u32::from_le_bytes([m[0], m[1], m[2], 0]);
+            let b = (e << 23) | (m & 0x007f_ffff) | ((m & 0x0080_0000) << 8);
+            out.extend_from_slice(&b.to_le_bytes());
+        }
+    }
+
+    pub fn split_f64(src: &[u8], exp: &mut Vec<u8>, man: &mut Vec<u8>) {
+        exp.reserve(src.len() / 4);
+        man.reserve(src.len() / 8 * 7);
+        for v in src.chunks_exact(8) {
+            let b = u64::from_le_bytes(v.try_into().unwrap());
+            let tmp =
+                (((b & 0x8000_0000_0000_0000) >> 11) | (b & 0x000f_ffff_ffff_ffff)).to_le_bytes();
+            exp.extend_from_slice(&(((b >> 52) & 0x7ff) as u16).to_le_bytes());
+            man.extend_from_slice(&tmp[..7]);
+        }
+    }
+
+    pub fn merge_f64(exp: &[u8], man: &[u8], out: &mut Vec<u8>) {
+        out.reserve(exp.len() * 4);
+        for (e, m) in exp.chunks_exact(2).zip(man.chunks_exact(7)) {
+            let e = u64::from(u16_at(e));
+            let m = u64::from_le_bytes([m[0], m[1], m[2], m[3], m[4], m[5], m[6], 0]);
+            let b = (e << 52) | (m & 0x000f_ffff_ffff_ffff) | ((m & 0x0010_0000_0000_0000) << 11);
+            out.extend_from_slice(&b.to_le_bytes());
+        }
+    }
+
+    pub fn split_f16(src: &[u8], exp: &mut Vec<u8>, man: &mut Vec<u8>) {
+        exp.reserve(src.len() / 2);
+        man.reserve(src.len());
+        for v in src.chunks_exact(2) {
+            let b = u16_at(v);
+            exp.push((b >> 10) as u8 & 0x1f);
+            man.extend_from_slice(&((b & 0x3ff) | ((b >> 5) & 0x400)).to_le_bytes());
+        }
+    }
+
+    pub fn merge_f16(exp: &[u8], man: &[u8], out: &mut Vec<u8>) {
+        out.reserve(exp.len() * 2);
+        for (&e, m) in exp.iter().zip(man.chunks_exact(2)) {
+            let (e, m) = (u16::from(e), u16_at(m));
+            let b = (e << 10) | (m & 0x3ff) | ((m & 0x400) << 5);
+            out.extend_from_slice(&b.to_le_bytes());
+        }
+    }
+
+    pub fn split_bf16(src: &[u8], exp: &mut Vec<u8>, man: &mut Vec<u8>) {
+        exp.reserve(src.len() / 2);
+        man.reserve(src.len() / 2);
+        for v in src.chunks_exact(2) {
+            let b = u16_at(v);
+            exp.push((b >> 7) as u8);
+            man.push(((b >> 8) as u8 & 0x80) | (b as u8 & 0x7f));
+        }
+    }
+
+    pub fn merge_bf16(exp: &[u8], man: &[u8], out: &mut Vec<u8>) {
+        out.reserve(exp.len() * 2);
+        for (&e, &m) in exp.iter().zip(man) {
+            let (e, m) = (u16::from(e), u16::from(m));
+            let b = (e << 7) | (m & 0x7f) | ((m & 0x80) << 8);
+            out.extend_from_slice(&b.to_le_bytes());
+        }
+    }
+
+    pub fn f32_to_bf16(src: &[u8], out: &mut Vec<u8>) {
+        out.reserve(src.len() / 2);
+        for v in src.chunks_exact(4) {
+            out.extend_from_slice(&half::bf16::from_f32(f32_at(v)).to_le_bytes());
+        }
+    }
+
+    pub fn bf16_to_f32(src: &[u8], out: &mut Vec<u8>) {
+        out.reserve(src.len() * 2);
+        for v in src.chunks_exact(2) {
+            let f = half::bf16::from_le_bytes([v[0], v[1]]).to_f32();
+            out.extend_from_slice(&f.to_le_bytes());
+        }
+    }
+
+    pub fn bf16_to_f64(src: &[u8], out: &mut Vec<u8>) {
+        out.reserve(src.len() * 4);
+        for v in src.chunks_exact(2) {
+            let f = half::bf16::from_le_bytes([v[0], v[1]]).to_f64();
+            out.extend_from_slice(&f.to_le_bytes());
+        }
+    }
+
+    pub fn f64_to_f32(src: &[u8], out: &mut Vec<u8>) {
+        out.reserve(src.len() / 2);
+        for v in src.chunks_exact(8) {
+            let f = f64::from_le_bytes(v.try_into().unwrap()) as f32;
+            out.extend_from_slice(&f.to_le_bytes());
+        }
+    }
+
+    pub fn f32_to_f64(src: &[u8], out: &mut Vec<u8>) {
+        out.reserve(src.len() * 2);
+        for v in src.chunks_exact(4) {
+            out.extend_from_slice(&f64::from(f32_at(v)).to_le_bytes());
+        }
+    }
+
+    fn max_diff_float<const N: usize>(src: &[u8], targ: &[u8], v: fn([u8; N]) -> f64) -> f64 {
+        src.chunks_exact(N)
+            .zip(targ.chunks_exact(N))
+            .fold(0.0, |err: f64, (s, t)| {
+                let (vs, vt) = (v(s.try_into().unwrap()), v(t.try_into().unwrap()));
+                err.max(float_diff(vs, vt, s == t))
+            })
+    }
+
+    pub fn max_diff_f32(src: &[u8], targ: &[u8]) -> f64 {
+        max_diff_float(src, targ, |b| f32::from_le_bytes(b).into())
+    }
+
+    pub fn max_diff_f64(src: &[u8], targ: &[u8]) -> f64 {
+        max_diff_float(src, targ, f64::from_le_bytes)
+    }
+
+    pub fn max_diff_f16(src: &[u8], targ: &[u8]) -> f64 {
+        max_diff_float(src, targ, |b| half::f16::from_le_bytes(b).into())
+    }
+
+    pub fn max_diff_bf16(src: &[u8], targ: &[u8]) -> f64 {
+        max_diff_float(src, targ, |b| half::bf16::from_le_bytes(b).into())
+    }
+
+    fn max_diff_int<const N: usize, T: Into<i64>>(
+        src: &[u8],
+        targ: &[u8],
+        v: fn([u8; N]) -> T,
+    ) -> f64 {
+        let max = src
+            .chunks_exact(N)
+            .zip(targ.chunks_exact(N))
+            .fold(0, |max: u64, (s, t)| {
+                let (vs, vt) = (v(s.try_into().unwrap()), v(t.try_into().unwrap()));
+                max.max(vs.into().abs_diff(vt.into()))
+            });
+        // at most 32 bits, so exact
+        max as f64
+    }
+
+    pub fn max_diff_i8(src: &[u8], targ: &[u8]) -> f64 {
+        max_diff_int(src, targ, i8::from_le_bytes)
+    }
+
+    pub fn max_diff_u8(src: &[u8], targ: &[u8]) -> f64 {
+        max_diff_int(src, targ, u8::from_le_bytes)
+    }
+
+    pub fn max_diff_i16(src: &[u8], targ: &[u8]) -> f64 {
+        max_diff_int(src, targ, i16::from_le_bytes)
+    }
+
+    pub fn max_diff_u16(src: &[u8], targ: &[u8]) -> f64 {
+        max_diff_int(src, targ, u16::from_le_bytes)
+    }
+
+    pub fn max_diff_i32(src: &[u8], targ: &[u8]) -> f64 {
+        max_diff_int(src, targ, i32::from_le_bytes)
+    }
+
+    pub fn max_diff_u32(src: &[u8], targ: &[u8]) -> f64 {
+        max_diff_int(src, targ, u32::from_le_bytes)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    use super::scalar;
+
+    /// Pointer to at least `n` bytes of spare capacity at the end of `out`.
+    unsafe fn spare(out: &mut Vec<u8>, n: usize) -> *mut u8 {
+        out.reserve(n);
+        out.as_mut_ptr().add(out.len())
+    }
+
+    /// Picks the low byte of every 16-bit lane into the low 8 bytes of each
+    /// 128-bit half.
+    #[target_feature(enable = "avx2")]
+    unsafe fn pick_low_bytes() -> __m256i {
+        #[rustfmt::skip]
+        let pick = _mm256_setr_epi8(
+            0, 2, 4, 6, 8, 10, 12, 14, -1, -1, -1, -1, -1, -1, -1, -1,
+            0, 2, 4, 6, 8, 10, 12, 14, -1, -1, -1, -1, -1, -1, -1, -1,
+        );
+        pick
+    }
+
+    /// Store the low 8 bytes of both 128-bit halves of `v` to `p`.
+    #[target_feature(enable = "avx2")]
+    unsafe fn store_low_halves(p: *mut u8, v: __m256i) {
+        _mm_storel_epi64(p.cast(), _mm256_castsi256_si128(v));
+        _mm_storel_epi64(p.add(8).cast(), _mm256_extracti128_si256(v, 1));
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn split_f32(src: &[u8], exp: &mut Vec<u8>, man: &mut Vec<u8>) {
+        let blocks = src.len() / 32;
+        let mant = _mm256_set1_epi32(0x007f_ffff);
+        let sign = _mm256_set1_epi32(i32::MIN);
+        #[rustfmt::skip]
+        let pick_exp = _mm256_setr_epi8(
+            0, 4, 8, 12, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+            0, 4, 8, 12, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+        );
+        #[rustfmt::skip]
+        let pick_man = _mm256_setr_epi8(
+            0, 1, 2, 4, 5, 6, 8, 9, 10, 12, 13, 14, -1, -1, -1, -1,
+            0, 1, 2, 4, 5, 6, 8, 9, 10, 12, 13, 14, -1, -1, -1, -1,
+        );
+
+        let ep = spare(exp, blocks * 8);
+        // 16-byte stores of 12 useful bytes need 4 bytes of slack
+        let mp = spare(man, blocks * 24 + 4);
+        for i in 0..blocks {
+            let v = _mm256_loadu_si256(src.as_ptr().add(i * 32).cast());
+            let e = _mm256_shuffle_epi8(_mm256_srli_epi32(v, 23), pick_exp);
+            let m = _mm256_or_si256(
+                _mm256_and_si256(v, mant),
+                _mm256_srli_epi32(_mm256_and_si256(v, sign), 8),
+            );
+            let m = _mm256_shuffle_epi8(m, pick_man);
+
+            let ep = ep.add(i * 8).cast::<i32>();
+            ep.write_unaligned(_mm_cvtsi128_si32(_mm256_castsi256_si128(e)));
+            ep.add(1)
+                .write_unaligned(_mm_cvtsi128_si32(_mm256_extracti128_si256(e, 1)));
+            let mp = mp.add(i * 24);
+            _mm_storeu_si128(mp.cast(), _mm256_castsi256_si128(m));
+            _mm_storeu_si128(mp.add(12).cast(), _mm256_extracti128_si256(m, 1));
+        }
+        exp.set_len(exp.len() + blocks * 8);
+        man.set_len(man.len() + blocks * 24);
+        scalar::split_f32(&src[blocks * 32..], exp, man);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn merge_f32(exp: &[u8], man: &[u8], out: &mut Vec<u8>) {
+        let n = exp.len().min(man.len() / 3);
+        // the 16-byte load of the upper 4 mantissas reads 4 bytes past them
+        let blocks = if n * 3 >= 4 { (n * 3 - 4) / 24 } else { 0 };
+        let mant = _mm256_set1_epi32(0x007f_ffff);
+        let sign = _mm256_set1_epi32(0x0080_0000);
+        #[rustfmt::skip]
+        let spread = _mm256_setr_epi8(
+            0, 1, 2, -1, 3, 4, 5, -1, 6, 7, 8, -1, 9, 10, 11, -1,
+            0, 1, 2, -1, 3, 4, 5, -1, 6, 7, 8, -1, 9, 10, 11, -1,
+        );
+
+        let op = spare(out, blocks * 32);
+        for i in 0..blocks {
+            let e = _mm256_cvtepu8_epi32(_mm_loadl_epi64(exp.as_ptr().add(i * 8).cast()));
+            let mp = man.as_ptr().add(i * 24);
+            let m = _mm256_set_m128i(
+                _mm_loadu_si128(mp.add(12).cast()),
+                _mm_loadu_si128(mp.cast()),
+            );
+            let m = _mm256_shuffle_epi8(m, spread);
+            let v = _mm256_or_si256(
+                _mm256_or_si256(_mm256_slli_epi32(e, 23), _mm256_and_si256(m, mant)),
+                _mm256_slli_epi32(_mm256_and_si256(m, sign), 8),
+            );
+            _mm256_storeu_si256(op.add(i * 32).cast(), v);
+        }
+        out.set_len(out.len() + blocks * 32);
+        scalar::merge_f32(&exp[blocks * 8..n], &man[blocks * 24..n * 3], out);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn split_f64(src: &[u8], exp: &mut Vec<u8>, man: &mut Vec<u8>) {
+        let blocks = src.len() / 32;
+        let mant = _mm256_set1_epi64x(0x000f_ffff_ffff_ffff);
+        let sign = _mm256_set1_epi64x(i64::MIN);
+        let exp_mask = _mm256_set1_epi64x(0x7ff);
+        #[rustfmt::skip]
+        let pick_exp = _mm256_setr_epi8(
+            0, 1, 8, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+            0, 1, 8, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+        );
+        #[rustfmt::skip]
+        let pick_man = _mm256_setr_epi8(
+            0, 1, 2, 3, 4, 5, 6, 8, 9, 10, 11, 12, 13, 14, -1, -1,
+            0, 1, 2, 3, 4, 5, 6, 8, 9, 10, 11, 12, 13, 14, -1, -1,
+        );
+
+        let ep = spare(exp, blocks * 8);
+        // 16-byte stores of 14 useful bytes need 2 bytes of slack
+        let mp = spare(man, blocks * 28 + 2);
+        for i in 0..blocks {
+            let v = _mm256_loadu_si256(src.as_ptr().add(i * 32).cast());
+            let e = _mm256_and_si256(_mm256_srli_epi64(v, 52), exp_mask);
+            let e = _mm256_shuffle_epi8(e, pick_exp);
+            let m = _mm256_or_si256(
+                _mm256_and_si256(v, mant),
+                _mm256_srli_epi64(_mm256_and_si256(v, sign), 11),
+            );
+            let m = _mm256_shuffle_epi8(m, pick_man);
+
+            let ep = ep.add(i * 8).cast::<i32>();
+            ep.write_unaligned(_mm_cvtsi128_si32(_mm256_castsi256_si128(e)));
+            ep.add(1)
+                .write_unaligned(_mm_cvtsi128_si32(_mm256_extracti128_si256(e, 1)));
+            let mp = mp.add(i * 28);
+            _mm_storeu_si128(mp.cast(), _mm256_castsi256_si128(m));
+            _mm_storeu_si128(mp.add(14).cast(), _mm256_extracti128_si256(m, 1));
+        }
+        exp.set_len(exp.len() + blocks * 8);
+        man.set_len(man.len() + blocks * 28);
+        scalar::split_f64(&src[blocks * 32..], exp, man);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn merge_f64(exp: &[u8], man: &[u8], out: &mut Vec<u8>) {
+        let n = (exp.len() / 2).min(man.len() / 7);
+        // the 16-byte load of the upper 2 mantissas reads 2 bytes past them
+        let blocks = if n * 7 >= 2 { (n * 7 - 2) / 28 } else { 0 };
+        let mant = _mm256_set1_epi64x(0x000f_ffff_ffff_ffff);
+        let sign = _mm256_set1_epi64x(0x0010_0000_0000_0000);
+        #[rustfmt::skip]
+        let spread = _mm256_setr_epi8(
+            0, 1, 2, 3, 4, 5, 6, -1, 7, 8, 9, 10, 11, 12, 13, -1,
+            0, 1, 2, 3, 4, 5, 6, -1, 7, 8, 9, 10, 11, 12, 13, -1,
+        );
+
+        let op = spare(out, blocks * 32);
+        for i in 0..blocks {
+            let e = _mm256_cvtepu16_epi64(_mm_loadl_epi64(exp.as_ptr().add(i * 8).cast()));
+            let mp = man.as_ptr().add(i * 28);
+            let m = _mm256_set_m128i(
+                _mm_loadu_si128(mp.add(14).cast()),
+                _mm_loadu_si128(mp.cast()),
+            );
+            let m = _mm256_shuffle_epi8(m, spread);
+            let v = _mm256_or_si256(
+                _mm256_or_si256(_mm256_slli_epi64(e, 52), _mm256_and_si256(m, mant)),
+                _mm256_slli_epi64(_mm256_and_si256(m, sign), 11),
+            );
+            _mm256_storeu_si256(op.add(i * 32).cast(), v);
+        }
+        out.set_len(out.len() + blocks * 32);
+        scalar::merge_f64(&exp[blocks * 8..n * 2], &man[blocks * 28..n * 7], out);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn split_f16(src: &[u8], exp: &mut Vec<u8>, man: &mut Vec<u8>) {
+        let blocks = src.len() / 32;
+        let exp_mask = _mm256_set1_epi16(0x1f);
+        let mant = _mm256_set1_epi16(0x3ff);
+        let sign = _mm256_set1_epi16(0x400);
+        let pick = pick_low_bytes();
+
+        let ep = spare(exp, blocks * 16);
+        let mp = spare(man, blocks * 32);
+        for i in 0..blocks {
+            let v = _mm256_loadu_si256(src.as_ptr().add(i * 32).cast());
+            let e = _mm256_and_si256(_mm256_srli_epi16(v, 10), exp_mask);
+            store_low_halves(ep.add(i * 16), _mm256_shuffle_epi8(e, pick));
+            let m = _mm256_or_si256(
+                _mm256_and_si256(v, mant),
+                _mm256_and_si256(_mm256_srli_epi16(v, 5), sign),
+            );
+            _mm256_storeu_si256(mp.add(i * 32).cast(), m);
+        }
+        exp.set_len(exp.len() + blocks * 16);
+        man.set_len(man.len() + blocks * 32);
+        scalar::split_f16(&src[blocks * 32..], exp, man);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn merge_f16(exp: &[u8], man: &[u8], out: &mut Vec<u8>) {
+        let n = exp.len().min(man.len() / 2);
+        let blocks = n / 16;
+        let mant = _mm256_set1_epi16(0x3ff);
+        let sign = _mm256_set1_epi16(0x400);
+
+        let op = spare(out, blocks * 32);
+        for i in 0..blocks {
+            let e = _mm256_cvtepu8_epi16(_mm_loadu_si128(exp.as_ptr().add(i * 16).cast()));
+            let m = _mm256_loadu_si256(man.as_ptr().add(i * 32).cast());
+            let v = _mm256_or_si256(
+                _mm256_or_si256(_mm256_slli_epi16(e, 10), _mm256_and_si256(m, mant)),
+                _mm256_slli_epi16(_mm256_and_si256(m, sign), 5),
+            );
+            _mm256_storeu_si256(op.add(i * 32).cast(), v);
+        }
+        out.set_len(out.len() + blocks * 32);
+        scalar::merge_f16(&exp[blocks * 16..n], &man[blocks * 32..n * 2], out);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn split_bf16(src: &[u8], exp: &mut Vec<u8>, man: &mut Vec<u8>) {
+        let blocks = src.len() / 32;
+        let mant = _mm256_set1_epi16(0x7f);
+        let sign = _mm256_set1_epi16(0x80);
+        let pick = pick_low_bytes();
+
+        let ep = spare(exp, blocks * 16);
+        let mp = spare(man, blocks * 16);
+        for i in 0..blocks {
+            let v = _mm256_loadu_si256(src.as_ptr().add(i * 32).cast());
+            let e = _mm256_srli_epi16(v, 7);
+            store_low_halves(ep.add(i * 16), _mm256_shuffle_epi8(e, pick));
+            let m = _mm256_or_si256(
+                _mm256_and_si256(v, mant),
+                _mm256_and_si256(_mm256_srli_epi16(v, 8), sign),
+            );
+            store_low_halves(mp.add(i * 16), _mm256_shuffle_epi8(m, pick));
+        }
+        exp.set_len(exp.len() + blocks * 16);
+        man.set_len(man.len() + blocks * 16);
+        scalar::split_bf16(&src[blocks * 32..], exp, man);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn merge_bf16(exp: &[u8], man: &[u8], out: &mut Vec<u8>) {
+        let n = exp.len().min(man.len());
+        let blocks = n / 16;
+        let mant = _mm256_set1_epi16(0x7f);
+        let sign = _mm256_set1_epi16(0x80);
+
+        let op = spare(out, blocks * 32);
+        for i in 0..blocks {
+            let e = _mm256_cvtepu8_epi16(_mm_loadu_si128(exp.as_ptr().add(i * 16).cast()));
+            let m = _mm256_cvtepu8_epi16(_mm_loadu_si128(man.as_ptr().add(i * 16).cast()));
+            let v = _mm256_or_si256(
+                _mm256_or_si256(_mm256_slli_epi16(e, 7), _mm256_and_si256(m, mant)),
+                _mm256_slli_epi16(_mm256_and_si256(m, sign), 8),
+            );
+            _mm256_storeu_si256(op.add(i * 32).cast(), v);
+        }
+        out.set_len(out.len() + blocks * 32);
+        scalar::merge_bf16(&exp[blocks * 16..n], &man[blocks * 16..n], out);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn f32_to_bf16(src: &[u8], out: &mut Vec<u8>) {
+        let blocks = src.len() / 32;
+        let abs = _mm256_set1_epi32(0x7fff_ffff);
+        let inf = _mm256_set1_epi32(0x7f80_0000);
+        let quiet = _mm256_set1_epi32(0x0040);
+        let round = _mm256_set1_epi32(0x8000);
+        let sticky = _mm256_set1_epi32(3 * 0x8000 - 1);
+        let zero = _mm256_setzero_si256();
+        #[rustfmt::skip]
+        let pick = _mm256_setr_epi8(
+            0, 1, 4, 5, 8, 9, 12, 13, -1, -1, -1, -1, -1, -1, -1, -1,
+            0, 1, 4, 5, 8, 9, 12, 13, -1, -1, -1, -1, -1, -1, -1, -1,
+        );
+
+        let op = spare(out, blocks * 16);
+        for i in 0..blocks {
+            let x = _mm256_loadu_si256(src.as_ptr().add(i * 32).cast());
+            let hi = _mm256_srli_epi32(x, 16);
+            let nan = _mm256_cmpgt_epi32(_mm256_and_si256(x, abs), inf);
+            // round half to even: up when the round bit is set along
+            // with any bit below it or the lowest kept bit
+            let no_round = _mm256_or_si256(
+                _mm256_cmpeq_epi32(_mm256_and_si256(x, round), zero),
+                _mm256_cmpeq_epi32(_mm256_and_si256(x, sticky), zero),
+            );
+            let rounded =
+                _mm256_sub_epi32(hi, _mm256_andnot_si256(no_round, _mm256_set1_epi32(-1)));
+            let v = _mm256_blendv_epi8(rounded, _mm256_or_si256(hi, quiet), nan);
+            store_low_halves(op.add(i * 16), _mm256_shuffle_epi8(v, pick));
+        }
+        out.set_len(out.len() + blocks * 16);
+        scalar::f32_to_bf16(&src[blocks * 32..], out);
+    }
+
+    /// Widen 8 bfloat16 to floats, quieting NaNs as `half::bf16::to_f32` does.
+    #[target_feature(enable = "avx2")]
+    unsafe fn widen_bf16(src: *const u8) -> __m256i {
+        let abs = _mm256_set1_epi32(0x7fff);
+        let inf = _mm256_set1_epi32(0x7f80);
+        let quiet = _mm256_set1_epi32(0x0040);
+        let w = _mm256_cvtepu16_epi32(_mm_loadu_si128(src.cast()));
+        let nan = _mm256_cmpgt_epi32(_mm256_and_si256(w, abs), inf);
+        let w = _mm256_or_si256(w, _mm256_and_si256(nan, quiet));
+        _mm256_slli_epi32(w, 16)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn bf16_to_f32(src: &[u8], out: &mut Vec<u8>) {
+        let blocks = src.len() / 16;
+        let op = spare(out, blocks * 32);
+        for i in 0..blocks {
+            let w = widen_bf16(src.as_ptr().add(i * 16));
+            _mm256_storeu_si256(op.add(i * 32).cast(), w);
+        }
+        out.set_len(out.len() + blocks * 32);
+        scalar::bf16_to_f32(&src[blocks * 16..], out);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn bf16_to_f64(src: &[u8], out: &mut Vec<u8>) {
+        let blocks = src.len() / 16;
+        let op = spare(out, blocks * 64);
+        for i in 0..blocks {
+            // NaNs are already quiet, so widening keeps their bits
+            let f = _mm256_castsi256_ps(widen_bf16(src.as_ptr().add(i * 16)));
+            let op = op.add(i * 64).cast::<f64>();
+            _mm256_storeu_pd(op, _mm256_cvtps_pd(_mm256_castps256_ps128(f)));
+            _mm256_storeu_pd(op.add(4), _mm256_cvtps_pd(_mm256_extractf128_ps(f, 1)));
+        }
+        out.set_len(out.len() + blocks * 64);
+        scalar::bf16_to_f64(&src[blocks * 16..], out);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn f64_to_f32(src: &[u8], out: &mut Vec<u8>) {
+        let blocks = src.len() / 32;
+        let op = spare(out, blocks * 16);
+        for i in 0..blocks {
+            let v = _mm256_loadu_pd(src.as_ptr().add(i * 32).cast());
+            _mm_storeu_ps(op.add(i * 16).cast(), _mm256_cvtpd_ps(v));
+        }
+        out.set_len(out.len() + blocks * 16);
+        scalar::f64_to_f32(&src[blocks * 32..], out);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn f32_to_f64(src: &[u8], out: &mut Vec<u8>) {
+        let blocks = src.len() / 16;
+        let op = spare(out, blocks * 32);
+        for i in 0..blocks {
+            let v = _mm_loadu_ps(src.as_ptr().add(i * 16).cast());
+            _mm256_storeu_pd(op.add(i * 32).cast(), _mm256_cvtps_pd(v));
+        }
+        out.set_len(out.len() + blocks * 32);
+        scalar::f32_to_f64(&src[blocks * 16..], out);
+    }
+
+    /// Fold the absolute differences of 8 pairs of finite floats into `err`.
+    #[target_feature(enable = "avx2")]
+    unsafe fn diff_ps(mut err: __m256d, s: __m256, t: __m256) -> __m256d {
+        let abs = _mm256_castsi256_pd(_mm256_set1_epi64x(i64::MAX));
+        for (s, t) in [
+            (_mm256_castps256_ps128(s), _mm256_castps256_ps128(t)),
+            (_mm256_extractf128_ps(s, 1), _mm256_extractf128_ps(t, 1)),
+        ] {
+            let d = _mm256_sub_pd(_mm256_cvtps_pd(s), _mm256_cvtps_pd(t));
+            err = _mm256_max_pd(err, _mm256_and_pd(d, abs));
+        }
+        err
+    }
+
+    /// Largest of the lanes of `err` and `rest`.
+    #[target_feature(enable = "avx2")]
+    unsafe fn max_lane(err: __m256d, rest: f64) -> f64 {
+        let mut lanes = [0.0; 4];
+        _mm256_storeu_pd(lanes.as_mut_ptr(), err);
+        lanes.into_iter().fold(rest, f64::max)
+    }
+
+    /// Whether any lane of `s` or `t` has all bits of `exp` set, i.e. is not
+    /// finite, with `cmpeq` comparing lanes of the float's width.
+    macro_rules! any_nonfinite {
+        ($cmpeq:ident, $exp:expr, $s:expr, $t:expr) => {
+            _mm256_movemask_epi8(_mm256_or_si256(
+                $cmpeq(_mm256_and_si256($s, $exp), $exp),
+                $cmpeq(_mm256_and_si256($t, $exp), $exp),
+            )) != 0
+        };
+    }
+
+    /// Run `$diff` on every 32-byte block of finite values, and the scalar
+    /// kernel on blocks with special values and on the tail.
+    macro_rules! max_diff_blocks {
+        ($src:expr, $targ:expr, $scalar:path, |$err:ident, $s:ident, $t:ident| $diff:expr) => {{
+            let (src, targ) = ($src, $targ);
+            let blocks = src.len() / 32;
+            let mut $err = _mm256_setzero_pd();
+            let mut special = 0.0f64;
+            for i in 0..blocks {
+                let $s = _mm256_loadu_si256(src.as_ptr().add(i * 32).cast());
+                let $t = _mm256_loadu_si256(targ.as_ptr().add(i * 32).cast());
+                match $diff {
+                    Some(e) => $err = e,
+                    None => {
+                        let r = i * 32..(i + 1) * 32;
+                        special = special.max($scalar(&src[r.clone()], &targ[r]));
+                    }
+                }
+            }
+            let tail = $scalar(&src[blocks * 32..], &targ[blocks * 32..]);
+            max_lane($err, special.max(tail))
+        }};
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn max_diff_f32(src: &[u8], targ: &[u8]) -> f64 {
+        let exp = _mm256_set1_epi32(0x7f80_0000);
+        max_diff_blocks!(src, targ, scalar::max_diff_f32, |err, s, t| {
+            if any_nonfinite!(_mm256_cmpeq_epi32, exp, s, t) {
+                None
+            } else {
+                Some(diff_ps(err, _mm256_castsi256_ps(s), _mm256_castsi256_ps(t)))
+            }
+        })
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn max_diff_f64(src: &[u8], targ: &[u8]) -> f64 {
+        let exp = _mm256_set1_epi64x(0x7ff0_0000_0000_0000);
+        let abs = _mm256_castsi256_pd(_mm256_set1_epi64x(i64::MAX));
+        max_diff_blocks!(src, targ, scalar::max_diff_f64, |err, s, t| {
+            if any_nonfinite!(_mm256_cmpeq_epi64, exp, s, t) {
+                None
+            } else {
+                let d = _mm256_sub_pd(_mm256_castsi256_pd(s), _mm256_castsi256_pd(t));
+                Some(_mm256_max_pd(err, _mm256_and_pd(d, abs)))
+            }
+        })
+    }
+
+    #[target_feature(enable = "avx2,f16c")]
+    pub unsafe fn max_diff_f16(src: &[u8], targ: &[u8]) -> f64 {
+        let exp = _mm256_set1_epi16(0x7c00);
+        max_diff_blocks!(src, targ, scalar::max_diff_f16, |err, s, t| {
+            if any_nonfinite!(_mm256_cmpeq_epi16, exp, s, t) {
+                None
+            } else {
+                let (s0, t0) = (_mm256_castsi256_si128(s), _mm256_castsi256_si128(t));
+                let (s1, t1) = (
+                    _mm256_extracti128_si256(s, 1),
+                    _mm256_extracti128_si256(t, 1),
+                );
+                let err = diff_ps(err, _mm256_cvtph_ps(s0), _mm256_cvtph_ps(t0));
+                Some(diff_ps(err, _mm256_cvtph_ps(s1), _mm256_cvtph_ps(t1)))
+            }
+        })
+    }
+
+    /// Widen 8 bfloat16 without NaNs to floats.
+    #[target_feature(enable = "avx2")]
+    unsafe fn widen_finite_bf16(v: __m128i) -> __m256 {
+        _mm256_castsi256_ps(_mm256_slli_epi32(_mm256_cvtepu16_epi32(v), 16))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn max_diff_bf16(src: &[u8], targ: &[u8]) -> f64 {
+        let exp = _mm256_set1_epi16(0x7f80);
+        max_diff_blocks!(src, targ, scalar::max_diff_bf16, |err, s, t| {
+            if any_nonfinite!(_mm256_cmpeq_epi16, exp, s, t) {
+                None
+            } else {
+                let (s0, t0) = (_mm256_castsi256_si128(s), _mm256_castsi256_si128(t));
+                let (s1, t1) = (
+                    _mm256_extracti128_si256(s, 1),
+                    _mm256_extracti128_si256(t, 1),
+                );
+                let err = diff_ps(err, widen_finite_bf16(s0), widen_finite_bf16(t0));
+                Some(diff_ps(err, widen_finite_bf16(s1), widen_finite_bf16(t1)))
+            }
+        })
+    }
+
+    /// Integer diffs as the largest `max(s, t) - min(s, t)`, which always
+    /// fits the unsigned type of the same width.
+    macro_rules! int_diff_kernels {
+        ($($name:ident: $uty:ty, $max:ident, $min:ident, $sub:ident, $max_u:ident;)*) => {$(
+            #[target_feature(enable = "avx2")]
+            pub unsafe fn $name(src: &[u8], targ: &[u8]) -> f64 {
+                let blocks = src.len() / 32;
+                let mut acc = _mm256_setzero_si256();
+                for i in 0..blocks {
+                    let s = _mm256_loadu_si256(src.as_ptr().add(i * 32).cast());
+                    let t = _mm256_loadu_si256(targ.as_ptr().add(i * 32).cast());
+                    acc = $max_u(acc, $sub($max(s, t), $min(s, t)));
+                }
+                let mut lanes = [0u8; 32];
+                _mm256_storeu_si256(lanes.as_mut_ptr().cast(), acc);
+                let max = lanes
+                    .chunks_exact(size_of::<$uty>())
+                    .map(|b| <$uty>::from_le_bytes(b.try_into().unwrap()))
+                    .fold(0, <$uty>::max);
+                f64::from(max).max(scalar::$name(&src[blocks * 32..], &targ[blocks * 32..]))
+            }
+        )*};
+    }
+
+    int_diff_kernels! {
+        max_diff_i8: u8, _mm256_max_epi8, _mm256_min_epi8, _mm256_sub_epi8, _mm256_max_epu8;
+        max_diff_u8: u8, _mm256_max_epu8, _mm256_min_epu8, _mm256_sub_epi8, _mm256_max_epu8;
+        max_diff_i16: u16, _mm256_max_epi16, _mm256_min_epi16, _mm256_sub_epi16, _mm256_max_epu16;
+        max_diff_u16: u16, _mm256_max_epu16, _mm256_min_epu16, _mm256_sub_epi16, _mm256_max_epu16;
+        max_diff_i32: u32, _mm256_max_epi32, _mm256_min_epi32, _mm256_sub_epi32, _mm256_max_epu32;
+        max_diff_u32: u32, _mm256_max_epu32, _mm256_min_epu32, _mm256_sub_epi32, _mm256_max_epu32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Random bytes from a fixed xorshift seed.
+    fn random(len: usize, mut state: u64) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    /// Floats covering specials, subnormals, rounding ties and random bits,
+    /// at a length that leaves a scalar tail.
+    fn samples() -> Vec<u8> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut v = vec![
+            0.0,
+            -0.0,
+            f32::NAN,
+            -f32::NAN,
+            f32::from_bits(0x7f80_0001),
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::MIN_POSITIVE / 3.0,
+            f32::MAX,
+            f32::from_bits(0x3f80_8000),
+            f32::from_bits(0x3f81_8000),
+            f32::from_bits(0x7f7f_ffff),
+        ];
+        v.extend((0..1000).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            f32::from_bits(state as u32)
+        }));
+        v.extend((0..37).map(|i| i as f32 * 0.1));
+        v.iter().flat_map(|f| f.to_le_bytes()).collect()
+    }
+
+    /// Doubles covering specials, subnormals, values out of float range and
+    /// random bits, at a length that leaves a scalar tail.
+    fn samples_f64() -> Vec<u8> {
+        let mut v = vec![
+            0.0,
+            -0.0,
+            f64::NAN,
+            -f64::NAN,
+            f64::from_bits(0x7ff0_0000_0000_0001),
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::MIN_POSITIVE / 3.0,
+            f64::MAX,
+            1e-40,
+            f64::from(f32::MAX) * 1.5,
+            1.0 + f64::EPSILON,
+        ];
+        let bits = random(8 * 1000, 0x9e37_79b9_7f4a_7c15);
+        v.extend(
+            bits.chunks_exact(8)
+                .map(|b| f64::from_le_bytes(b.try_into().unwrap())),
+        );
+        v.extend((0..37).map(|i| i as f64 * 0.1));
+        v.iter().flat_map(|f| f.to_le_bytes()).collect()
+    }
+
+    /// Half floats covering specials and random bits, the same bytes for
+    /// float16 and bfloat16.
+    fn samples_16() -> Vec<u8> {
+        let mut v = [
+            0x0000u16, 0x8000, 0x7c00, 0xfc00, 0x7e00, 0x7c01, 0x7f80, 0xff80, 0x7fc0, 0x7f81,
+            0x0001, 0x8001, 0x03ff, 0x7bff, 0x7f7f,
+        ]
+        .iter()
+        .flat_map(|b| b.to_le_bytes())
+        .collect::<Vec<_>>();
+        v.extend(random(2 * 1037, 0x2545_f491_4f6c_dd1d));
+        v
+    }
+
+    /// A copy of `src` with every third value past the specials replaced by
+    /// `f` of it.
+    fn perturb<const N: usize>(src: &[u8], f: impl Fn([u8; N]) -> [u8; N]) -> Vec<u8> {
+        let mut targ = src.to_vec();
+        for v in targ.chunks_exact_mut(N).skip(15).step_by(3) {
+            let b = f(v.try_into().unwrap());
+            v.copy_from_slice(&b);
+        }
+        targ
+    }
+
+    #[test]
+    fn split_merge() {
+        type Split = fn(&[u8], &mut Vec<u8>, &mut Vec<u8>);
+        type Merge = fn(&[u8], &[u8], &mut Vec<u8>);
+        let kernels: [(Split, Split, Merge, Merge, Vec<u8>); 4] = [
+            (
+                scalar::split_f32,
+                split_f32,
+                scalar::merge_f32,
+                merge_f32,
+                samples(),
+            ),
+            (
+                scalar::split_f64,
+                split_f64,
+                scalar::merge_f64,
+                merge_f64,
+                samples_f64(),
+            ),
+            (
+                scalar::split_f16,
+                split_f16,
+                scalar::merge_f16,
+                merge_f16,
+                samples_16(),
+            ),
+            (
+                scalar::split_bf16,
+                split_bf16,
+                scalar::merge_bf16,
+                merge_bf16,
+                samples_16(),
+            ),
+        ];
+        for (split_0, split_1, merge_0, merge_1, src) in kernels {
+            let (mut e0, mut m0, mut e1, mut m1) = (vec![], vec![], vec![], vec![]);
+            split_0(&src, &mut e0, &mut m0);
+            split_1(&src, &mut e1, &mut m1);
+            assert_eq!((&e0, &m0), (&e1, &m1));
+
+            let (mut o0, mut o1) = (vec![], vec![]);
+            merge_0(&e0, &m0, &mut o0);
+            merge_1(&e0, &m0, &mut o1);
+            assert_eq!(o0, src);
+            assert_eq!(o1, src);
+        }
+    }
+
+    #[test]
+    fn bf16() {
+        let src = samples();
+        let (mut b0, mut b1) = (vec![], vec![]);
+        scalar::f32_to_bf16(&src, &mut b0);
+        f32_to_bf16(&src, &mut b1);
+        assert_eq!(b0, b1);
+
+        let (mut f0, mut f1) = (vec![], vec![]);
+        scalar::bf16_to_f32(&b0, &mut f0);
+        bf16_to_f32(&b0, &mut f1);
+        assert_eq!(f0, f1);
+
+        let src = samples_16();
+        let (mut d0, mut d1) = (vec![], vec![]);
+        scalar::bf16_to_f64(&src, &mut d0);
+        bf16_to_f64(&src, &mut d1);
+        assert_eq!(d0, d1);
+    }
+
+    #[test]
+    fn f64_f32() {
+        let src = samples_f64();
+        let (mut f0, mut f1) = (vec![], vec![]);
+        scalar::f64_to_f32(&src, &mut f0);
+        f64_to_f32(&src, &mut f1);
+        assert_eq!(f0, f1);
+
+        let (mut d0, mut d1) = (vec![], vec![]);
+        scalar::f32_to_f64(&f0, &mut d0);
+        f32_to_f64(&f0, &mut d1);
+        assert_eq!(d0, d1);
+    }
+
+    #[test]
+    fn diff() {
+        let src = samples();
+        let mut targ = src.clone();
+        for v in targ.chunks_exact_mut(4).skip(12).step_by(3) {
+            let f = f32::from_le_bytes(v.try_into().unwrap());
+            v.copy_from_slice(&(f * 1.0001).to_le_bytes());
+        }
+        for (s, t) in [(&src, &src), (&src, &targ)] {
+            let a = scalar::max_diff_f32(s, t);
+            assert_eq!(a.to_bits(), max_diff_f32(s, t).to_bits());
+        }
+
+        // finite blocks only
+        let src = &src[4 * 1012..];
+        let targ = &targ[4 * 1012..];
+        let a = scalar::max_diff_f32(src, targ);
+        assert!(a > 0.0);
+        assert_eq!(a.to_bits(), max_diff_f32(src, targ).to_bits());
+    }
+
+    #[test]
+    fn diff_types() {
+        type Diff = fn(&[u8], &[u8]) -> f64;
+        let f64s = samples_f64();
+        let f64_targ = perturb(&f64s, |b| (f64::from_le_bytes(b) * 1.0001).to_le_bytes());
+        let halves = samples_16();
+        let half_targ = perturb(&halves, |b| (u16::from_le_bytes(b) ^ 0x11).to_le_bytes());
+        // random values below 2, so that every block takes the vector path
+        let finite_f64 = random(8 * 1037, 1)
+            .chunks_exact(8)
+            .flat_map(|b| (u64::from_le_bytes(b.try_into().unwrap()) & !(1 << 62)).to_le_bytes())
+            .collect::<Vec<_>>();
+        let finite_f64_targ = perturb(&finite_f64, |b| {
+            (f64::from_le_bytes(b) * 1.0001).to_le_bytes()
+        });
+        let finite_16 = random(2 * 1037, 2)
+            .chunks_exact(2)
+            .flat_map(|b| (u16::from_le_bytes(b.try_into().unwrap()) & !(1 << 14)).to_le_bytes())
+            .collect::<Vec<_>>();
+        let finite_16_targ = perturb(&finite_16, |b| (u16::from_le_bytes(b) ^ 0x11).to_le_bytes());
+        let ints = random(4 * 1037, 0x9e37_79b9_7f4a_7c15);
+        let int_targ = random(4 * 1037, 0x2545_f491_4f6c_dd1d);
+
+        let kernels: [(Diff, Diff, &[u8], &[u8]); 12] = [
+            (scalar::max_diff_f64, max_diff_f64, &f64s, &f64_targ),
+            (
+                scalar::max_diff_f64,
+                max_diff_f64,
+                &finite_f64,
+                &finite_f64_targ,
+            ),
+            (scalar::max_diff_f16, max_diff_f16, &halves, &half_targ),
+            (
+                scalar::max_diff_f16,
+                max_diff_f16,
+                &finite_16,
+                &finite_16_targ,
+            ),
+            (scalar::max_diff_bf16, max_diff_bf16, &halves, &half_targ),
+            (
+                scalar::max_diff_bf16,
+                max_diff_bf16,
+                &finite_16,
+                &finite_16_targ,
+            ),
+            (scalar::max_diff_i8, max_diff_i8, &ints, &int_targ),
+            (scalar::max_diff_u8, max_diff_u8, &ints, &int_targ),
+            (scalar::max_diff_i16, max_diff_i16, &ints, &int_targ),
+            (scalar::max_diff_u16, max_diff_u16, &ints, &int_targ),
+            (scalar::max_diff_i32, max_diff_i32, &ints, &int_targ),
+            (scalar::max_diff_u32, max_diff_u32, &ints, &int_targ),
+        ];
+        for (i, (d0, d1, src, targ)) in kernels.into_iter().enumerate() {
+            assert_eq!(d1(src, src), 0.0, "kernel {i}");
+            let d = d0(src, targ);
+            assert!(d > 0.0, "kernel {i}");
+            assert_eq!(d.to_bits(), d1(src, targ).to_bits(), "kernel {i}");
+        }
+        for (d, src, targ) in [
+            (max_diff_f64 as Diff, &finite_f64, &finite_f64_targ),
+            (max_diff_f16, &finite_16, &finite_16_targ),
+            (max_diff_bf16, &finite_16, &finite_16_targ),
+        ] {
+            assert!(d(src, targ).is_finite());
+        }
+
+        // the full range of a 32-bit difference
+        let (lo, hi) = (i32::MIN.to_le_bytes(), i32::MAX.to_le_bytes());
+        assert_eq!(max_diff_i32(&lo, &hi), u32::MAX as f64);
+        assert_eq!(max_diff_i32(&lo.repeat(9), &hi.repeat(9)), u32::MAX as f64);
+    }
+}
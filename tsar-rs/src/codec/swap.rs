@@ -0,0 +1,41 @@
+/// Reverse the byte order of every `N`-byte word.
+pub struct ByteSwap(pub usize);
+
+impl ByteSwap {
+    pub fn swap_in_place(&self, buf: &mut [u8]) {
+        if self.0 > 1 {
+            buf.chunks_exact_mut(self.0).for_each(<[u8]>::reverse);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::test_util;
+
+    #[test]
+    fn swap_f32() {
+        let be = test_util::F32_DATA
+            .iter()
+            .flat_map(|f| f.to_be_bytes())
+            .collect::<Vec<_>>();
+        let le = test_util::F32_DATA
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let mut out = be.clone();
+        ByteSwap(4).swap_in_place(&mut out);
+        assert_eq!(out, le);
+        ByteSwap(4).swap_in_place(&mut out);
+        assert_eq!(out, be);
+
+        // words of one byte or less are left alone
+        let mut odd = be[..6].to_vec();
+        ByteSwap(1).swap_in_place(&mut odd);
+        assert_eq!(odd, be[..6]);
+        ByteSwap(0).swap_in_place(&mut odd);
+        assert_eq!(odd, be[..6]);
+    }
+}
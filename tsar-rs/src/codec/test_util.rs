@@ -42,3 +42,18 @@ pub const BF16_DATA: [half::bf16; 12] = [
     half::bf16::INFINITY,
     half::bf16::NEG_INFINITY,
 ];
+
+pub const F16_DATA: [half::f16; 12] = [
+    half::f16::from_f32_const(0.0),
+    half::f16::from_f32_const(123.4),
+    half::f16::from_f32_const(-123.4),
+    half::f16::MAX,
+    half::f16::MIN_POSITIVE_SUBNORMAL,
+    half::f16::NAN,
+    half::f16::EPSILON,
+    half::f16::from_f32_const(std::f32::consts::PI),
+    half::f16::from_f32_const(std::f32::consts::LN_2),
+    half::f16::from_f32_const(std::f32::consts::E),
+    half::f16::INFINITY,
+    half::f16::NEG_INFINITY,
+];
@@ -23,7 +23,8 @@ impl<'a> Zfp<'a> {
         }
     }
 
-    unsafe fn new_field(&self, data: *mut std::ffi::c_void) -> *mut zfp_sys::zfp_field {
+    /// The shape folded into `dim` dimensions.
+    fn field_shape(&self) -> [usize; 4] {
         let mut field_shape = [1usize; 4];
         for (i, &s) in self.shape.iter().enumerate() {
             if i < self.dim {
@@ -32,6 +33,11 @@ impl<'a> Zfp<'a> {
                 field_shape[self.dim - 1] *= s;
             }
         }
+        field_shape
+    }
+
+    unsafe fn new_field(&self, data: *mut std::ffi::c_void) -> *mut zfp_sys::zfp_field {
+        let field_shape = self.field_shape();
 
         let dt = match self.dt {
             DataType::Float32 => zfp_sys::zfp_type_zfp_type_float,
@@ -60,6 +66,10 @@ impl<'a> Zfp<'a> {
 
 #[allow(clippy::useless_conversion, clippy::unnecessary_cast)] // workaround for windows
 impl Codec for Zfp<'_> {
+    fn lossless(&self) -> bool {
+        false
+    }
+
     fn encode<'a, I>(&self, data: I, out: &mut super::BufferList) -> Result<()>
     where
         I: IntoIterator<Item = &'a [u8]>,
@@ -68,6 +78,10 @@ impl Codec for Zfp<'_> {
         let mut data = data.into_iter();
         assert_eq!(data.len(), 1, "ZFP: must be only one input");
         let data = data.next().unwrap();
+        // zfp has no representation for an empty field
+        if data.is_empty() {
+            return Err(Error::Unsupported("empty ZFP field".into()));
+        }
         out.reset(1);
 
         let field = unsafe { self.new_field(data.as_ptr() as *mut std::ffi::c_void) };
@@ -109,40 +123,140 @@ impl Codec for Zfp<'_> {
         I::IntoIter: ExactSizeIterator,
     {
         let mut data = data.into_iter();
-        assert_eq!(data.len(), 1, "ZFP: must be only one input");
+        if data.len() != 1 {
+            return Err(Error::Corrupt("ZFP takes exactly one input"));
+        }
         let data = data.next().unwrap();
-        out.reset(1);
-
-        out[0].resize(self.shape.iter().product::<usize>() * self.dt.byte_len(), 0);
-
-        let field = unsafe { self.new_field(out[0].as_mut_ptr() as *mut std::ffi::c_void) };
+        let len = self
+            .shape
+            .iter()
+            .try_fold(1usize, |n, &d| n.checked_mul(d))
+            .and_then(|n| n.checked_mul(self.dt.byte_len()))
+            .filter(|&n| n > 0)
+            .ok_or(Error::Corrupt("invalid ZFP field shape"))?;
+        // every block takes at least one bit, so a short stream cannot stand
+        // for a huge field; check before allocating it
+        let blocks = self.field_shape()[..self.dim]
+            .iter()
+            .map(|n| n.div_ceil(4))
+            .product::<usize>();
+        if blocks.div_ceil(8) > data.len() {
+            return Err(Error::Corrupt("truncated ZFP stream"));
+        }
 
+        // zfp reads whole words without bounds checks, so decode from an
+        // aligned zero-padded copy as long as the largest stream the header
+        // allows for this field
+        let words = |bytes: usize| {
+            let mut w = vec![0u64; bytes.div_ceil(8).max(1)];
+            let n = data.len().min(w.len() * 8);
+            // SAFETY: `w` holds at least `n` bytes
+            unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), w.as_mut_ptr().cast(), n) };
+            w
+        };
+        let field = unsafe { self.new_field(std::ptr::null_mut()) };
         let zfp =
             unsafe { zfp_sys::zfp_stream_open(std::ptr::null_mut() as *mut zfp_sys::bitstream) };
-
-        let stream = unsafe {
-            zfp_sys::stream_open(
-                data.as_ptr() as *mut std::ffi::c_void,
-                data.len().try_into().unwrap(),
+        let decode = |buf: &mut [u64]| unsafe {
+            let stream = zfp_sys::stream_open(buf.as_mut_ptr().cast(), buf.len() * 8);
+            zfp_sys::zfp_stream_set_bit_stream(zfp, stream);
+            zfp_sys::zfp_stream_rewind(zfp);
+            (
+                stream,
+                zfp_sys::zfp_read_header(zfp, field, zfp_sys::ZFP_HEADER_MODE),
             )
         };
-        unsafe {
-            zfp_sys::zfp_stream_set_bit_stream(zfp, stream);
-        }
-
-        let hdr = unsafe { zfp_sys::zfp_read_header(zfp, field, zfp_sys::ZFP_HEADER_MODE) };
 
-        let sz = unsafe { zfp_sys::zfp_decompress(zfp, field) };
+        // the decoder trusts the header's bit budget per block, so bound it
+        // by what a block can take, which makes the maximum size a read bound
+        // (header bits in reversible mode, precision), as zfp counts them
+        let (ebits, prec) = match self.dt {
+            DataType::Float64 => (19, 64),
+            DataType::Int64 => (6, 64),
+            DataType::Int32 => (5, 32),
+            _ => (15, 32),
+        };
+        let values = 1u32 << (2 * self.dim);
+        let clamp = || unsafe {
+            let (mut minbits, mut maxbits, mut maxprec, mut minexp) = (0, 0, 0, 0);
+            zfp_sys::zfp_stream_params(zfp, &mut minbits, &mut maxbits, &mut maxprec, &mut minexp);
+            // fewer bits than the block header underflow zfp's budget
+            let cap = ebits + values - 1 + values * maxprec.min(prec);
+            maxbits >= ebits
+                && zfp_sys::zfp_stream_set_params(zfp, minbits, maxbits.min(cap), maxprec, minexp)
+                    != 0
+        };
 
+        let mut res = Err(Error::Corrupt("invalid ZFP header"));
+        let mut head = words(16);
+        let (stream, hdr) = decode(&mut head);
+        let ok = hdr != 0 && clamp();
+        let max = unsafe { zfp_sys::zfp_stream_maximum_size(zfp, field) } as usize;
+        unsafe { zfp_sys::stream_close(stream) };
+        // streams we write stay well under twice the decoded size
+        if ok && max <= len.saturating_mul(2) + 64 {
+            // one more word for the read-ahead of the bit stream
+            let mut buf = words(max.max(data.len()) + 8);
+            let (stream, _) = decode(&mut buf);
+            out.reset(1);
+            out[0].resize(len, 0);
+            unsafe { zfp_sys::zfp_field_set_pointer(field, out[0].as_mut_ptr().cast()) };
+            if clamp() {
+                let sz = unsafe { zfp_sys::zfp_decompress(zfp, field) } as usize;
+                res = match sz {
+                    0 => Err(Error::ZPFUnknown),
+                    sz if sz > data.len().next_multiple_of(8) => {
+                        Err(Error::Corrupt("truncated ZFP stream"))
+                    }
+                    _ => Ok(()),
+                };
+            }
+            unsafe { zfp_sys::stream_close(stream) };
+        }
         unsafe {
             zfp_sys::zfp_field_free(field);
             zfp_sys::zfp_stream_close(zfp);
-            zfp_sys::stream_close(stream);
         }
+        res
+    }
+}
 
-        if hdr == 0 || sz == 0 {
-            return Err(Error::ZPFUnknown);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::BufferList;
+
+    #[test]
+    fn malformed() {
+        let data = (0..1000)
+            .flat_map(|i| (i as f32 * 0.01).sin().to_le_bytes())
+            .collect::<Vec<_>>();
+        let zfp = Zfp::new(DataType::Float32, 1, &[1000], 1e-3);
+        let mut out = BufferList::new();
+        zfp.encode([data.as_slice()], &mut out).unwrap();
+        let stream = out[0].clone();
+        let mut back = BufferList::new();
+        zfp.decode([stream.as_slice()], &mut back).unwrap();
+        assert!(DataType::Float32.max_difference(&data, &back[0]).unwrap() <= 1e-3);
+
+        for bad in [&stream[..0], &stream[..7], &stream[..stream.len() / 2]] {
+            assert!(zfp.decode([bad], &mut back).is_err());
         }
-        Ok(())
+        // a mode header asking for a huge number of bits per block
+        assert!(zfp.decode([&[0xff; 64][..]], &mut back).is_err());
+        // fixed rate of one bit per block, fewer than its exponent takes
+        let f64s = Zfp::new(DataType::Float64, 1, &[4096], 0.0);
+        let rate = [0x00, 0xf0, 0xff, 0xff, 0xff, 0xff];
+        assert!(f64s.decode([&rate[..]], &mut back).is_err());
+        assert!(zfp.decode([&stream[..], &stream[..]], &mut back).is_err());
+        for shape in [&[0][..], &[usize::MAX, 2]] {
+            let zfp = Zfp::new(DataType::Float32, 1, shape, 0.0);
+            assert!(zfp.decode([stream.as_slice()], &mut back).is_err());
+        }
+        let empty = Zfp::new(DataType::Float32, 1, &[0], 1e-3);
+        assert!(matches!(
+            empty.encode([&[][..]], &mut out),
+            Err(Error::Unsupported(_))
+        ));
     }
 }
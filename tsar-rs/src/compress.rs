@@ -1,49 +1,193 @@
 use crate::{
     codec::{self, BufferList, Codec},
     pb,
-    result::Result,
+    result::{Error, Result},
     DataType,
 };
 
-pub fn compress<'a>(
-    data: &'a [u8],
-    dt: DataType,
-    shape: &'a [usize],
-    stages: &'a [pb::CompressionStage],
-    target_prec: f64,
-) -> Result<(BufferList, f64)> {
-    let mut out = BufferList::new();
-    let mut tmp = BufferList::new();
-    for (idx, &s) in stages.iter().enumerate() {
-        if idx == 0 {
-            do_encode(s, [data], shape, target_prec, &mut out)?;
-        } else {
-            do_encode(s, tmp.iter_slice(), shape, target_prec, &mut out)?;
-        }
-        std::mem::swap(&mut out, &mut tmp);
-    }
-    let result = tmp.clone();
-    for &s in stages.iter().rev() {
-        do_decode(s, tmp.iter_slice(), shape, &mut out)?;
-        std::mem::swap(&mut out, &mut tmp);
-    }
-    let err = dt.max_difference(data, tmp.iter().next().unwrap()).unwrap();
-    Ok((result, err))
+/// Lists kept for reuse by a [`Context`]; more are dropped.
+const MAX_POOLED: usize = 8;
+
+/// Most bytes any stored or intermediate buffer of data decoding to `len`
+/// bytes holds: no stage more than doubles its input, plus some framing.
+pub fn max_encoded_len(len: usize) -> usize {
+    len.saturating_mul(2).saturating_add(64)
 }
 
-pub fn decompress<'a>(
-    mut data: BufferList,
-    _dt: DataType,
-    shape: &'a [usize],
-    stages: &'a [pb::CompressionStage],
-) -> Result<Vec<u8>> {
-    let mut out = BufferList::new();
-    for &s in stages.iter().rev() {
-        do_decode(s, data.iter_slice(), shape, &mut out)?;
-        std::mem::swap(&mut out, &mut data);
-    }
-    assert_eq!(data.len(), 1);
-    Ok(std::mem::take(&mut data[0]))
+/// Scratch buffers recycled across `compress` and `decompress` calls, so
+/// packing or extracting many tensors does not reallocate them each time.
+#[derive(Default)]
+pub struct Context {
+    pool: Vec<BufferList>,
+    lent: usize,
+    peak: usize,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Most bytes of buffer capacity held at once, including lists handed
+    /// out by `compress` and not yet recycled.
+    pub fn peak_bytes(&self) -> usize {
+        self.peak
+    }
+
+    /// Return a list handed out by [`Context::compress`].
+    pub fn recycle(&mut self, b: BufferList) {
+        self.lent = self.lent.saturating_sub(b.capacity());
+        self.give(b);
+    }
+
+    fn take(&mut self) -> BufferList {
+        self.pool.pop().unwrap_or_default()
+    }
+
+    fn give(&mut self, b: BufferList) {
+        if self.pool.len() < MAX_POOLED {
+            self.pool.push(b);
+        }
+    }
+
+    /// Record the current footprint, with `busy` lists taken from the pool.
+    fn track(&mut self, busy: &[&BufferList]) {
+        let held = self.pool.iter().map(BufferList::capacity).sum::<usize>()
+            + busy.iter().map(|b| b.capacity()).sum::<usize>()
+            + self.lent;
+        self.peak = self.peak.max(held);
+    }
+
+    /// Encode `data` through `stages` and measure the error of the round
+    /// trip. The returned list should be given back with [`Context::recycle`].
+    ///
+    /// Only the first stage of a pipeline may be lossy, so the error is that
+    /// of the first stage alone, and later stages are never decoded.
+    pub fn compress(
+        &mut self,
+        data: &[u8],
+        dt: DataType,
+        shape: &[usize],
+        stages: &[pb::CompressionStage],
+        target_prec: f64,
+    ) -> Result<(BufferList, f64)> {
+        let mut out = self.take();
+        let mut tmp = self.take();
+        let mut err = 0.0;
+        for (idx, &s) in stages.iter().enumerate() {
+            if idx == 0 {
+                do_encode(s, [data], shape, target_prec, &mut out)?;
+                err = self.stage_error(s, data, &out, dt, shape)?;
+            } else if is_lossless(s) {
+                do_encode(s, tmp.iter_slice(), shape, target_prec, &mut out)?;
+            } else {
+                return Err(Error::Unsupported(format!(
+                    "lossy stage {s:?} after the first"
+                )));
+            }
+            std::mem::swap(&mut out, &mut tmp);
+        }
+        self.track(&[&out, &tmp]);
+        self.give(out);
+        self.lent += tmp.capacity();
+        Ok((tmp, err))
+    }
+
+    /// Error of the first stage `s` of a pipeline, which encoded `data` to
+    /// `encoded`: none for lossless stages, while conversions compute it
+    /// from `data` alone. Other stages, such as ZFP whose tolerances are not
+    /// strict bounds for every input, are decoded and measured.
+    fn stage_error(
+        &mut self,
+        s: pb::CompressionStage,
+        data: &[u8],
+        encoded: &BufferList,
+        dt: DataType,
+        shape: &[usize],
+    ) -> Result<f64> {
+        if is_lossless(s) {
+            return Ok(0.0);
+        }
+        let convert = match s {
+            pb::CompressionStage::CONVERT_FLOAT32_TO_BFLOAT16 => codec::Convert::Float32ToBfloat16,
+            pb::CompressionStage::CONVERT_FLOAT64_TO_BFLOAT16 => codec::Convert::Float64ToBfloat16,
+            pb::CompressionStage::CONVERT_FLOAT64_TO_FLOAT32 => codec::Convert::Float64ToFloat32,
+            _ => return self.measured_error(s, data, encoded, dt, shape),
+        };
+        Ok(convert.max_error(data))
+    }
+
+    /// Error of stage `s`, found by decoding `encoded` and comparing it to
+    /// `data`.
+    fn measured_error(
+        &mut self,
+        s: pb::CompressionStage,
+        data: &[u8],
+        encoded: &BufferList,
+        dt: DataType,
+        shape: &[usize],
+    ) -> Result<f64> {
+        let mut back = self.take();
+        do_decode(s, encoded.iter_slice(), shape, usize::MAX, &mut back)?;
+        // a decoded length mismatch is as wrong as it gets
+        let err = match back.len() {
+            1 => dt.max_difference(data, &back[0]).unwrap_or(f64::INFINITY),
+            _ => f64::INFINITY,
+        };
+        self.track(&[encoded, &back]);
+        self.give(back);
+        Ok(err)
+    }
+
+    pub fn decompress(
+        &mut self,
+        mut data: BufferList,
+        dt: DataType,
+        shape: &[usize],
+        stages: &[pb::CompressionStage],
+    ) -> Result<Vec<u8>> {
+        let len = shape
+            .iter()
+            .try_fold(dt.bit_len(), |n, &d| n.checked_mul(d))
+            .map_or(usize::MAX, |n| n.div_ceil(8));
+        let mut out = self.take();
+        for &s in stages.iter().rev() {
+            do_decode(s, data.iter_slice(), shape, max_encoded_len(len), &mut out)?;
+            std::mem::swap(&mut out, &mut data);
+        }
+        if data.len() != 1 {
+            self.give(data);
+            self.give(out);
+            return Err(Error::Corrupt("blob does not decode to one buffer"));
+        }
+        self.track(&[&data, &out]);
+        let d = std::mem::take(&mut data[0]);
+        self.give(data);
+        self.give(out);
+        Ok(d)
+    }
+}
+
+/// Whether `stage` decodes to its input bit for bit, as its codec reports;
+/// an invalid stage does not decode at all.
+fn is_lossless(stage: pb::CompressionStage) -> bool {
+    use pb::CompressionStage::*;
+    match stage {
+        INVALID_STAGE => false,
+        ZSTD => codec::Compress::Zstd(9).lossless(),
+        CONVERT_FLOAT32_TO_BFLOAT16 => codec::Convert::Float32ToBfloat16.lossless(),
+        CONVERT_FLOAT64_TO_BFLOAT16 => codec::Convert::Float64ToBfloat16.lossless(),
+        CONVERT_FLOAT64_TO_FLOAT32 => codec::Convert::Float64ToFloat32.lossless(),
+        SPLIT_MANTISSA_BFLOAT16 => codec::Split::Bfloat16.lossless(),
+        SPLIT_MANTISSA_FLOAT32 => codec::Split::Float32.lossless(),
+        SPLIT_MANTISSA_FLOAT64 => codec::Split::Float64.lossless(),
+        FPC_FLOAT16 => codec::Fpc::Float16.lossless(),
+        FPC_BFLOAT16 => codec::Fpc::Bfloat16.lossless(),
+        FPC_FLOAT32 => codec::Fpc::Float32.lossless(),
+        FPC_FLOAT64 => codec::Fpc::Float64.lossless(),
+        ZFP_FLOAT32_1D => codec::Zfp::new(DataType::Float32, 1, &[], 0.0).lossless(),
+        ZFP_FLOAT64_1D => codec::Zfp::new(DataType::Float64, 1, &[], 0.0).lossless(),
+    }
 }
 
 fn do_encode<'a, I>(
@@ -58,7 +202,9 @@ where
     I::IntoIter: ExactSizeIterator,
 {
     match stage {
-        pb::CompressionStage::INVALID_STAGE => todo!(),
+        pb::CompressionStage::INVALID_STAGE => {
+            Err(Error::Unsupported("invalid compression stage".into()))
+        }
         pb::CompressionStage::ZSTD => codec::Compress::Zstd(9).encode(data, out),
         pb::CompressionStage::CONVERT_FLOAT32_TO_BFLOAT16 => {
             codec::Convert::Float32ToBfloat16.encode(data, out)
@@ -72,6 +218,10 @@ where
         pb::CompressionStage::SPLIT_MANTISSA_BFLOAT16 => codec::Split::Bfloat16.encode(data, out),
         pb::CompressionStage::SPLIT_MANTISSA_FLOAT32 => codec::Split::Float32.encode(data, out),
         pb::CompressionStage::SPLIT_MANTISSA_FLOAT64 => codec::Split::Float64.encode(data, out),
+        pb::CompressionStage::FPC_FLOAT16 => codec::Fpc::Float16.encode(data, out),
+        pb::CompressionStage::FPC_BFLOAT16 => codec::Fpc::Bfloat16.encode(data, out),
+        pb::CompressionStage::FPC_FLOAT32 => codec::Fpc::Float32.encode(data, out),
+        pb::CompressionStage::FPC_FLOAT64 => codec::Fpc::Float64.encode(data, out),
         pb::CompressionStage::ZFP_FLOAT32_1D => {
             codec::Zfp::new(DataType::Float32, 1, shape, target_prec).encode(data, out)
         }
@@ -85,6 +235,7 @@ fn do_decode<'a, I>(
     stage: pb::CompressionStage,
     data: I,
     shape: &'a [usize],
+    limit: usize,
     out: &mut BufferList,
 ) -> Result<()>
 where
@@ -92,8 +243,8 @@ where
     I::IntoIter: ExactSizeIterator,
 {
     match stage {
-        pb::CompressionStage::INVALID_STAGE => todo!(),
-        pb::CompressionStage::ZSTD => codec::Compress::Zstd(9).decode(data, out),
+        pb::CompressionStage::INVALID_STAGE => Err(Error::Corrupt("invalid compression stage")),
+        pb::CompressionStage::ZSTD => codec::Compress::Zstd(9).decode_bounded(data, limit, out),
         pb::CompressionStage::CONVERT_FLOAT32_TO_BFLOAT16 => {
             codec::Convert::Float32ToBfloat16.decode(data, out)
         }
@@ -106,6 +257,10 @@ where
         pb::CompressionStage::SPLIT_MANTISSA_BFLOAT16 => codec::Split::Bfloat16.decode(data, out),
         pb::CompressionStage::SPLIT_MANTISSA_FLOAT32 => codec::Split::Float32.decode(data, out),
         pb::CompressionStage::SPLIT_MANTISSA_FLOAT64 => codec::Split::Float64.decode(data, out),
+        pb::CompressionStage::FPC_FLOAT16 => codec::Fpc::Float16.decode(data, out),
+        pb::CompressionStage::FPC_BFLOAT16 => codec::Fpc::Bfloat16.decode(data, out),
+        pb::CompressionStage::FPC_FLOAT32 => codec::Fpc::Float32.decode(data, out),
+        pb::CompressionStage::FPC_FLOAT64 => codec::Fpc::Float64.decode(data, out),
         pb::CompressionStage::ZFP_FLOAT32_1D => {
             codec::Zfp::new(DataType::Float32, 1, shape, 0.0).decode(data, out)
         }
@@ -114,3 +269,115 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specials() -> Vec<u8> {
+        (0..4096)
+            .map(|i| match i % 97 {
+                0 => f32::NAN,
+                1 => f32::INFINITY,
+                2 => f32::NEG_INFINITY,
+                _ => (i as f32).sin(),
+            })
+            .flat_map(|f| f.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn special_values() {
+        let data = specials();
+        let shape = [data.len() / 4];
+        let err = |stages: &[pb::CompressionStage]| {
+            Context::new()
+                .compress(&data, DataType::Float32, &shape, stages, 0.01)
+                .unwrap()
+                .1
+        };
+        assert_eq!(
+            err(&[
+                pb::CompressionStage::SPLIT_MANTISSA_FLOAT32,
+                pb::CompressionStage::ZSTD
+            ]),
+            0.0
+        );
+        assert_eq!(err(&[pb::CompressionStage::ZFP_FLOAT32_1D]), f64::INFINITY);
+    }
+
+    #[test]
+    fn lossy_prefix() {
+        let data = (0..4096)
+            .flat_map(|i| ((i as f32) * 0.01).sin().to_le_bytes())
+            .collect::<Vec<_>>();
+        let shape = [data.len() / 4];
+        let stages = [
+            pb::CompressionStage::CONVERT_FLOAT32_TO_BFLOAT16,
+            pb::CompressionStage::SPLIT_MANTISSA_BFLOAT16,
+            pb::CompressionStage::ZSTD,
+        ];
+        assert!(!is_lossless(stages[0]) && is_lossless(stages[1]) && is_lossless(stages[2]));
+        assert!(!is_lossless(pb::CompressionStage::INVALID_STAGE));
+        assert!(Context::new()
+            .compress(
+                &data,
+                DataType::Float32,
+                &shape,
+                &[pb::CompressionStage::INVALID_STAGE],
+                0.01
+            )
+            .is_err());
+
+        let mut ctx = Context::new();
+        let (out, err) = ctx
+            .compress(&data, DataType::Float32, &shape, &stages, 0.01)
+            .unwrap();
+        let back = ctx
+            .decompress(out, DataType::Float32, &shape, &stages)
+            .unwrap();
+        let full = DataType::Float32.max_difference(&data, &back).unwrap();
+        assert!(err > 0.0);
+        assert_eq!(err, full);
+
+        let (out, err) = ctx
+            .compress(&data, DataType::Float32, &shape, &stages[1..], 0.01)
+            .unwrap();
+        assert_eq!(err, 0.0);
+        ctx.recycle(out);
+
+        let mut late = stages;
+        late.swap(0, 1);
+        assert!(ctx
+            .compress(&data, DataType::Float32, &shape, &late, 0.01)
+            .is_err());
+    }
+
+    #[test]
+    fn reuse() {
+        let data = (0..65536)
+            .flat_map(|i| ((i as f32) * 0.01).sin().to_le_bytes())
+            .collect::<Vec<_>>();
+        let shape = [data.len() / 4];
+        let stages = [
+            pb::CompressionStage::SPLIT_MANTISSA_FLOAT32,
+            pb::CompressionStage::ZSTD,
+        ];
+        let mut ctx = Context::new();
+        for _ in 0..3 {
+            let (out, _) = ctx
+                .compress(&data, DataType::Float32, &shape, &stages, 0.0)
+                .unwrap();
+            ctx.recycle(out);
+        }
+        let peak = ctx.peak_bytes();
+        assert!(peak >= data.len(), "{peak}");
+        for _ in 0..10 {
+            let (out, _) = ctx
+                .compress(&data, DataType::Float32, &shape, &stages, 0.0)
+                .unwrap();
+            ctx.recycle(out);
+        }
+        assert_eq!(ctx.peak_bytes(), peak);
+    }
+}
@@ -1,6 +1,4 @@
-use crate::pb;
-
-use core::cmp::Ordering;
+use crate::{codec::simd, pb};
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum DataType {
@@ -17,6 +15,14 @@ pub enum DataType {
     Uint32,
     Int64,
     Uint64,
+    Bool,
+    Complex64,
+    Complex128,
+    /// Two's complement 4-bit integers, two per byte, low nibble first.
+    Int4,
+    Uint4,
+    Float8E4M3,
+    Float8E5M2,
 }
 
 impl From<DataType> for pb::DataType {
@@ -35,6 +41,13 @@ impl From<DataType> for pb::DataType {
             DataType::Uint32 => pb::DataType::UINT32,
             DataType::Int64 => pb::DataType::INT64,
             DataType::Uint64 => pb::DataType::UINT64,
+            DataType::Bool => pb::DataType::BOOL,
+            DataType::Complex64 => pb::DataType::COMPLEX64,
+            DataType::Complex128 => pb::DataType::COMPLEX128,
+            DataType::Int4 => pb::DataType::INT4,
+            DataType::Uint4 => pb::DataType::UINT4,
+            DataType::Float8E4M3 => pb::DataType::FLOAT8_E4M3,
+            DataType::Float8E5M2 => pb::DataType::FLOAT8_E5M2,
         }
     }
 }
@@ -57,37 +70,71 @@ impl TryFrom<protobuf::EnumOrUnknown<pb::DataType>> for DataType {
             pb::DataType::UINT32 => Ok(DataType::Uint32),
             pb::DataType::INT64 => Ok(DataType::Int64),
             pb::DataType::UINT64 => Ok(DataType::Uint64),
-            pb::DataType::UNKNOWN_DATA_TYPE => unreachable!(),
+            pb::DataType::BOOL => Ok(DataType::Bool),
+            pb::DataType::COMPLEX64 => Ok(DataType::Complex64),
+            pb::DataType::COMPLEX128 => Ok(DataType::Complex128),
+            pb::DataType::INT4 => Ok(DataType::Int4),
+            pb::DataType::UINT4 => Ok(DataType::Uint4),
+            pb::DataType::FLOAT8_E4M3 => Ok(DataType::Float8E4M3),
+            pb::DataType::FLOAT8_E5M2 => Ok(DataType::Float8E5M2),
+            pb::DataType::UNKNOWN_DATA_TYPE => Err(0),
         }
     }
 }
 
-macro_rules! diff_float {
-    ($ty:ty, $src:expr, $targ:expr) => {{
-        const N: usize = std::mem::size_of::<$ty>();
-        #[allow(clippy::modulo_one)]
-        if $src.len() % N != 0 {
-            return None;
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum ByteOrder {
+    #[default]
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    pub const fn native() -> Self {
+        if cfg!(target_endian = "big") {
+            ByteOrder::Big
+        } else {
+            ByteOrder::Little
         }
-        let mut err = f64::default();
-        for (src, targ) in $src.chunks_exact(N).zip($targ.chunks_exact(N)) {
-            let src = f64::from(<$ty>::from_le_bytes(src.try_into().unwrap()));
-            let targ = f64::from(<$ty>::from_le_bytes(targ.try_into().unwrap()));
-            err = err.max(match targ.partial_cmp(&src) {
-                Some(Ordering::Equal) => continue,
-                Some(Ordering::Less) => (src - targ),
-                Some(Ordering::Greater) => (targ - src),
-                None => return None,
-            });
+    }
+}
+
+impl From<ByteOrder> for pb::ByteOrder {
+    fn from(o: ByteOrder) -> Self {
+        match o {
+            ByteOrder::Little => pb::ByteOrder::LITTLE_ENDIAN,
+            ByteOrder::Big => pb::ByteOrder::BIG_ENDIAN,
         }
-        Some(err)
-    }};
+    }
+}
+
+impl TryFrom<protobuf::EnumOrUnknown<pb::ByteOrder>> for ByteOrder {
+    type Error = i32;
+
+    fn try_from(value: protobuf::EnumOrUnknown<pb::ByteOrder>) -> Result<Self, Self::Error> {
+        match value.enum_value()? {
+            pb::ByteOrder::LITTLE_ENDIAN => Ok(ByteOrder::Little),
+            pb::ByteOrder::BIG_ENDIAN => Ok(ByteOrder::Big),
+        }
+    }
+}
+
+/// Error between a source value and its decoded value. Non-finite values
+/// must be reproduced exactly, NaNs down to their bits (`same_bits`), and are
+/// infinitely wrong otherwise so that no error limit accepts them.
+pub(crate) fn float_diff(src: f64, targ: f64, same_bits: bool) -> f64 {
+    if src.is_finite() && targ.is_finite() {
+        (src - targ).abs()
+    } else if same_bits || src == targ {
+        0.0
+    } else {
+        f64::INFINITY
+    }
 }
 
 macro_rules! diff_int {
     ($ty:ty, $src:expr, $targ:expr) => {{
         const N: usize = std::mem::size_of::<$ty>();
-        #[allow(clippy::modulo_one)]
         if $src.len() % N != 0 {
             return None;
         }
@@ -96,36 +143,78 @@ macro_rules! diff_int {
             |prev, (src, targ)| {
                 let src = <$ty>::from_le_bytes(src.try_into().unwrap());
                 let targ = <$ty>::from_le_bytes(targ.try_into().unwrap());
-                prev.max(match targ.cmp(&src) {
-                    Ordering::Equal => return prev,
-                    Ordering::Less => (src - targ) as f64,
-                    Ordering::Greater => (targ - src) as f64,
-                })
+                prev.max(src.abs_diff(targ) as f64)
             },
         ))
     }};
+}
 
-    (unsigned, $ty:ty, $src:expr, $targ:expr) => {{
+macro_rules! diff_complex {
+    ($ty:ty, $src:expr, $targ:expr) => {{
         const N: usize = std::mem::size_of::<$ty>();
-        #[allow(clippy::modulo_one)]
-        if $src.len() % N != 0 {
+        if $src.len() % (2 * N) != 0 {
             return None;
         }
-        Some($src.chunks_exact(N).zip($targ.chunks_exact(N)).fold(
-            f64::default(),
-            |prev, (src, targ)| {
-                let src = <$ty>::from_le_bytes(src.try_into().unwrap());
-                let targ = <$ty>::from_le_bytes(targ.try_into().unwrap());
-                prev.max(match targ.cmp(&src) {
-                    Ordering::Equal => return prev,
-                    Ordering::Less => (src - targ) as f64,
-                    Ordering::Greater => (targ - src) as f64,
-                })
-            },
-        ))
+        let mut err = f64::default();
+        for (src, targ) in $src.chunks_exact(2 * N).zip($targ.chunks_exact(2 * N)) {
+            let v = |b: &[u8]| f64::from(<$ty>::from_le_bytes(b.try_into().unwrap()));
+            let re = float_diff(v(&src[..N]), v(&targ[..N]), src[..N] == targ[..N]);
+            let im = float_diff(v(&src[N..]), v(&targ[N..]), src[N..] == targ[N..]);
+            err = err.max(re.hypot(im));
+        }
+        Some(err)
+    }};
+}
+
+macro_rules! diff_nibble {
+    ($signed:expr, $src:expr, $targ:expr) => {{
+        let v = |b: u8| {
+            if $signed {
+                f64::from((b << 4) as i8 >> 4)
+            } else {
+                f64::from(b & 0xf)
+            }
+        };
+        Some(
+            $src.iter()
+                .zip($targ.iter())
+                .fold(f64::default(), |prev, (&src, &targ)| {
+                    prev.max((v(src) - v(targ)).abs())
+                        .max((v(src >> 4) - v(targ >> 4)).abs())
+                }),
+        )
+    }};
+}
+
+macro_rules! diff_f8 {
+    ($decode:expr, $src:expr, $targ:expr) => {{
+        Some(
+            $src.iter()
+                .zip($targ.iter())
+                .fold(f64::default(), |prev, (&src, &targ)| {
+                    prev.max(float_diff($decode(src), $decode(targ), src == targ))
+                }),
+        )
     }};
 }
 
+/// Decode an OCP FP8 E4M3 value, which has no infinities.
+fn f8e4m3_to_f64(b: u8) -> f64 {
+    let sign = if b & 0x80 != 0 { -1.0 } else { 1.0 };
+    let exp = i32::from((b >> 3) & 0xf);
+    let man = f64::from(b & 0x7);
+    sign * match exp {
+        0xf if man == 7.0 => f64::NAN,
+        0 => man / 8.0 * 2f64.powi(-6),
+        _ => (1.0 + man / 8.0) * 2f64.powi(exp - 7),
+    }
+}
+
+/// Decode an OCP FP8 E5M2 value, the upper half of an IEEE half float.
+fn f8e5m2_to_f64(b: u8) -> f64 {
+    half::f16::from_bits(u16::from(b) << 8).into()
+}
+
 impl DataType {
     pub fn max_difference(&self, src: &[u8], targ: &[u8]) -> Option<f64> {
         if src.len() != targ.len() {
@@ -134,6 +223,7 @@ impl DataType {
         if src.is_empty() {
             return Some(0.0);
         }
+        let whole = |n: usize| src.len().is_multiple_of(n);
 
         match self {
             DataType::Byte => {
@@ -156,21 +246,58 @@ impl DataType {
                     .sum::<u32>() as f64;
                 Some(err)
             }
-            DataType::Float32 => diff_float!(f32, src, targ),
-            DataType::Float64 => diff_float!(f64, src, targ),
-            DataType::Float16 => diff_float!(half::f16, src, targ),
-            DataType::Bfloat16 => diff_float!(half::bf16, src, targ),
-            DataType::Int8 => diff_int!(i8, src, targ),
-            DataType::Uint8 => diff_int!(unsigned, u8, src, targ),
-            DataType::Int16 => diff_int!(i16, src, targ),
-            DataType::Uint16 => diff_int!(unsigned, u16, src, targ),
-            DataType::Int32 => diff_int!(i32, src, targ),
-            DataType::Uint32 => diff_int!(unsigned, u32, src, targ),
+            DataType::Float32 => whole(4).then(|| simd::max_diff_f32(src, targ)),
+            DataType::Float64 => whole(8).then(|| simd::max_diff_f64(src, targ)),
+            DataType::Float16 => whole(2).then(|| simd::max_diff_f16(src, targ)),
+            DataType::Bfloat16 => whole(2).then(|| simd::max_diff_bf16(src, targ)),
+            DataType::Int8 => Some(simd::max_diff_i8(src, targ)),
+            DataType::Uint8 => Some(simd::max_diff_u8(src, targ)),
+            DataType::Int16 => whole(2).then(|| simd::max_diff_i16(src, targ)),
+            DataType::Uint16 => whole(2).then(|| simd::max_diff_u16(src, targ)),
+            DataType::Int32 => whole(4).then(|| simd::max_diff_i32(src, targ)),
+            DataType::Uint32 => whole(4).then(|| simd::max_diff_u32(src, targ)),
             DataType::Int64 => diff_int!(i64, src, targ),
-            DataType::Uint64 => diff_int!(unsigned, u64, src, targ),
+            DataType::Uint64 => diff_int!(u64, src, targ),
+            DataType::Bool => Some(
+                if src.iter().zip(targ).all(|(&s, &t)| (s != 0) == (t != 0)) {
+                    0.0
+                } else {
+                    1.0
+                },
+            ),
+            DataType::Complex64 => diff_complex!(f32, src, targ),
+            DataType::Complex128 => diff_complex!(f64, src, targ),
+            DataType::Int4 => diff_nibble!(true, src, targ),
+            DataType::Uint4 => diff_nibble!(false, src, targ),
+            DataType::Float8E4M3 => diff_f8!(f8e4m3_to_f64, src, targ),
+            DataType::Float8E5M2 => diff_f8!(f8e5m2_to_f64, src, targ),
+        }
+    }
+
+    /// Bits per element.
+    pub fn bit_len(&self) -> usize {
+        match self {
+            DataType::Int4 | DataType::Uint4 => 4,
+            _ => self.byte_len() * 8,
         }
     }
 
+    /// Bytes holding `n` elements, with packed types padded to a whole byte.
+    pub fn size_of(&self, n: usize) -> usize {
+        (n * self.bit_len()).div_ceil(8)
+    }
+
+    /// Width of the scalars whose bytes are reordered for non-native byte
+    /// order, e.g. each half of a complex number.
+    pub fn word_len(&self) -> usize {
+        match self {
+            DataType::Complex64 => 4,
+            DataType::Complex128 => 8,
+            _ => self.byte_len(),
+        }
+    }
+
+    /// Bytes per element, rounded up to one for packed sub-byte types.
     pub fn byte_len(&self) -> usize {
         match self {
             DataType::Byte => 1,
@@ -186,6 +313,13 @@ impl DataType {
             DataType::Uint32 => 4,
             DataType::Int64 => 8,
             DataType::Uint64 => 8,
+            DataType::Bool => 1,
+            DataType::Complex64 => 8,
+            DataType::Complex128 => 16,
+            DataType::Int4 => 1,
+            DataType::Uint4 => 1,
+            DataType::Float8E4M3 => 1,
+            DataType::Float8E5M2 => 1,
         }
     }
 }
@@ -243,6 +377,39 @@ mod tests {
         assert_eq!(DataType::Float64.max_difference(&src, &targ), Some(3.0));
     }
 
+    #[test]
+    fn special_diff() {
+        let write = |f: &[f32]| f.iter().flat_map(|f| f.to_le_bytes()).collect::<Vec<_>>();
+        let src = write(&[1.0, f32::NAN, f32::INFINITY, f32::NEG_INFINITY]);
+        assert_eq!(DataType::Float32.max_difference(&src, &src), Some(0.0));
+        let targ = write(&[1.5, f32::NAN, f32::INFINITY, f32::NEG_INFINITY]);
+        assert_eq!(DataType::Float32.max_difference(&src, &targ), Some(0.5));
+        for targ in [
+            write(&[1.0, 0.0, f32::INFINITY, f32::NEG_INFINITY]),
+            write(&[1.0, f32::NAN, f32::MAX, f32::NEG_INFINITY]),
+            write(&[1.0, f32::NAN, f32::INFINITY, f32::INFINITY]),
+            write(&[f32::NAN, f32::NAN, f32::INFINITY, f32::NEG_INFINITY]),
+            write(&[1.0, -f32::NAN, f32::INFINITY, f32::NEG_INFINITY]),
+        ] {
+            assert_eq!(
+                DataType::Float32.max_difference(&src, &targ),
+                Some(f64::INFINITY)
+            );
+        }
+
+        let src = [half::bf16::NAN, half::bf16::ONE]
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(DataType::Bfloat16.max_difference(&src, &src), Some(0.0));
+        let c = write(&[f32::NAN, 1.0]);
+        assert_eq!(DataType::Complex64.max_difference(&c, &c), Some(0.0));
+        assert_eq!(
+            DataType::Complex64.max_difference(&c, &write(&[0.0, 1.0])),
+            Some(f64::INFINITY)
+        );
+    }
+
     #[test]
     fn i64_diff() {
         let write = |f: &[i64]| {
@@ -259,6 +426,18 @@ mod tests {
         assert_eq!(DataType::Int64.max_difference(&src, &targ), None);
         let targ = write(&[1, 2, 1, 4, 5, 3]);
         assert_eq!(DataType::Int64.max_difference(&src, &targ), Some(3.0));
+        let (lo, hi) = (write(&[i64::MIN]), write(&[i64::MAX]));
+        assert_eq!(
+            DataType::Int64.max_difference(&lo, &hi),
+            Some(u64::MAX as f64)
+        );
+        let (lo, hi) = (i32::MIN.to_le_bytes(), i32::MAX.to_le_bytes());
+        assert_eq!(
+            DataType::Int32.max_difference(&lo, &hi),
+            Some(u32::MAX as f64)
+        );
+        assert_eq!(DataType::Int32.max_difference(&lo, &hi[..3]), None);
+        assert_eq!(DataType::Int16.max_difference(&lo[..3], &hi[..3]), None);
     }
 
     #[test]
@@ -278,4 +457,43 @@ mod tests {
         let targ = write(&[1, 2, 1, 4, 5, 3]);
         assert_eq!(DataType::Uint64.max_difference(&src, &targ), Some(3.0));
     }
+
+    #[test]
+    fn new_types_diff() {
+        assert_eq!(
+            DataType::Bool.max_difference(&[0, 1, 2], &[0, 5, 1]),
+            Some(0.0)
+        );
+        assert_eq!(DataType::Bool.max_difference(&[0, 1], &[1, 1]), Some(1.0));
+
+        // low nibble -8 vs 7, high nibble 1 vs 2
+        assert_eq!(DataType::Int4.max_difference(&[0x18], &[0x27]), Some(15.0));
+        assert_eq!(DataType::Uint4.max_difference(&[0x18], &[0x27]), Some(1.0));
+        assert_eq!(DataType::Int4.size_of(5), 3);
+
+        let c = |v: &[f32]| v.iter().flat_map(|f| f.to_le_bytes()).collect::<Vec<_>>();
+        let src = c(&[1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(
+            DataType::Complex64.max_difference(&src, &c(&[1.0, 1.0, 3.0, 4.0])),
+            Some(5.0)
+        );
+        assert_eq!(
+            DataType::Complex64.max_difference(&src, &c(&[1.0, 1.0])),
+            None
+        );
+
+        assert_eq!(f8e4m3_to_f64(0x7e), 448.0);
+        assert_eq!(f8e4m3_to_f64(0x01), 2f64.powi(-9));
+        assert!(f8e4m3_to_f64(0xff).is_nan());
+        assert_eq!(f8e5m2_to_f64(0x7b), 57344.0);
+        assert_eq!(f8e5m2_to_f64(0x7c), f64::INFINITY);
+        assert_eq!(
+            DataType::Float8E4M3.max_difference(&[0x38, 0x40], &[0x38, 0x38]),
+            Some(1.0)
+        );
+        assert_eq!(
+            DataType::Float8E5M2.max_difference(&[0x7f], &[0x7f]),
+            Some(0.0)
+        );
+    }
 }
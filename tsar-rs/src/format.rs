@@ -0,0 +1,65 @@
+use crate::{
+    pb,
+    result::{Error, Result},
+};
+
+/// Archive format version written by this crate, as `(major, minor)`.
+/// Readers reject a newer major version and read a newer minor version
+/// best-effort with a logged warning, ignoring what they do not know.
+pub const FORMAT_VERSION: (u32, u32) = (1, 0);
+
+/// Blobs streamed as independently compressed blocks.
+const BLOB_BLOCKS: &str = "blob-blocks";
+
+/// Features this reader understands.
+const FEATURES: &[&str] = &[BLOB_BLOCKS];
+
+/// Record the format version and the features `meta` relies on.
+pub fn stamp(meta: &mut pb::Bundle) {
+    (meta.format_major, meta.format_minor) = FORMAT_VERSION;
+    meta.required_features.clear();
+    if meta.blobs.iter().any(|b| !b.blocks.is_empty()) {
+        meta.required_features.push(BLOB_BLOCKS.into());
+    }
+}
+
+/// Reject archives this reader would misread.
+pub fn check(meta: &pb::Bundle) -> Result<()> {
+    if meta.format_major > FORMAT_VERSION.0 {
+        return Err(Error::Unsupported(format!(
+            "archive format {}.{}, this reader supports up to {}.x",
+            meta.format_major, meta.format_minor, FORMAT_VERSION.0
+        )));
+    }
+    if meta.format_major == FORMAT_VERSION.0 && meta.format_minor > FORMAT_VERSION.1 {
+        log::warn!(
+            "archive format {}.{} is newer than {}.{}, reading what this reader knows",
+            meta.format_major,
+            meta.format_minor,
+            FORMAT_VERSION.0,
+            FORMAT_VERSION.1
+        );
+    }
+    let unknown = meta
+        .required_features
+        .iter()
+        .filter(|f| !FEATURES.contains(&f.as_str()))
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        return Err(Error::Unsupported(format!("archive features {unknown:?}")));
+    }
+    for b in &meta.blobs {
+        let mut stages = b
+            .compression_stages
+            .iter()
+            .chain(b.blocks.iter().flat_map(|k| &k.compression_stages));
+        if let Some(s) = stages.find(|s| s.enum_value().is_err()) {
+            return Err(Error::Unsupported(format!(
+                "compression stage {} of blob {:?}",
+                s.value(),
+                b.name
+            )));
+        }
+    }
+    Ok(())
+}
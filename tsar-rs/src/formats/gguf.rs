@@ -0,0 +1,464 @@
+//! GGUF model files, as used by llama.cpp.
+//!
+//! The file is split into blobs targeting it: `{name}[{idx}]` holds each
+//! tensor and `{name}[gap@{offset}]` the header and any non-zero padding,
+//! or all padding with [`Builder::set_contiguous_targets`], so extracting
+//! the archive gives back the same bytes. Plain tensors keep their data
+//! type while quantized block formats are stored as `Byte` blobs, and
+//! every tensor blob records its ggml type under `gguf.type`.
+//! Scalar key/value entries are also copied into the archive metadata, with
+//! `u64` values above `i64::MAX` as decimal strings. Array entries, such as
+//! the tokenizer vocabulary, go to `.{name}.arrays.json` as one JSON object,
+//! and `.{name}.json` maps tensor names to blob names.
+
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+
+use super::{json_string, tensor_map};
+use crate::{
+    result::{Error, Result},
+    BlobWriteOption, Builder, DataType, Metadata, MetadataValue, Region,
+};
+
+const MAGIC: &[u8; 4] = b"GGUF";
+const DEFAULT_ALIGNMENT: u64 = 32;
+/// Most arrays a metadata value may nest inside one another.
+const MAX_ARRAY_DEPTH: usize = 16;
+
+fn malformed() -> Error {
+    Error::Corrupt("malformed GGUF file")
+}
+
+/// Tensor info from the GGUF header; `dims` are innermost first.
+struct TensorInfo {
+    name: String,
+    dims: Vec<u64>,
+    ggml_type: u32,
+    offset: u64,
+}
+
+/// Data type of unquantized ggml types.
+fn plain_type(ggml_type: u32) -> Option<DataType> {
+    Some(match ggml_type {
+        0 => DataType::Float32,
+        1 => DataType::Float16,
+        24 => DataType::Int8,
+        25 => DataType::Int16,
+        26 => DataType::Int32,
+        27 => DataType::Int64,
+        28 => DataType::Float64,
+        30 => DataType::Bfloat16,
+        _ => return None,
+    })
+}
+
+/// Elements per block and bytes per block of quantized ggml types.
+fn block_size(ggml_type: u32) -> Option<(u64, u64)> {
+    Some(match ggml_type {
+        2 => (32, 18),    // Q4_0
+        3 => (32, 20),    // Q4_1
+        6 => (32, 22),    // Q5_0
+        7 => (32, 24),    // Q5_1
+        8 => (32, 34),    // Q8_0
+        9 => (32, 36),    // Q8_1
+        10 => (256, 84),  // Q2_K
+        11 => (256, 110), // Q3_K
+        12 => (256, 144), // Q4_K
+        13 => (256, 176), // Q5_K
+        14 => (256, 210), // Q6_K
+        15 => (256, 292), // Q8_K
+        16 => (256, 66),  // IQ2_XXS
+        17 => (256, 74),  // IQ2_XS
+        18 => (256, 98),  // IQ3_XXS
+        19 => (256, 50),  // IQ1_S
+        20 => (32, 18),   // IQ4_NL
+        21 => (256, 110), // IQ3_S
+        22 => (256, 82),  // IQ2_S
+        23 => (256, 136), // IQ4_XS
+        29 => (256, 56),  // IQ1_M
+        34 => (256, 54),  // TQ1_0
+        35 => (256, 66),  // TQ2_0
+        _ => return None,
+    })
+}
+
+fn read_u32(r: &mut impl Read) -> Result<u32> {
+    let mut b = [0; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+fn read_u64(r: &mut impl Read) -> Result<u64> {
+    let mut b = [0; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+fn read_string(r: &mut impl Read) -> Result<String> {
+    let len = read_u64(r)?;
+    let mut s = Vec::new();
+    r.take(len).read_to_end(&mut s)?;
+    if s.len() as u64 != len {
+        return Err(malformed());
+    }
+    String::from_utf8(s).map_err(|_| malformed())
+}
+
+/// Byte size of fixed-size value types.
+fn scalar_len(ty: u32) -> Option<u64> {
+    Some(match ty {
+        0 | 1 | 7 => 1,
+        2 | 3 => 2,
+        4..=6 => 4,
+        10..=12 => 8,
+        _ => return None,
+    })
+}
+
+/// Read a fixed-size value of type `ty` as its little-endian bits.
+fn read_bits(r: &mut impl Read, ty: u32) -> Result<u64> {
+    let len = scalar_len(ty).ok_or_else(malformed)? as usize;
+    let mut b = [0; 8];
+    r.read_exact(&mut b[..len])?;
+    Ok(u64::from_le_bytes(b))
+}
+
+/// Integer types other than `u64`, sign-extended.
+fn int(ty: u32, u: u64) -> i64 {
+    match ty {
+        1 => i64::from(u as u8 as i8),
+        3 => i64::from(u as u16 as i16),
+        5 => i64::from(u as u32 as i32),
+        _ => u as i64,
+    }
+}
+
+/// Read a scalar or string value of type `ty`. A `u64` above `i64::MAX`
+/// becomes its decimal string, since integer metadata is signed.
+fn read_value(r: &mut impl Read, ty: u32) -> Result<MetadataValue> {
+    if ty == 8 {
+        return Ok(MetadataValue::String(read_string(r)?));
+    }
+    let u = read_bits(r, ty)?;
+    Ok(match ty {
+        6 => MetadataValue::Float(f64::from(f32::from_bits(u as u32))),
+        12 => MetadataValue::Float(f64::from_bits(u)),
+        7 => MetadataValue::Bool(u != 0),
+        10 => i64::try_from(u)
+            .map_or_else(|_| MetadataValue::String(u.to_string()), MetadataValue::Int),
+        _ => MetadataValue::Int(int(ty, u)),
+    })
+}
+
+/// Append the value of type `ty`, inside `depth` arrays, to `out` as JSON.
+/// Non-finite floats become `null`.
+fn read_json(r: &mut impl Read, ty: u32, depth: usize, out: &mut String) -> Result<()> {
+    match ty {
+        8 => out.push_str(&json_string(&read_string(r)?)),
+        9 => {
+            if depth >= MAX_ARRAY_DEPTH {
+                return Err(malformed());
+            }
+            let elem = read_u32(r)?;
+            let n = read_u64(r)?;
+            out.push('[');
+            for i in 0..n {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                read_json(r, elem, depth + 1, out)?;
+            }
+            out.push(']');
+        }
+        _ => {
+            let u = read_bits(r, ty)?;
+            match ty {
+                6 => match f32::from_bits(u as u32) {
+                    f if f.is_finite() => out.push_str(&format!("{f:?}")),
+                    _ => out.push_str("null"),
+                },
+                12 => match f64::from_bits(u) {
+                    f if f.is_finite() => out.push_str(&format!("{f:?}")),
+                    _ => out.push_str("null"),
+                },
+                7 => out.push_str(if u != 0 { "true" } else { "false" }),
+                10 => out.push_str(&u.to_string()),
+                _ => out.push_str(&int(ty, u).to_string()),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Add the GGUF file in `reader`, to be extracted as `name`.
+pub fn add_gguf<W: Write + Seek>(
+    builder: &mut Builder<W>,
+    name: &str,
+    reader: impl Read + Seek,
+    opt: BlobWriteOption,
+) -> Result<()> {
+    let mut r = BufReader::new(reader);
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(malformed());
+    }
+    match read_u32(&mut r)? {
+        2 | 3 => {}
+        v => return Err(Error::Unsupported(format!("GGUF version {v}"))),
+    }
+    let n_tensors = read_u64(&mut r)?;
+    let n_kv = read_u64(&mut r)?;
+
+    let mut alignment = DEFAULT_ALIGNMENT;
+    let mut arrays = Vec::new();
+    for _ in 0..n_kv {
+        let key = read_string(&mut r)?;
+        let ty = read_u32(&mut r)?;
+        if ty == 9 {
+            let mut json = json_string(&key) + ": ";
+            read_json(&mut r, ty, 0, &mut json)?;
+            arrays.push(json);
+            continue;
+        }
+        let v = read_value(&mut r, ty)?;
+        if key == "general.alignment" {
+            match v {
+                MetadataValue::Int(a) if a > 0 => alignment = a as u64,
+                _ => return Err(malformed()),
+            }
+        }
+        builder.set_metadata(key, v);
+    }
+
+    let mut tensors = Vec::new();
+    for _ in 0..n_tensors {
+        let name = read_string(&mut r)?;
+        let n_dims = read_u32(&mut r)?;
+        if n_dims > 8 {
+            return Err(malformed());
+        }
+        let dims = (0..n_dims)
+            .map(|_| read_u64(&mut r))
+            .collect::<Result<Vec<_>>>()?;
+        let ggml_type = read_u32(&mut r)?;
+        let offset = read_u64(&mut r)?;
+        tensors.push(TensorInfo {
+            name,
+            dims,
+            ggml_type,
+            offset,
+        });
+    }
+    let data_start = r.stream_position()?.next_multiple_of(alignment);
+    let file_len = r.seek(SeekFrom::End(0))?;
+    if data_start > file_len {
+        return Err(malformed());
+    }
+
+    // tensor extents, in file order
+    tensors.sort_by_key(|t| t.offset);
+    let mut extents = Vec::with_capacity(tensors.len());
+    for (i, t) in tensors.iter().enumerate() {
+        let start = data_start.checked_add(t.offset).ok_or_else(malformed)?;
+        let n = t
+            .dims
+            .iter()
+            .try_fold(1u64, |a, &d| a.checked_mul(d))
+            .ok_or_else(malformed)?;
+        let len = match (plain_type(t.ggml_type), block_size(t.ggml_type)) {
+            (Some(dt), _) => n.checked_mul(dt.byte_len() as u64),
+            (_, Some((block, bytes))) => n.is_multiple_of(block).then(|| n / block * bytes),
+            // unknown block format, runs up to the next tensor
+            _ => tensors
+                .get(i + 1)
+                .map_or(file_len, |t| data_start.saturating_add(t.offset))
+                .checked_sub(start),
+        };
+        let end = len.and_then(|l| start.checked_add(l));
+        match end {
+            Some(end) if end <= file_len => extents.push((start, end)),
+            _ => return Err(malformed()),
+        }
+    }
+
+    let mut regions = Vec::with_capacity(tensors.len());
+    let mut map = Vec::with_capacity(tensors.len());
+    for (idx, (t, &(start, end))) in tensors.iter().zip(&extents).enumerate() {
+        let blob = format!("{name}[{idx}]");
+        let (data_type, shape) = match plain_type(t.ggml_type) {
+            Some(dt) => (dt, t.dims.iter().rev().map(|&d| d as usize).collect()),
+            None => (DataType::Byte, vec![(end - start) as usize]),
+        };
+        let mut metadata = Metadata::new();
+        metadata.insert("gguf.type".into(), MetadataValue::Int(t.ggml_type.into()));
+        regions.push(Region {
+            name: blob.clone(),
+            offset: start,
+            len: end - start,
+            data_type,
+            shape,
+            metadata,
+        });
+        map.push((t.name.as_str(), blob));
+    }
+    builder.add_regions(name, &mut r, regions, opt)?;
+    builder.add_file(format!(".{name}.json"), tensor_map(map).as_bytes())?;
+    let arrays = format!("{{{}}}", arrays.join(", "));
+    builder.add_file(format!(".{name}.arrays.json"), arrays.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use super::*;
+    use crate::{Archive, ExtractOption};
+
+    fn string(v: &mut Vec<u8>, s: &str) {
+        v.extend((s.len() as u64).to_le_bytes());
+        v.extend(s.as_bytes());
+    }
+
+    /// A GGUF file with an f32, a Q8_0, an f16 and an empty tensor.
+    fn gguf(padding: u8) -> Vec<u8> {
+        let mut v = MAGIC.to_vec();
+        v.extend(3u32.to_le_bytes());
+        v.extend(4u64.to_le_bytes());
+        v.extend(5u64.to_le_bytes());
+        string(&mut v, "general.architecture");
+        v.extend(8u32.to_le_bytes());
+        string(&mut v, "llama");
+        string(&mut v, "general.alignment");
+        v.extend(4u32.to_le_bytes());
+        v.extend(64u32.to_le_bytes());
+        string(&mut v, "tokenizer.ggml.scores");
+        v.extend(9u32.to_le_bytes());
+        v.extend(6u32.to_le_bytes());
+        v.extend(2u64.to_le_bytes());
+        v.extend(0.5f32.to_le_bytes());
+        v.extend(f32::NAN.to_le_bytes());
+        string(&mut v, "tokenizer.ggml.tokens");
+        v.extend(9u32.to_le_bytes());
+        v.extend(8u32.to_le_bytes());
+        v.extend(2u64.to_le_bytes());
+        string(&mut v, "<s>");
+        string(&mut v, "\"");
+        string(&mut v, "test.seed");
+        v.extend(10u32.to_le_bytes());
+        v.extend(u64::MAX.to_le_bytes());
+
+        let tensors: [(&str, &[u64], u32, u64); 4] = [
+            ("w", &[4, 3], 0, 0),
+            ("q", &[32], 8, 64),
+            ("h", &[8], 1, 128),
+            ("z", &[0], 0, 128),
+        ];
+        for (name, dims, ty, offset) in tensors {
+            string(&mut v, name);
+            v.extend((dims.len() as u32).to_le_bytes());
+            dims.iter().for_each(|d| v.extend(d.to_le_bytes()));
+            v.extend(ty.to_le_bytes());
+            v.extend(offset.to_le_bytes());
+        }
+        v.resize(v.len().next_multiple_of(64), padding);
+        let data = v.len();
+        v.extend((0..12).flat_map(|i| (i as f32 * 0.5).to_le_bytes()));
+        v.resize(data + 64, padding);
+        v.extend((0..34).map(|i| i as u8));
+        v.resize(data + 128, padding);
+        v.extend((0..8).flat_map(|i| half::f16::from_f32(i as f32).to_le_bytes()));
+        v.extend([padding; 3]);
+        v
+    }
+
+    #[test]
+    fn roundtrip() {
+        for padding in [0, 0xaa] {
+            let src = gguf(padding);
+            let mut buf = Cursor::new(Vec::new());
+            let mut b = Builder::new(&mut buf);
+            b.set_contiguous_targets(true);
+            add_gguf(
+                &mut b,
+                "model.gguf",
+                Cursor::new(&src),
+                BlobWriteOption::default(),
+            )
+            .unwrap();
+            b.finish().unwrap();
+
+            let mut a = Archive::new(Cursor::new(buf.into_inner())).unwrap();
+            assert_eq!(
+                a.metadata()["general.architecture"],
+                MetadataValue::String("llama".into())
+            );
+            let w = a.blob_by_name("model.gguf[0]").unwrap();
+            assert_eq!(w.data_type(), Some(DataType::Float32));
+            assert_eq!(w.shape().into_iter().collect::<Vec<_>>(), [3, 4]);
+            let q = a.blob_by_name("model.gguf[1]").unwrap();
+            assert_eq!(q.data_type(), Some(DataType::Byte));
+            assert_eq!(q.metadata()["gguf.type"], MetadataValue::Int(8));
+            let mut map = String::new();
+            a.file_by_name(".model.gguf.json")
+                .unwrap()
+                .read_to_string(&mut map)
+                .unwrap();
+            assert_eq!(
+                map,
+                r#"{"blobs": {"w": "model.gguf[0]", "q": "model.gguf[1]", "h": "model.gguf[2]", "z": "model.gguf[3]"}}"#
+            );
+            let mut arrays = String::new();
+            a.file_by_name(".model.gguf.arrays.json")
+                .unwrap()
+                .read_to_string(&mut arrays)
+                .unwrap();
+            assert_eq!(
+                arrays,
+                r#"{"tokenizer.ggml.scores": [0.5, null], "tokenizer.ggml.tokens": ["<s>", "\""]}"#
+            );
+            assert_eq!(
+                a.metadata()["test.seed"],
+                MetadataValue::String(u64::MAX.to_string())
+            );
+            let z = a.blob_by_name("model.gguf[3]").unwrap();
+            assert_eq!(z.byte_len(), Some(0));
+
+            let dir =
+                std::env::temp_dir().join(format!("tsar-gguf-{padding}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            a.extract_to(&dir, ExtractOption::default()).unwrap();
+            assert!(fs::read(dir.join("model.gguf")).unwrap() == src);
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn malformed_input() {
+        let src = gguf(0);
+        for len in [3, 20, 100, src.len() - 20] {
+            let mut b = Builder::new(Cursor::new(Vec::new()));
+            assert!(add_gguf(
+                &mut b,
+                "m",
+                Cursor::new(&src[..len]),
+                BlobWriteOption::default()
+            )
+            .is_err());
+        }
+
+        // arrays of arrays nested deeper than any model uses
+        let mut v = src[..24].to_vec();
+        v[16..24].copy_from_slice(&1u64.to_le_bytes());
+        string(&mut v, "k");
+        v.extend(9u32.to_le_bytes());
+        for _ in 0..1 << 16 {
+            v.extend(9u32.to_le_bytes());
+            v.extend(1u64.to_le_bytes());
+        }
+        let mut b = Builder::new(Cursor::new(Vec::new()));
+        assert!(matches!(
+            add_gguf(&mut b, "m", Cursor::new(&v), BlobWriteOption::default()),
+            Err(Error::Corrupt(_))
+        ));
+    }
+}
@@ -0,0 +1,33 @@
+//! Converters between tsar archives and other tensor file formats.
+
+pub mod gguf;
+pub mod npy;
+pub mod onnx;
+mod pickle;
+pub mod tflite;
+pub mod torch;
+
+/// JSON object mapping tensor names to the blobs holding them, stored next
+/// to a packed model as `.{name}.json`.
+fn tensor_map(blobs: impl IntoIterator<Item = (impl AsRef<str>, String)>) -> String {
+    let entries = blobs
+        .into_iter()
+        .map(|(t, b)| format!("{}: {}", json_string(t.as_ref()), json_string(&b)))
+        .collect::<Vec<_>>();
+    format!("{{\"blobs\": {{{}}}}}", entries.join(", "))
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,434 @@
+//! NumPy `.npy` arrays and `.npz` archives of them.
+//!
+//! Fortran-ordered arrays are stored as is, with their NumPy shape, and
+//! flagged by the [`FORTRAN_ORDER`] blob metadata entry so that exporting
+//! them gives back the same file.
+
+use std::io::{self, Read, Seek, Write};
+
+use zip::write::SimpleFileOptions;
+
+use crate::{
+    result::{Error, Result},
+    Archive, BlobWriteOption, Builder, ByteOrder, DataType, MetadataValue,
+};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Blob metadata key set on arrays imported in Fortran order.
+pub const FORTRAN_ORDER: &str = "npy.fortran_order";
+
+/// Parsed `.npy` header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub data_type: DataType,
+    pub byte_order: ByteOrder,
+    pub fortran_order: bool,
+    pub shape: Vec<usize>,
+}
+
+fn malformed() -> Error {
+    Error::Corrupt("malformed .npy header")
+}
+
+fn descr_type(code: &str) -> Option<DataType> {
+    Some(match code {
+        "b1" => DataType::Bool,
+        "i1" => DataType::Int8,
+        "u1" => DataType::Uint8,
+        "i2" => DataType::Int16,
+        "u2" => DataType::Uint16,
+        "i4" => DataType::Int32,
+        "u4" => DataType::Uint32,
+        "i8" => DataType::Int64,
+        "u8" => DataType::Uint64,
+        "f2" => DataType::Float16,
+        "f4" => DataType::Float32,
+        "f8" => DataType::Float64,
+        "c8" => DataType::Complex64,
+        "c16" => DataType::Complex128,
+        _ => return None,
+    })
+}
+
+fn type_descr(dt: DataType) -> Option<&'static str> {
+    Some(match dt {
+        DataType::Bool => "b1",
+        DataType::Int8 => "i1",
+        DataType::Byte | DataType::Uint8 => "u1",
+        DataType::Int16 => "i2",
+        DataType::Uint16 => "u2",
+        DataType::Int32 => "i4",
+        DataType::Uint32 => "u4",
+        DataType::Int64 => "i8",
+        DataType::Uint64 => "u8",
+        DataType::Float16 => "f2",
+        DataType::Float32 => "f4",
+        DataType::Float64 => "f8",
+        DataType::Complex64 => "c8",
+        DataType::Complex128 => "c16",
+        DataType::Bfloat16
+        | DataType::Int4
+        | DataType::Uint4
+        | DataType::Float8E4M3
+        | DataType::Float8E5M2 => return None,
+    })
+}
+
+/// Values of the Python dict literal making up a header.
+enum Value {
+    Str(String),
+    Bool(bool),
+    Tuple(Vec<usize>),
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+}
+
+impl Parser<'_> {
+    fn peek(&mut self) -> Option<u8> {
+        while let [b' ' | b'\t' | b'\n', rest @ ..] = self.s {
+            self.s = rest;
+        }
+        self.s.first().copied()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        let hit = self.peek() == Some(c);
+        if hit {
+            self.s = &self.s[1..];
+        }
+        hit
+    }
+
+    fn expect(&mut self, c: u8) -> Result<()> {
+        self.eat(c).then_some(()).ok_or_else(malformed)
+    }
+
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> &[u8] {
+        let n = self.s.iter().position(|&c| !f(c)).unwrap_or(self.s.len());
+        let (t, rest) = self.s.split_at(n);
+        self.s = rest;
+        t
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let q = self.peek().filter(|&q| q == b'\'' || q == b'"');
+        let q = q.ok_or_else(malformed)?;
+        self.s = &self.s[1..];
+        let t = self.take_while(|c| c != q).to_vec();
+        self.expect(q)?;
+        String::from_utf8(t).map_err(|_| malformed())
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some(b'\'' | b'"') => Ok(Value::Str(self.string()?)),
+            Some(b'(') => {
+                self.s = &self.s[1..];
+                let mut dims = vec![];
+                while !self.eat(b')') {
+                    self.peek();
+                    let d = self.take_while(|c| c.is_ascii_digit());
+                    let d = std::str::from_utf8(d).map_err(|_| malformed())?;
+                    dims.push(d.parse().map_err(|_| malformed())?);
+                    if !self.eat(b',') {
+                        self.expect(b')')?;
+                        break;
+                    }
+                }
+                Ok(Value::Tuple(dims))
+            }
+            Some(b'[') => Err(Error::Unsupported("structured .npy dtype".into())),
+            _ => match self.take_while(|c| c.is_ascii_alphabetic()) {
+                b"True" => Ok(Value::Bool(true)),
+                b"False" => Ok(Value::Bool(false)),
+                _ => Err(malformed()),
+            },
+        }
+    }
+}
+
+fn parse_header(s: &[u8]) -> Result<Header> {
+    let mut p = Parser { s };
+    let (mut descr, mut fortran_order, mut shape) = (None, None, None);
+    p.expect(b'{')?;
+    while !p.eat(b'}') {
+        let key = p.string()?;
+        p.expect(b':')?;
+        match (key.as_str(), p.value()?) {
+            ("descr", Value::Str(v)) => descr = Some(v),
+            ("fortran_order", Value::Bool(v)) => fortran_order = Some(v),
+            ("shape", Value::Tuple(v)) => shape = Some(v),
+            _ => return Err(malformed()),
+        }
+        if !p.eat(b',') {
+            p.expect(b'}')?;
+            break;
+        }
+    }
+
+    let descr = descr.ok_or_else(malformed)?;
+    let (order, code) = descr.split_at_checked(1).ok_or_else(malformed)?;
+    let byte_order = match order {
+        "<" | "|" => ByteOrder::Little,
+        ">" => ByteOrder::Big,
+        "=" => ByteOrder::native(),
+        _ => return Err(malformed()),
+    };
+    let data_type =
+        descr_type(code).ok_or_else(|| Error::Unsupported(format!(".npy dtype {descr:?}")))?;
+    let shape: Vec<usize> = shape.ok_or_else(malformed)?;
+    // nothing is allocated from the shape, but its size must still be sane
+    shape
+        .iter()
+        .try_fold(data_type.byte_len(), |n, &d| n.checked_mul(d))
+        .filter(|&n| n <= isize::MAX as usize)
+        .ok_or(Error::Corrupt(".npy shape overflows"))?;
+    Ok(Header {
+        data_type,
+        byte_order,
+        fortran_order: fortran_order.ok_or_else(malformed)?,
+        shape,
+    })
+}
+
+/// Read the header of a `.npy` file, leaving `r` at the start of the data.
+pub fn read_header(r: &mut impl Read) -> Result<Header> {
+    let mut pre = [0; 8];
+    r.read_exact(&mut pre)?;
+    if &pre[..6] != MAGIC {
+        return Err(malformed());
+    }
+    let len = match pre[6] {
+        1 => {
+            let mut l = [0; 2];
+            r.read_exact(&mut l)?;
+            u16::from_le_bytes(l) as usize
+        }
+        2 | 3 => {
+            let mut l = [0; 4];
+            r.read_exact(&mut l)?;
+            u32::from_le_bytes(l) as usize
+        }
+        v => return Err(Error::Unsupported(format!(".npy version {v}"))),
+    };
+    let mut h = Vec::new();
+    r.take(len as u64).read_to_end(&mut h)?;
+    if h.len() != len {
+        return Err(malformed());
+    }
+    parse_header(&h)
+}
+
+/// Write a version 1.0 header, or 2.0 if it does not fit.
+pub fn write_header(w: &mut impl Write, h: &Header) -> Result<()> {
+    let code = type_descr(h.data_type)
+        .ok_or_else(|| Error::Unsupported(format!("{:?} in .npy", h.data_type)))?;
+    let order = match (h.data_type.byte_len(), h.byte_order) {
+        (1, _) => '|',
+        (_, ByteOrder::Little) => '<',
+        (_, ByteOrder::Big) => '>',
+    };
+    let shape = match &h.shape[..] {
+        [d] => format!("({d},)"),
+        s => format!(
+            "({})",
+            s.iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let fortran = if h.fortran_order { "True" } else { "False" };
+    let mut dict =
+        format!("{{'descr': '{order}{code}', 'fortran_order': {fortran}, 'shape': {shape}, }}");
+
+    // pad with spaces so the data starts 64-byte aligned
+    let pre = if dict.len() + 11 <= u16::MAX as usize {
+        10
+    } else {
+        12
+    };
+    dict.extend(std::iter::repeat_n(' ', 63 - (pre + dict.len()) % 64));
+    dict.push('\n');
+    w.write_all(MAGIC)?;
+    if pre == 10 {
+        w.write_all(&[1, 0])?;
+        w.write_all(&(dict.len() as u16).to_le_bytes())?;
+    } else {
+        w.write_all(&[2, 0])?;
+        w.write_all(&(dict.len() as u32).to_le_bytes())?;
+    }
+    w.write_all(dict.as_bytes())?;
+    Ok(())
+}
+
+/// Add the `.npy` file in `reader` as blob `name`.
+pub fn add_npy<W: Write + Seek>(
+    builder: &mut Builder<W>,
+    name: impl Into<String>,
+    mut reader: impl Read,
+    mut opt: BlobWriteOption,
+) -> Result<()> {
+    let h = read_header(&mut reader)?;
+    opt.byte_order = h.byte_order;
+    if h.fortran_order {
+        opt.metadata
+            .insert(FORTRAN_ORDER.into(), MetadataValue::Bool(true));
+    }
+    builder.add_blob_from_reader(name, reader, h.data_type, &h.shape, opt)
+}
+
+/// Add every `.npy` member of the `.npz` archive in `reader`, named without
+/// the extension.
+pub fn add_npz<W: Write + Seek>(
+    builder: &mut Builder<W>,
+    reader: impl Read + Seek,
+    opt: BlobWriteOption,
+) -> Result<()> {
+    let mut z = zip::ZipArchive::new(reader)?;
+    for i in 0..z.len() {
+        let f = z.by_index(i)?;
+        let Some(name) = f.name().strip_suffix(".npy").map(str::to_owned) else {
+            continue;
+        };
+        add_npy(builder, name, f, opt.clone())?;
+    }
+    Ok(())
+}
+
+/// Write blob `name` as a `.npy` file, in the byte order it was added with.
+pub fn write_npy<R: Read + Seek>(
+    archive: &mut Archive<R>,
+    name: &str,
+    mut w: impl Write,
+) -> Result<()> {
+    let mut b = archive.blob_by_name(name)?;
+    let h = Header {
+        data_type: b
+            .data_type()
+            .ok_or_else(|| Error::Unsupported(format!("data type of blob {name:?}")))?,
+        byte_order: b.source_byte_order(),
+        fortran_order: b.metadata().get(FORTRAN_ORDER) == Some(&MetadataValue::Bool(true)),
+        shape: b.shape().into_iter().collect(),
+    };
+    write_header(&mut w, &h)?;
+    b.set_byte_order(h.byte_order)?;
+    io::copy(&mut b, &mut w)?;
+    Ok(())
+}
+
+/// Write every blob of `archive` into an uncompressed `.npz` archive, as
+/// `numpy.savez` does.
+pub fn write_npz<R: Read + Seek>(archive: &mut Archive<R>, w: impl Write + Seek) -> Result<()> {
+    let names = archive.blob_names().map(str::to_owned).collect::<Vec<_>>();
+    let mut z = zip::ZipWriter::new(w);
+    for name in names {
+        let opt = SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored)
+            .large_file(true);
+        z.start_file(format!("{name}.npy"), opt)?;
+        write_npy(archive, &name, &mut z)?;
+    }
+    z.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn npy(descr: &str, fortran: bool, shape: &str, data: &[u8]) -> Vec<u8> {
+        let f = if fortran { "True" } else { "False" };
+        let mut dict = format!("{{'descr': '{descr}', 'fortran_order': {f}, 'shape': {shape}}}");
+        dict.extend(std::iter::repeat_n(' ', 63 - (10 + dict.len()) % 64));
+        dict.push('\n');
+        let mut v = MAGIC.to_vec();
+        v.extend([1, 0]);
+        v.extend((dict.len() as u16).to_le_bytes());
+        v.extend(dict.as_bytes());
+        v.extend(data);
+        v
+    }
+
+    #[test]
+    fn header() {
+        let h = read_header(&mut &npy("<f4", false, "(2, 3)", &[])[..]).unwrap();
+        assert_eq!(h.data_type, DataType::Float32);
+        assert_eq!(h.byte_order, ByteOrder::Little);
+        assert_eq!(h.shape, [2, 3]);
+
+        for (shape, dims) in [("()", &[][..]), ("(7,)", &[7]), ("(1, 2, 3,)", &[1, 2, 3])] {
+            let h = read_header(&mut &npy(">c16", true, shape, &[])[..]).unwrap();
+            assert_eq!(h.shape, dims);
+            assert!(h.fortran_order);
+            assert_eq!(h.byte_order, ByteOrder::Big);
+
+            let mut out = vec![];
+            write_header(&mut out, &h).unwrap();
+            assert_eq!(out.len() % 64, 0);
+            assert_eq!(read_header(&mut &out[..]).unwrap(), h);
+        }
+
+        assert!(matches!(
+            read_header(&mut &npy("<V8", false, "(1,)", &[])[..]),
+            Err(Error::Unsupported(_))
+        ));
+        assert!(read_header(&mut &npy("<f4", false, "(1", &[])[..]).is_err());
+        assert!(read_header(&mut &b"\x93NUMPY\x01\x00\xff\xff{"[..]).is_err());
+        let huge = "(4294967296, 4294967296)";
+        assert!(read_header(&mut &npy("<f4", false, huge, &[])[..]).is_err());
+
+        // a truncated file fails without allocating what its header declares
+        let mut b = Builder::new(Cursor::new(Vec::new()));
+        let truncated = npy("<f4", false, "(1099511627776,)", &[0; 64]);
+        assert!(add_npy(&mut b, "t", &truncated[..], Default::default()).is_err());
+    }
+
+    #[test]
+    fn npz_roundtrip() {
+        let le = (0..12)
+            .flat_map(|i| (i as f32).to_le_bytes())
+            .collect::<Vec<_>>();
+        let be = (0..6)
+            .flat_map(|i| (i as i16 * 300).to_be_bytes())
+            .collect::<Vec<_>>();
+        let files = [
+            ("a", npy("<f4", false, "(3, 4)", &le)),
+            ("b", npy(">i2", true, "(2, 3)", &be)),
+            ("c", npy("|b1", false, "(4,)", &[0, 1, 1, 0])),
+        ];
+        let mut npz = Cursor::new(Vec::new());
+        let mut z = zip::ZipWriter::new(&mut npz);
+        for (name, data) in &files {
+            z.start_file(format!("{name}.npy"), SimpleFileOptions::default())
+                .unwrap();
+            z.write_all(data).unwrap();
+        }
+        z.finish().unwrap();
+
+        let mut buf = Cursor::new(Vec::new());
+        let mut b = Builder::new(&mut buf);
+        npz.set_position(0);
+        add_npz(&mut b, npz, BlobWriteOption::default()).unwrap();
+        b.finish().unwrap();
+
+        let mut a = Archive::new(Cursor::new(buf.into_inner())).unwrap();
+        let mut out = Cursor::new(Vec::new());
+        write_npz(&mut a, &mut out).unwrap();
+        let mut z = zip::ZipArchive::new(out).unwrap();
+        for (name, data) in &files {
+            let mut f = z.by_name(&format!("{name}.npy")).unwrap();
+            let h = read_header(&mut f).unwrap();
+            let mut back = vec![];
+            f.read_to_end(&mut back).unwrap();
+            let expected = &mut &data[..];
+            assert_eq!(h, read_header(expected).unwrap());
+            assert_eq!(&back, expected);
+        }
+    }
+}
@@ -0,0 +1,409 @@
+//! ONNX models, packed the same way as `tsar.formats.onnx` in Python.
+//!
+//! Initializers and attribute tensors, including those of subgraphs, with at
+//! least `size_limit` bytes of payload (the length of the raw data, or of the
+//! typed values as stored) become blobs `{name}[{idx}]` targeting `{name}`
+//! with a `.data` extension, and are rewritten as external data references
+//! into it. The stripped model is stored as the raw file `name`,
+//! and `.{name}.json` maps tensor names to blob names.
+
+use std::{
+    io::{Read, Seek, Write},
+    path::Path,
+};
+
+use protobuf::{Enum, EnumOrUnknown, Message};
+
+use super::tensor_map;
+use crate::{
+    pbgen::onnx::{
+        attribute_proto::AttributeType,
+        tensor_proto::{self, DataLocation},
+        GraphProto, ModelProto, StringStringEntryProto, TensorProto,
+    },
+    result::Result,
+    BlobWriteOption, Builder, DataType,
+};
+
+/// Smallest tensor, in bytes, moved out of the model by default.
+pub const DEFAULT_SIZE_LIMIT: usize = 16 * 1024;
+
+/// Collect initializers and attribute tensors separately, both in the order
+/// the Python packer visits them.
+fn walk<'a>(
+    g: &'a mut GraphProto,
+    inits: &mut Vec<&'a mut TensorProto>,
+    attrs: &mut Vec<&'a mut TensorProto>,
+) {
+    inits.extend(g.initializer.iter_mut());
+    for node in g.node.iter_mut() {
+        for a in node.attribute.iter_mut() {
+            let ty = a.type_.and_then(|t| t.enum_value().ok());
+            attrs.extend(a.t.as_mut());
+            attrs.extend(a.tensors.iter_mut());
+            match ty {
+                Some(AttributeType::GRAPH) => {
+                    if let Some(g) = a.g.as_mut() {
+                        walk(g, inits, attrs);
+                    }
+                }
+                Some(AttributeType::GRAPHS) => {
+                    for g in a.graphs.iter_mut() {
+                        walk(g, inits, attrs);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Number of elements of `t`, or `None` for negative or overflowing dims.
+fn num_elements(t: &TensorProto) -> Option<usize> {
+    t.dims
+        .iter()
+        .try_fold(1usize, |n, &d| n.checked_mul(usize::try_from(d).ok()?))
+}
+
+/// Data type of tensors stored from `raw_data`.
+fn raw_type(data_type: i32) -> Option<DataType> {
+    use tensor_proto::DataType as T;
+    Some(match T::from_i32(data_type)? {
+        T::FLOAT => DataType::Float32,
+        T::DOUBLE => DataType::Float64,
+        T::BFLOAT16 => DataType::Bfloat16,
+        T::FLOAT16 => DataType::Float16,
+        T::INT8 => DataType::Int8,
+        T::UINT8 => DataType::Uint8,
+        T::INT16 => DataType::Int16,
+        T::UINT16 => DataType::Uint16,
+        T::INT32 => DataType::Int32,
+        T::UINT32 => DataType::Uint32,
+        T::INT64 => DataType::Int64,
+        T::UINT64 => DataType::Uint64,
+        T::BOOL => DataType::Bool,
+        T::COMPLEX64 => DataType::Complex64,
+        T::COMPLEX128 => DataType::Complex128,
+        T::INT4 => DataType::Int4,
+        T::UINT4 => DataType::Uint4,
+        T::FLOAT8E4M3FN => DataType::Float8E4M3,
+        T::FLOAT8E5M2 => DataType::Float8E5M2,
+        _ => return None,
+    })
+}
+
+/// Move the data of `t` out if it has at least `size_limit` bytes, as
+/// little-endian bytes of its data type or `None` for unknown types.
+fn take_data(t: &mut TensorProto, size_limit: usize) -> Option<(Option<DataType>, Vec<u8>)> {
+    use tensor_proto::DataType as T;
+
+    let dt = raw_type(t.data_type());
+    if t.raw_data.as_ref().is_some_and(|r| r.len() >= size_limit) {
+        return Some((dt, t.raw_data.take().unwrap()));
+    }
+
+    macro_rules! take {
+        ($field:ident, $width:expr, $conv:expr) => {{
+            if t.$field.len() * $width < size_limit {
+                return None;
+            }
+            let d = std::mem::take(&mut t.$field)
+                .into_iter()
+                .flat_map($conv)
+                .collect();
+            Some((dt, d))
+        }};
+    }
+    match T::from_i32(t.data_type())? {
+        T::FLOAT => take!(float_data, 4, f32::to_le_bytes),
+        T::DOUBLE => take!(double_data, 8, f64::to_le_bytes),
+        T::FLOAT16 | T::UINT16 => take!(int32_data, 2, |v| (v as u16).to_le_bytes()),
+        T::INT16 => take!(int32_data, 2, |v| (v as i16).to_le_bytes()),
+        T::INT8 => take!(int32_data, 1, |v| (v as i8).to_le_bytes()),
+        T::UINT8 => take!(int32_data, 1, |v| (v as u8).to_le_bytes()),
+        T::INT32 => take!(int32_data, 4, i32::to_le_bytes),
+        T::UINT32 => take!(uint64_data, 4, |v| (v as u32).to_le_bytes()),
+        T::INT64 => take!(int64_data, 8, i64::to_le_bytes),
+        T::UINT64 => take!(uint64_data, 8, u64::to_le_bytes),
+        _ => None,
+    }
+}
+
+/// Add the ONNX model in `reader`, to be extracted as `name`, moving tensors
+/// of at least `size_limit` bytes into blobs.
+pub fn add_onnx<W: Write + Seek>(
+    builder: &mut Builder<W>,
+    name: &str,
+    mut reader: impl Read,
+    size_limit: usize,
+    opt: BlobWriteOption,
+) -> Result<()> {
+    let mut model = ModelProto::parse_from_reader(&mut reader)?;
+    let (mut inits, mut attrs) = (vec![], vec![]);
+    if let Some(g) = model.graph.as_mut() {
+        walk(g, &mut inits, &mut attrs);
+    }
+    let mut tensors = inits;
+    tensors.append(&mut attrs);
+    tensors.sort_by_key(|t| num_elements(t).unwrap_or(usize::MAX));
+
+    let location = Path::new(name)
+        .with_extension("data")
+        .to_string_lossy()
+        .into_owned();
+    let mut offset = 0;
+    let mut blobs = vec![];
+    for (idx, t) in tensors.into_iter().enumerate() {
+        if t.name().is_empty() {
+            continue;
+        }
+        let Some((dt, data)) = take_data(t, size_limit) else {
+            continue;
+        };
+
+        let blob = format!("{name}[{idx}]");
+        let opt = BlobWriteOption {
+            target_file: Some((location.clone(), offset)),
+            ..opt.clone()
+        };
+        // data not matching its shape is kept as opaque bytes
+        let bits = |dt: DataType| num_elements(t)?.checked_mul(dt.bit_len());
+        match dt.filter(|&dt| bits(dt).map(|b| b.div_ceil(8)) == Some(data.len())) {
+            Some(dt) => {
+                // all dims are non-negative, as the element count exists
+                let dims = t.dims.iter().map(|&d| d as usize).collect::<Vec<_>>();
+                builder.add_blob(&blob, &data, dt, &dims, opt)?
+            }
+            None => builder.add_blob(&blob, &data, DataType::Byte, &[data.len()], opt)?,
+        }
+        blobs.push((t.name().to_owned(), blob));
+
+        t.data_location = Some(EnumOrUnknown::new(DataLocation::EXTERNAL));
+        t.external_data = [
+            ("location", location.clone()),
+            ("offset", offset.to_string()),
+            ("length", data.len().to_string()),
+        ]
+        .into_iter()
+        .map(|(k, v)| StringStringEntryProto {
+            key: Some(k.into()),
+            value: Some(v),
+            ..Default::default()
+        })
+        .collect();
+        offset += data.len() as u64;
+    }
+
+    let map = tensor_map(blobs);
+    builder.add_file(format!(".{name}.json"), map.as_bytes())?;
+    builder.add_file(name, &model.write_to_bytes()?[..])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs, io::Cursor};
+
+    use super::*;
+    use crate::{
+        pbgen::onnx::{AttributeProto, NodeProto},
+        Archive, ExtractOption,
+    };
+
+    fn tensor(name: &str, data_type: tensor_proto::DataType, dims: &[i64]) -> TensorProto {
+        TensorProto {
+            name: Some(name.into()),
+            data_type: Some(data_type as i32),
+            dims: dims.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pack() {
+        use tensor_proto::DataType as T;
+
+        let mut w = tensor("w", T::FLOAT, &[64, 64]);
+        w.raw_data = Some((0..4096).flat_map(|i| (i as f32).to_le_bytes()).collect());
+        let mut f = tensor("f", T::FLOAT, &[4096]);
+        f.float_data = (0..4096).map(|i| i as f32 * 0.5).collect();
+        let mut small = tensor("small", T::FLOAT, &[8]);
+        small.float_data = vec![1.0; 8];
+        let mut unnamed = tensor("", T::FLOAT, &[4096]);
+        unnamed.raw_data = Some(vec![0; 4096 * 4]);
+        let mut c = tensor("c", T::INT64, &[2048]);
+        c.int64_data = (0..2048).collect();
+        let mut sub = tensor("sub", T::DOUBLE, &[2048]);
+        sub.double_data = (0..2048).map(|i| i as f64).collect();
+
+        let subgraph = GraphProto {
+            initializer: vec![sub],
+            ..Default::default()
+        };
+        let node = NodeProto {
+            attribute: vec![
+                AttributeProto {
+                    t: Some(c).into(),
+                    type_: Some(AttributeType::TENSOR.into()),
+                    ..Default::default()
+                },
+                AttributeProto {
+                    g: Some(subgraph).into(),
+                    type_: Some(AttributeType::GRAPH.into()),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let model = ModelProto {
+            graph: Some(GraphProto {
+                node: vec![node],
+                initializer: vec![w, f, small, unnamed],
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        };
+        let expected = model.clone();
+        let mut src = model.write_to_bytes().unwrap();
+        // ir_version, which the reduced schema does not know about
+        src.extend([0x08, 0x07]);
+
+        let mut buf = Cursor::new(Vec::new());
+        let mut b = Builder::new(&mut buf);
+        add_onnx(
+            &mut b,
+            "model.onnx",
+            &src[..],
+            DEFAULT_SIZE_LIMIT,
+            BlobWriteOption::default(),
+        )
+        .unwrap();
+        b.finish().unwrap();
+
+        let mut a = Archive::new(Cursor::new(buf.into_inner())).unwrap();
+        let mut map = String::new();
+        a.file_by_name(".model.onnx.json")
+            .unwrap()
+            .read_to_string(&mut map)
+            .unwrap();
+        assert_eq!(
+            map,
+            r#"{"blobs": {"sub": "model.onnx[1]", "c": "model.onnx[2]", "w": "model.onnx[3]", "f": "model.onnx[4]"}}"#
+        );
+
+        let dir = std::env::temp_dir().join(format!("tsar-onnx-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        a.extract_to(&dir, ExtractOption::default()).unwrap();
+        let packed =
+            ModelProto::parse_from_bytes(&fs::read(dir.join("model.onnx")).unwrap()).unwrap();
+        let data = fs::read(dir.join("model.data")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(packed.special_fields.unknown_fields().get(1).is_some());
+
+        // loading the external data back gives the original model
+        let mut packed = packed;
+        let (mut inits, mut attrs) = (vec![], vec![]);
+        walk(packed.graph.as_mut().unwrap(), &mut inits, &mut attrs);
+        let (mut inits_e, mut attrs_e) = (vec![], vec![]);
+        let mut expected = expected;
+        walk(expected.graph.as_mut().unwrap(), &mut inits_e, &mut attrs_e);
+        inits.append(&mut attrs);
+        inits_e.append(&mut attrs_e);
+        for (t, e) in inits.into_iter().zip(inits_e) {
+            if t.data_location.is_none() {
+                assert_eq!(t, e);
+                continue;
+            }
+            let ext = t
+                .external_data
+                .iter()
+                .map(|e| (e.key(), e.value()))
+                .collect::<HashMap<_, _>>();
+            assert_eq!(ext["location"], "model.data");
+            let off = ext["offset"].parse::<usize>().unwrap();
+            let len = ext["length"].parse::<usize>().unwrap();
+            let mut back = TensorProto {
+                name: e.name.clone(),
+                data_type: e.data_type,
+                dims: e.dims.clone(),
+                ..Default::default()
+            };
+            let d = &data[off..off + len];
+            match T::from_i32(e.data_type()).unwrap() {
+                T::FLOAT if e.raw_data.is_none() => {
+                    back.float_data = d
+                        .chunks_exact(4)
+                        .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+                        .collect()
+                }
+                T::INT64 => {
+                    back.int64_data = d
+                        .chunks_exact(8)
+                        .map(|c| i64::from_le_bytes(c.try_into().unwrap()))
+                        .collect()
+                }
+                T::DOUBLE => {
+                    back.double_data = d
+                        .chunks_exact(8)
+                        .map(|c| f64::from_le_bytes(c.try_into().unwrap()))
+                        .collect()
+                }
+                _ => back.raw_data = Some(d.to_vec()),
+            }
+            assert_eq!(&back, e);
+        }
+    }
+
+    #[test]
+    fn size_limit() {
+        use tensor_proto::DataType as T;
+
+        // the limit counts payload bytes, without any container overhead
+        let mut r = tensor("r", T::FLOAT, &[4]);
+        r.raw_data = Some(vec![0; 16]);
+        assert!(take_data(&mut r.clone(), 17).is_none());
+        assert_eq!(take_data(&mut r, 16).unwrap().1.len(), 16);
+        let mut f = tensor("f", T::FLOAT, &[4]);
+        f.float_data = vec![1.0; 4];
+        assert!(take_data(&mut f.clone(), 17).is_none());
+        assert_eq!(take_data(&mut f, 16).unwrap().1.len(), 16);
+    }
+
+    #[test]
+    fn bad_dims() {
+        use tensor_proto::DataType as T;
+
+        let mut tensors = vec![];
+        for (name, dims) in [
+            ("negative", &[-1, -4096][..]),
+            ("huge", &[i64::MAX, i64::MAX]),
+            ("wrapping", &[1 << 32, 1 << 32, 4097]),
+        ] {
+            let mut t = tensor(name, T::FLOAT, dims);
+            t.raw_data = Some(vec![1; 4096 * 4]);
+            tensors.push(t);
+        }
+        let model = ModelProto {
+            graph: Some(GraphProto {
+                initializer: tensors,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        };
+        let src = model.write_to_bytes().unwrap();
+
+        let mut buf = Cursor::new(Vec::new());
+        let mut b = Builder::new(&mut buf);
+        add_onnx(&mut b, "m.onnx", &src[..], 1024, BlobWriteOption::default()).unwrap();
+        b.finish().unwrap();
+
+        let mut a = Archive::new(Cursor::new(buf.into_inner())).unwrap();
+        let names = a.blob_names().map(str::to_owned).collect::<Vec<_>>();
+        assert_eq!(names.len(), 3);
+        for n in names {
+            let blob = a.blob_by_name(&n).unwrap();
+            assert_eq!(blob.data_type(), Some(DataType::Byte), "{n}");
+        }
+    }
+}
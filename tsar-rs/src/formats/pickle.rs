@@ -0,0 +1,568 @@
+//! Just enough of a pickle interpreter to read PyTorch checkpoints.
+//!
+//! Nothing is executed: calls and object construction are kept as values
+//! for the caller to interpret, except `collections.OrderedDict()` which
+//! becomes a plain dict.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use crate::result::{Error, Result};
+
+/// Most bytes a pickle may expand to through references to memoized values.
+const MAX_EXPANSION: usize = 1 << 28;
+
+/// Most containers a pickle may nest inside one another.
+pub const MAX_DEPTH: usize = 256;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    None,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Tuple(Vec<Value>),
+    List(Vec<Value>),
+    Dict(Vec<(Value, Value)>),
+    Global(String, String),
+    /// `REDUCE`, `NEWOBJ` and `BUILD` results: callable and arguments.
+    Call(Box<Value>, Box<Value>),
+    PersId(Box<Value>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_tuple(&self) -> Option<&[Value]> {
+        match self {
+            Value::Tuple(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn is_global(&self, module: &str, name: &str) -> bool {
+        matches!(self, Value::Global(m, n) if m == module && n == name)
+    }
+}
+
+fn malformed() -> Error {
+    Error::Corrupt("malformed pickle")
+}
+
+/// Items of a list or dict under construction, and how deeply they nest.
+struct Items<T> {
+    items: RefCell<Vec<T>>,
+    depth: Cell<usize>,
+    /// Set once the container is put inside another, whose depth was
+    /// computed from this one's, so it may not grow any deeper.
+    nested: Cell<bool>,
+}
+
+impl<T> Default for Items<T> {
+    fn default() -> Self {
+        Self {
+            items: RefCell::default(),
+            depth: Cell::new(1),
+            nested: Cell::new(false),
+        }
+    }
+}
+
+impl<T> Items<T> {
+    /// Make room for items needing a container `depth` deep.
+    fn deepen(&self, depth: usize) -> Result<()> {
+        if depth > self.depth.get() {
+            if self.nested.get() {
+                return Err(malformed());
+            }
+            self.depth.set(depth);
+        }
+        Ok(())
+    }
+}
+
+/// Value under construction. Every node is shared rather than copied by
+/// the memo, so items added to a list or dict after memoizing it show
+/// through every reference to it, and references cost nothing until the
+/// result is copied out. Tuples, calls and persistent IDs carry their
+/// depth.
+#[derive(Clone)]
+enum Obj {
+    Value(Rc<Value>),
+    Tuple(Rc<[Obj]>, usize),
+    List(Rc<Items<Obj>>),
+    Dict(Rc<Items<(Obj, Obj)>>),
+    Call(Rc<(Obj, Obj)>, usize),
+    PersId(Rc<Obj>, usize),
+}
+
+/// Depth of a container holding `items`, at most [`MAX_DEPTH`].
+fn nesting<'o>(items: impl IntoIterator<Item = &'o Obj>) -> Result<usize> {
+    let d = items.into_iter().map(Obj::depth).max().unwrap_or(0) + 1;
+    if d > MAX_DEPTH {
+        return Err(malformed());
+    }
+    Ok(d)
+}
+
+/// Mark the lists and dicts in `items` as put inside another container.
+fn seal<'o>(items: impl IntoIterator<Item = &'o Obj>) {
+    for o in items {
+        match o {
+            Obj::List(l) => l.nested.set(true),
+            Obj::Dict(d) => d.nested.set(true),
+            _ => {}
+        }
+    }
+}
+
+impl Obj {
+    fn tuple(items: Vec<Obj>) -> Result<Obj> {
+        let d = nesting(&items)?;
+        seal(&items);
+        Ok(Obj::Tuple(items.into(), d))
+    }
+
+    fn call(f: Obj, args: Obj) -> Result<Obj> {
+        let d = nesting([&f, &args])?;
+        seal([&f, &args]);
+        Ok(Obj::Call(Rc::new((f, args)), d))
+    }
+
+    fn depth(&self) -> usize {
+        match self {
+            Obj::Value(_) => 0,
+            Obj::Tuple(_, d) | Obj::Call(_, d) | Obj::PersId(_, d) => *d,
+            Obj::List(l) => l.depth.get(),
+            Obj::Dict(d) => d.depth.get(),
+        }
+    }
+
+    /// Copy into a plain [`Value`], spending the bytes of every node and
+    /// string from `budget`. A container reached again from inside itself, which
+    /// `Value` cannot represent, becomes `None`.
+    fn to_value(&self, budget: &mut usize, path: &mut Vec<*const ()>) -> Result<Value> {
+        let cost = match self {
+            Obj::Value(v) => match &**v {
+                Value::Str(s) => s.len(),
+                Value::Bytes(b) => b.len(),
+                _ => 0,
+            },
+            _ => 0,
+        };
+        let cost = cost + std::mem::size_of::<Value>();
+        *budget = budget.checked_sub(cost).ok_or_else(malformed)?;
+        let all = |v: &[Obj], budget: &mut usize, path: &mut Vec<_>| {
+            v.iter()
+                .map(|o| o.to_value(budget, path))
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(match self {
+            Obj::Value(v) => (**v).clone(),
+            Obj::Tuple(t, _) => Value::Tuple(all(t, budget, path)?),
+            Obj::List(l) => {
+                let p = Rc::as_ptr(l).cast();
+                if path.contains(&p) {
+                    return Ok(Value::None);
+                }
+                path.push(p);
+                let v = all(&l.items.borrow(), budget, path)?;
+                path.pop();
+                Value::List(v)
+            }
+            Obj::Dict(d) => {
+                let p = Rc::as_ptr(d).cast();
+                if path.contains(&p) {
+                    return Ok(Value::None);
+                }
+                path.push(p);
+                let v = d
+                    .items
+                    .borrow()
+                    .iter()
+                    .map(|(k, v)| Ok((k.to_value(budget, path)?, v.to_value(budget, path)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                path.pop();
+                Value::Dict(v)
+            }
+            Obj::Call(c, _) => Value::Call(
+                Box::new(c.0.to_value(budget, path)?),
+                Box::new(c.1.to_value(budget, path)?),
+            ),
+            Obj::PersId(p, _) => Value::PersId(Box::new(p.to_value(budget, path)?)),
+        })
+    }
+
+    fn is_global(&self, module: &str, name: &str) -> bool {
+        matches!(self, Obj::Value(v) if v.is_global(module, name))
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Obj::Value(v) => v.as_str(),
+            _ => None,
+        }
+    }
+}
+
+struct Machine<'a> {
+    s: &'a [u8],
+    stack: Vec<Obj>,
+    marks: Vec<usize>,
+    memo: HashMap<usize, Obj>,
+}
+
+impl<'a> Machine<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let (t, rest) = self.s.split_at_checked(n).ok_or_else(malformed)?;
+        self.s = rest;
+        Ok(t)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn line(&mut self) -> Result<String> {
+        let n = self
+            .s
+            .iter()
+            .position(|&c| c == b'\n')
+            .ok_or_else(malformed)?;
+        let l = self.take(n + 1)?;
+        String::from_utf8(l[..n].to_vec()).map_err(|_| malformed())
+    }
+
+    fn pop(&mut self) -> Result<Obj> {
+        match self.marks.last() {
+            Some(&m) if m >= self.stack.len() => Err(malformed()),
+            _ => self.stack.pop().ok_or_else(malformed),
+        }
+    }
+
+    fn top(&mut self) -> Result<&mut Obj> {
+        self.stack.last_mut().ok_or_else(malformed)
+    }
+
+    fn pop_mark(&mut self) -> Result<Vec<Obj>> {
+        let m = self.marks.pop().ok_or_else(malformed)?;
+        Ok(self.stack.split_off(m))
+    }
+
+    fn push(&mut self, v: Value) {
+        self.stack.push(Obj::Value(Rc::new(v)));
+    }
+
+    fn put(&mut self, idx: usize) -> Result<()> {
+        let v = self.top()?.clone();
+        self.memo.insert(idx, v);
+        Ok(())
+    }
+
+    fn get(&mut self, idx: usize) -> Result<()> {
+        let v = self.memo.get(&idx).ok_or_else(malformed)?.clone();
+        self.stack.push(v);
+        Ok(())
+    }
+
+    fn string(&mut self, n: usize) -> Result<()> {
+        let s = self.take(n)?.to_vec();
+        let s = String::from_utf8(s).map_err(|_| malformed())?;
+        self.push(Value::Str(s));
+        Ok(())
+    }
+
+    fn set_items(&mut self, items: Vec<Obj>) -> Result<()> {
+        if !items.len().is_multiple_of(2) {
+            return Err(malformed());
+        }
+        let Obj::Dict(d) = self.top()? else {
+            return Err(malformed());
+        };
+        d.deepen(nesting(&items)?)?;
+        seal(&items);
+        let mut d = d.items.borrow_mut();
+        let mut it = items.into_iter();
+        while let (Some(k), Some(v)) = (it.next(), it.next()) {
+            d.push((k, v));
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, items: Vec<Obj>) -> Result<()> {
+        let Obj::List(l) = self.top()? else {
+            return Err(malformed());
+        };
+        l.deepen(nesting(&items)?)?;
+        seal(&items);
+        l.items.borrow_mut().extend(items);
+        Ok(())
+    }
+
+    fn call(&mut self, f: Obj, args: Obj) -> Result<()> {
+        let v = if f.is_global("collections", "OrderedDict") {
+            Obj::Dict(Rc::default())
+        } else {
+            Obj::call(f, args)?
+        };
+        self.stack.push(v);
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<Obj> {
+        loop {
+            let [op] = self.take_array()?;
+            match op {
+                // PROTO, FRAME
+                0x80 => drop(self.take(1)?),
+                0x95 => drop(self.take(8)?),
+                b'.' => return self.pop(),
+                b'(' => self.marks.push(self.stack.len()),
+                b'0' => drop(self.pop()?),
+                b'N' => self.push(Value::None),
+                0x88 => self.push(Value::Bool(true)),
+                0x89 => self.push(Value::Bool(false)),
+                b'J' => {
+                    let v = i32::from_le_bytes(self.take_array()?);
+                    self.push(Value::Int(v.into()));
+                }
+                b'K' => {
+                    let [v] = self.take_array()?;
+                    self.push(Value::Int(v.into()));
+                }
+                b'M' => {
+                    let v = u16::from_le_bytes(self.take_array()?);
+                    self.push(Value::Int(v.into()));
+                }
+                // LONG1
+                0x8a => {
+                    let [n] = self.take_array()?;
+                    let b = self.take(n.into())?;
+                    if n > 8 {
+                        return Err(Error::Unsupported("pickled integer over 64 bits".into()));
+                    }
+                    let fill = if b.last().is_some_and(|&c| c & 0x80 != 0) {
+                        0xff
+                    } else {
+                        0
+                    };
+                    let mut v = [fill; 8];
+                    v[..b.len()].copy_from_slice(b);
+                    self.push(Value::Int(i64::from_le_bytes(v)));
+                }
+                b'G' => {
+                    let v = f64::from_be_bytes(self.take_array()?);
+                    self.push(Value::Float(v));
+                }
+                b'X' => {
+                    let n = u32::from_le_bytes(self.take_array()?);
+                    self.string(n as usize)?;
+                }
+                0x8c => {
+                    let [n] = self.take_array()?;
+                    self.string(n.into())?;
+                }
+                b'C' => {
+                    let [n] = self.take_array()?;
+                    let b = self.take(n.into())?.to_vec();
+                    self.push(Value::Bytes(b));
+                }
+                b'B' => {
+                    let n = u32::from_le_bytes(self.take_array()?);
+                    let b = self.take(n as usize)?.to_vec();
+                    self.push(Value::Bytes(b));
+                }
+                b'c' => {
+                    let (m, n) = (self.line()?, self.line()?);
+                    self.push(Value::Global(m, n));
+                }
+                // STACK_GLOBAL
+                0x93 => {
+                    let n = self.pop()?;
+                    let m = self.pop()?;
+                    let (m, n) = m.as_str().zip(n.as_str()).ok_or_else(malformed)?;
+                    self.push(Value::Global(m.to_owned(), n.to_owned()));
+                }
+                b')' => self.stack.push(Obj::Tuple(Rc::new([]), 1)),
+                b't' => {
+                    let t = self.pop_mark()?;
+                    self.stack.push(Obj::tuple(t)?);
+                }
+                0x85..=0x87 => {
+                    let n = usize::from(op - 0x84);
+                    if self.stack.len() < n + self.marks.last().copied().unwrap_or(0) {
+                        return Err(malformed());
+                    }
+                    let t = self.stack.split_off(self.stack.len() - n);
+                    self.stack.push(Obj::tuple(t)?);
+                }
+                b']' => self.stack.push(Obj::List(Rc::default())),
+                b'l' => {
+                    let l = self.pop_mark()?;
+                    self.stack.push(Obj::List(Rc::default()));
+                    self.append(l)?;
+                }
+                b'a' => {
+                    let v = self.pop()?;
+                    self.append(vec![v])?;
+                }
+                b'e' => {
+                    let items = self.pop_mark()?;
+                    self.append(items)?;
+                }
+                b'}' => self.stack.push(Obj::Dict(Rc::default())),
+                b'd' => {
+                    let items = self.pop_mark()?;
+                    self.stack.push(Obj::Dict(Rc::default()));
+                    self.set_items(items)?;
+                }
+                b's' => {
+                    let v = self.pop()?;
+                    let k = self.pop()?;
+                    self.set_items(vec![k, v])?;
+                }
+                b'u' => {
+                    let items = self.pop_mark()?;
+                    self.set_items(items)?;
+                }
+                b'q' => {
+                    let [i] = self.take_array()?;
+                    self.put(i.into())?;
+                }
+                b'r' => {
+                    let i = u32::from_le_bytes(self.take_array()?);
+                    self.put(i as usize)?;
+                }
+                // MEMOIZE
+                0x94 => {
+                    let i = self.memo.len();
+                    self.put(i)?;
+                }
+                b'h' => {
+                    let [i] = self.take_array()?;
+                    self.get(i.into())?;
+                }
+                b'j' => {
+                    let i = u32::from_le_bytes(self.take_array()?);
+                    self.get(i as usize)?;
+                }
+                b'Q' => {
+                    let pid = self.pop()?;
+                    let d = nesting([&pid])?;
+                    seal([&pid]);
+                    self.stack.push(Obj::PersId(Rc::new(pid), d));
+                }
+                b'R' | 0x81 => {
+                    let args = self.pop()?;
+                    let f = self.pop()?;
+                    self.call(f, args)?;
+                }
+                b'b' => {
+                    let state = self.pop()?;
+                    let obj = self.pop()?;
+                    // state set on dicts is only attributes like `_metadata`
+                    match obj {
+                        Obj::Dict(_) => self.stack.push(obj),
+                        obj => {
+                            let v = Obj::call(obj, Obj::tuple(vec![state])?)?;
+                            self.stack.push(v);
+                        }
+                    }
+                }
+                op => return Err(Error::Unsupported(format!("pickle opcode {op:#04x}"))),
+            }
+        }
+    }
+}
+
+/// Evaluate the pickle in `s`.
+pub fn load(s: &[u8]) -> Result<Value> {
+    let v = Machine {
+        s,
+        stack: vec![],
+        marks: vec![],
+        memo: HashMap::new(),
+    }
+    .run()?;
+    let mut budget = MAX_EXPANSION;
+    v.to_value(&mut budget, &mut vec![])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(i: i64) -> Value {
+        Value::Int(i)
+    }
+
+    #[test]
+    fn memo() {
+        // a dict memoized before its items are set, then fetched again
+        let d = Value::Dict(vec![(Value::Str("k".into()), int(1))]);
+        let p = b"\x80\x02}q\x00X\x01\x00\x00\x00kK\x01sh\x00\x86.";
+        assert_eq!(load(p).unwrap(), Value::Tuple(vec![d.clone(), d]));
+
+        // sparse indices do not allocate up to them
+        let p = b"Nr\xff\xff\xff\xffj\xff\xff\xff\xff\x86.";
+        assert_eq!(load(p).unwrap(), Value::Tuple(vec![Value::None; 2]));
+        assert!(load(b"j\x00\x00\x00\x01.").is_err());
+
+        // a list appended to itself
+        let p = b"]q\x00K\x07ah\x00a.";
+        assert_eq!(load(p).unwrap(), Value::List(vec![int(7), Value::None]));
+    }
+
+    #[test]
+    fn expansion() {
+        // every step pairs the previous value with itself
+        let mut p = b"N".to_vec();
+        for _ in 0..16 {
+            p.extend(b"q\x00h\x00\x86");
+        }
+        p.push(b'.');
+        assert!(load(&p).is_ok());
+        p.pop();
+        for _ in 0..32 {
+            p.extend(b"q\x00h\x00\x86");
+        }
+        p.push(b'.');
+        assert!(load(&p).is_err());
+    }
+
+    #[test]
+    fn depth() {
+        let nested = |n| {
+            let mut p = b"N".to_vec();
+            p.resize(n + 1, 0x85);
+            p.push(b'.');
+            p
+        };
+        assert!(load(&nested(MAX_DEPTH)).is_ok());
+        assert!(load(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(load(&nested(1 << 20)).is_err());
+
+        // a list may not deepen after being put in a tuple
+        let p = b"]q\x00\x85h\x00)\x85a.";
+        assert!(load(p).is_err());
+        let p = b"]q\x00)\x85ah\x00\x85.";
+        assert!(load(p).is_ok());
+    }
+}
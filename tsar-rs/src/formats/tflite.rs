@@ -0,0 +1,438 @@
+//! TFLite flatbuffer models.
+//!
+//! Buffers of constant tensors of at least `size_limit` bytes become blobs
+//! `{name}[{idx}]` at their offset in the model, typed from the first tensor
+//! using them, and `{name}[gap@{offset}]` holds each non-zero stretch of
+//! flatbuffer between them, so extracting gives back the same file.
+//! `.{name}.json` maps tensor names to blob names.
+
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Read, Seek, Write},
+};
+
+use super::tensor_map;
+use crate::{
+    result::{Error, Result},
+    BlobWriteOption, Builder, DataType, Metadata, Region,
+};
+
+/// Smallest buffer, in bytes, stored as a blob of its own by default.
+pub const DEFAULT_SIZE_LIMIT: usize = 16 * 1024;
+
+fn malformed() -> Error {
+    Error::Corrupt("malformed TFLite flatbuffer")
+}
+
+/// Bounds-checked reads from a flatbuffer.
+struct Flatbuffer<'a>(&'a [u8]);
+
+impl Flatbuffer<'_> {
+    fn bytes<const N: usize>(&self, pos: usize) -> Result<[u8; N]> {
+        let end = pos.checked_add(N).ok_or_else(malformed)?;
+        let b = self.0.get(pos..end).ok_or_else(malformed)?;
+        Ok(b.try_into().unwrap())
+    }
+
+    fn u32(&self, pos: usize) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(pos)?))
+    }
+
+    /// Follow the offset stored at `pos`.
+    fn deref(&self, pos: usize) -> Result<usize> {
+        pos.checked_add(self.u32(pos)? as usize)
+            .ok_or_else(malformed)
+    }
+
+    /// Position of field `idx` of the table at `table`, if present.
+    fn field(&self, table: usize, idx: usize) -> Result<Option<usize>> {
+        let soff = i32::from_le_bytes(self.bytes(table)?);
+        let vtable = (table as i64 - i64::from(soff)) as usize;
+        let len = u16::from_le_bytes(self.bytes(vtable)?) as usize;
+        let slot = 4 + 2 * idx;
+        if slot + 2 > len {
+            return Ok(None);
+        }
+        let off = u16::from_le_bytes(self.bytes(vtable + slot)?) as usize;
+        Ok((off != 0).then_some(table + off))
+    }
+
+    /// Table or vector referenced by field `idx`.
+    fn child(&self, table: usize, idx: usize) -> Result<Option<usize>> {
+        self.field(table, idx)?.map(|p| self.deref(p)).transpose()
+    }
+
+    /// Start and length of the vector referenced by field `idx`.
+    fn vector(&self, table: usize, idx: usize) -> Result<(usize, usize)> {
+        match self.child(table, idx)? {
+            Some(v) => {
+                let n = self.u32(v)? as usize;
+                Ok((v + 4, n))
+            }
+            None => Ok((0, 0)),
+        }
+    }
+
+    /// Tables referenced by the vector in field `idx`.
+    fn tables(&self, table: usize, idx: usize) -> Result<Vec<usize>> {
+        let (start, n) = self.vector(table, idx)?;
+        if n > self.0.len() / 4 {
+            return Err(malformed());
+        }
+        (0..n).map(|i| self.deref(start + 4 * i)).collect()
+    }
+}
+
+fn tensor_type(ty: u8) -> Option<DataType> {
+    Some(match ty {
+        0 => DataType::Float32,
+        1 => DataType::Float16,
+        2 => DataType::Int32,
+        3 => DataType::Uint8,
+        4 => DataType::Int64,
+        6 => DataType::Bool,
+        7 => DataType::Int16,
+        8 => DataType::Complex64,
+        9 => DataType::Int8,
+        10 => DataType::Float64,
+        11 => DataType::Complex128,
+        12 => DataType::Uint64,
+        15 => DataType::Uint32,
+        16 => DataType::Uint16,
+        17 => DataType::Int4,
+        18 => DataType::Bfloat16,
+        _ => return None,
+    })
+}
+
+/// A constant tensor and the buffer holding its data.
+struct Tensor {
+    name: String,
+    buffer: usize,
+    data_type: Option<DataType>,
+    shape: Vec<usize>,
+}
+
+fn tensors(fb: &Flatbuffer, model: usize) -> Result<Vec<Tensor>> {
+    let mut out = vec![];
+    for subgraph in fb.tables(model, 2)? {
+        for t in fb.tables(subgraph, 0)? {
+            let buffer = match fb.field(t, 2)? {
+                Some(p) => fb.u32(p)? as usize,
+                None => 0,
+            };
+            if buffer == 0 {
+                continue;
+            }
+            let (start, n) = fb.vector(t, 0)?;
+            let shape = (0..n)
+                .map(|i| Ok(usize::try_from(fb.u32(start + 4 * i)? as i32).unwrap_or(0)))
+                .collect::<Result<Vec<_>>>()?;
+            let ty = match fb.field(t, 1)? {
+                Some(p) => fb.bytes::<1>(p)?[0],
+                None => 0,
+            };
+            let name = match fb.child(t, 3)? {
+                Some(s) => {
+                    let n = fb.u32(s)? as usize;
+                    let b = fb.0.get(s + 4..s + 4 + n).ok_or_else(malformed)?;
+                    String::from_utf8_lossy(b).into_owned()
+                }
+                None => String::new(),
+            };
+            out.push(Tensor {
+                name,
+                buffer,
+                data_type: tensor_type(ty),
+                shape,
+            });
+        }
+    }
+    Ok(out)
+}
+
+/// Byte range of each non-empty buffer, by index.
+fn buffers(fb: &Flatbuffer, model: usize) -> Result<Vec<Option<(usize, usize)>>> {
+    fb.tables(model, 4)?
+        .into_iter()
+        .map(|b| {
+            let (start, n) = fb.vector(b, 0)?;
+            if n > 0 {
+                return Ok(Some((start, start + n)));
+            }
+            // data appended after the flatbuffer, for models over 2GB
+            let read = |idx| -> Result<u64> {
+                match fb.field(b, idx)? {
+                    Some(p) => Ok(u64::from_le_bytes(fb.bytes(p)?)),
+                    None => Ok(0),
+                }
+            };
+            let (offset, size) = (read(1)?, read(2)?);
+            if offset <= 1 || size == 0 {
+                return Ok(None);
+            }
+            let end = offset.checked_add(size).ok_or_else(malformed)?;
+            Ok(Some((offset as usize, end as usize)))
+        })
+        .collect()
+}
+
+/// Add the TFLite model in `reader`, to be extracted as `name`.
+pub fn add_tflite<W: Write + Seek>(
+    builder: &mut Builder<W>,
+    name: &str,
+    mut reader: impl Read,
+    size_limit: usize,
+    opt: BlobWriteOption,
+) -> Result<()> {
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+    let fb = Flatbuffer(&data);
+    let model = fb.deref(0)?;
+    let tensors = tensors(&fb, model)?;
+    let buffers = buffers(&fb, model)?;
+
+    // buffers to store as blobs, by start offset
+    let mut regions = BTreeMap::new();
+    for t in &tensors {
+        let Some(&Some((start, end))) = buffers.get(t.buffer) else {
+            continue;
+        };
+        if end > data.len() {
+            return Err(malformed());
+        }
+        if end - start >= size_limit {
+            regions.entry(start).or_insert((start, end, t));
+        }
+    }
+
+    let blobs = regions.values().map(|&(start, end, t)| {
+        let len = end - start;
+        let numel = t.shape.iter().try_fold(1usize, |n, &d| n.checked_mul(d));
+        let (data_type, shape) = match (t.data_type, numel) {
+            (Some(dt), Some(n)) if n <= len * 8 && dt.size_of(n) == len => (dt, t.shape.clone()),
+            _ => (DataType::Byte, vec![len]),
+        };
+        Region {
+            name: format!("{name}[{}]", t.buffer),
+            offset: start as u64,
+            len: len as u64,
+            data_type,
+            shape,
+            metadata: Metadata::new(),
+        }
+    });
+    builder.add_regions(name, Cursor::new(&data), blobs, opt)?;
+
+    let map = tensors
+        .iter()
+        .filter_map(|t| {
+            let (start, _) = buffers.get(t.buffer).copied().flatten()?;
+            regions
+                .contains_key(&start)
+                .then(|| (&t.name, format!("{name}[{}]", regions[&start].2.buffer)))
+        })
+        .collect::<Vec<_>>();
+    builder.add_file(format!(".{name}.json"), tensor_map(map).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use super::*;
+    use crate::{Archive, ExtractOption};
+
+    enum Field {
+        None,
+        U8(u8),
+        U32(u32),
+        U64(u64),
+        Ref,
+    }
+
+    /// Flatbuffer writer laying tables out before their children, so every
+    /// offset points forward.
+    #[derive(Default)]
+    struct Writer(Vec<u8>);
+
+    impl Writer {
+        fn align(&mut self, n: usize) {
+            self.0.resize(self.0.len().next_multiple_of(n), 0);
+        }
+
+        /// Write a table, returning its position and that of each field.
+        fn table(&mut self, fields: &[Field]) -> (usize, Vec<usize>) {
+            self.align(4);
+            let vtable = self.0.len();
+            self.0.extend((4 + 2 * fields.len() as u16).to_le_bytes());
+            self.0.extend((4 + 8 * fields.len() as u16).to_le_bytes());
+            for (i, f) in fields.iter().enumerate() {
+                let off = if matches!(f, Field::None) {
+                    0
+                } else {
+                    4 + 8 * i as u16
+                };
+                self.0.extend(off.to_le_bytes());
+            }
+            self.align(4);
+            let table = self.0.len();
+            self.0.extend(((table - vtable) as i32).to_le_bytes());
+            let mut slots = vec![];
+            for f in fields {
+                slots.push(self.0.len());
+                let mut v = match *f {
+                    Field::U8(v) => vec![v],
+                    Field::U32(v) => v.to_le_bytes().to_vec(),
+                    Field::U64(v) => v.to_le_bytes().to_vec(),
+                    Field::None | Field::Ref => vec![],
+                };
+                v.resize(8, 0);
+                self.0.extend(v);
+            }
+            (table, slots)
+        }
+
+        /// Write a vector with its elements aligned to `align`.
+        fn vector(&mut self, data: &[u8], n: usize, align: usize) -> usize {
+            self.0
+                .resize((self.0.len() + 4).next_multiple_of(align) - 4, 0);
+            let v = self.0.len();
+            self.0.extend((n as u32).to_le_bytes());
+            self.0.extend(data);
+            v
+        }
+
+        /// Write a vector of `n` offsets, returning their positions.
+        fn refs(&mut self, n: usize) -> (usize, Vec<usize>) {
+            let v = self.vector(&vec![0; 4 * n], n, 4);
+            (v, (0..n).map(|i| v + 4 + 4 * i).collect())
+        }
+
+        fn patch(&mut self, slot: usize, target: usize) {
+            self.0[slot..slot + 4].copy_from_slice(&((target - slot) as u32).to_le_bytes());
+        }
+    }
+
+    /// A model with a 64x64 f32 weight, a small i32 bias and an f16 table
+    /// stored after the flatbuffer.
+    fn model() -> Vec<u8> {
+        let mut w = Writer(vec![0; 4]);
+        w.0.extend(b"TFL3");
+        let (model, m) = w.table(&[
+            Field::U32(3),
+            Field::None,
+            Field::Ref,
+            Field::None,
+            Field::Ref,
+        ]);
+        w.patch(0, model);
+
+        let (v, sg) = w.refs(1);
+        w.patch(m[2], v);
+        let (subgraph, s) = w.table(&[Field::Ref]);
+        w.patch(sg[0], subgraph);
+        let tensors: [(&str, &[i32], u8, u32); 4] = [
+            ("x", &[1, 64], 0, 0),
+            ("w", &[64, 64], 0, 1),
+            ("b", &[2], 2, 2),
+            ("h", &[8192], 1, 3),
+        ];
+        let (v, ts) = w.refs(tensors.len());
+        w.patch(s[0], v);
+        for ((name, shape, ty, buffer), slot) in tensors.into_iter().zip(ts) {
+            let (t, f) = w.table(&[Field::Ref, Field::U8(ty), Field::U32(buffer), Field::Ref]);
+            w.patch(slot, t);
+            let dims = shape
+                .iter()
+                .flat_map(|d| d.to_le_bytes())
+                .collect::<Vec<_>>();
+            let v = w.vector(&dims, shape.len(), 4);
+            w.patch(f[0], v);
+            let v = w.vector(name.as_bytes(), name.len(), 4);
+            w.0.push(0);
+            w.patch(f[3], v);
+        }
+
+        let (v, bs) = w.refs(4);
+        w.patch(m[4], v);
+        let (b, _) = w.table(&[]);
+        w.patch(bs[0], b);
+        let weight = (0..4096)
+            .flat_map(|i| (i as f32 * 0.25).to_le_bytes())
+            .collect::<Vec<_>>();
+        let bias = [7i32, -7]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        for (data, slot) in [(&weight, bs[1]), (&bias, bs[2])] {
+            let (b, f) = w.table(&[Field::Ref]);
+            w.patch(slot, b);
+            let v = w.vector(data, data.len(), 16);
+            w.patch(f[0], v);
+        }
+        let (b, f) = w.table(&[Field::None, Field::U64(0), Field::U64(16384)]);
+        w.patch(bs[3], b);
+        w.align(16);
+        let offset = w.0.len() as u64;
+        w.0[f[1]..f[1] + 8].copy_from_slice(&offset.to_le_bytes());
+        w.0.extend((0..8192).flat_map(|i| half::f16::from_f32(i as f32).to_le_bytes()));
+        w.0
+    }
+
+    #[test]
+    fn roundtrip() {
+        let src = model();
+        let mut buf = Cursor::new(Vec::new());
+        let mut b = Builder::new(&mut buf);
+        add_tflite(
+            &mut b,
+            "m.tflite",
+            Cursor::new(&src),
+            DEFAULT_SIZE_LIMIT,
+            BlobWriteOption::default(),
+        )
+        .unwrap();
+        b.set_contiguous_targets(true);
+        b.finish().unwrap();
+
+        let mut a = Archive::new(Cursor::new(buf.into_inner())).unwrap();
+        let w = a.blob_by_name("m.tflite[1]").unwrap();
+        assert_eq!(w.data_type(), Some(DataType::Float32));
+        assert_eq!(w.shape().into_iter().collect::<Vec<_>>(), [64, 64]);
+        let h = a.blob_by_name("m.tflite[3]").unwrap();
+        assert_eq!(h.data_type(), Some(DataType::Float16));
+        assert!(!a.blob_names().any(|n| n == "m.tflite[2]"));
+        let mut map = String::new();
+        a.file_by_name(".m.tflite.json")
+            .unwrap()
+            .read_to_string(&mut map)
+            .unwrap();
+        assert_eq!(
+            map,
+            r#"{"blobs": {"w": "m.tflite[1]", "h": "m.tflite[3]"}}"#
+        );
+
+        let dir = std::env::temp_dir().join(format!("tsar-tflite-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        a.extract_to(&dir, ExtractOption::default()).unwrap();
+        assert!(fs::read(dir.join("m.tflite")).unwrap() == src);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn malformed_input() {
+        let src = model();
+        for len in [3, 20, 200, src.len() - 20] {
+            let mut b = Builder::new(Cursor::new(Vec::new()));
+            assert!(add_tflite(
+                &mut b,
+                "m",
+                Cursor::new(&src[..len]),
+                DEFAULT_SIZE_LIMIT,
+                BlobWriteOption::default()
+            )
+            .is_err());
+        }
+    }
+}
@@ -0,0 +1,417 @@
+//! PyTorch zip checkpoints, as written by `torch.save`.
+//!
+//! Every storage `data/<key>` becomes a blob `{name}[<key>]` targeting
+//! `{name}/<entry>`, typed from the pickle. A storage viewed by a single
+//! tensor covering all of it gets that tensor's shape; shared and strided
+//! storages keep a flat shape. All other entries, including `data.pkl`, are
+//! stored as raw files under `{name}/`, so extracting gives the unzipped
+//! checkpoint and [`write_pt`] zips it back. `.{name}.json` maps tensor
+//! names to the blobs of their storages.
+
+use std::{
+    collections::HashMap,
+    io::{Read, Seek, Write},
+};
+
+use zip::write::SimpleFileOptions;
+
+use super::{
+    pickle::{self, Value},
+    tensor_map,
+};
+use crate::{
+    result::{Error, Result},
+    Archive, BlobWriteOption, Builder, ByteOrder, DataType,
+};
+
+/// A tensor of the checkpoint, a view into a storage.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tensor {
+    pub name: String,
+    pub storage: String,
+    pub data_type: Option<DataType>,
+    pub storage_len: usize,
+    pub offset: usize,
+    pub shape: Vec<usize>,
+    pub stride: Vec<usize>,
+}
+
+fn storage_type(name: &str) -> Option<DataType> {
+    Some(match name {
+        "FloatStorage" => DataType::Float32,
+        "DoubleStorage" => DataType::Float64,
+        "HalfStorage" => DataType::Float16,
+        "BFloat16Storage" => DataType::Bfloat16,
+        "LongStorage" => DataType::Int64,
+        "IntStorage" => DataType::Int32,
+        "ShortStorage" => DataType::Int16,
+        "CharStorage" => DataType::Int8,
+        "ByteStorage" => DataType::Uint8,
+        "BoolStorage" => DataType::Bool,
+        "ComplexFloatStorage" => DataType::Complex64,
+        "ComplexDoubleStorage" => DataType::Complex128,
+        "Float8_e4m3fnStorage" => DataType::Float8E4M3,
+        "Float8_e5m2Storage" => DataType::Float8E5M2,
+        _ => return None,
+    })
+}
+
+fn usizes(v: &Value) -> Option<Vec<usize>> {
+    v.as_tuple()?
+        .iter()
+        .map(|d| usize::try_from(d.as_int()?).ok())
+        .collect()
+}
+
+/// Interpret `v` as a call to `_rebuild_tensor_v2`, possibly wrapped in
+/// `_rebuild_parameter`.
+fn tensor(name: &str, v: &Value) -> Option<Tensor> {
+    let Value::Call(f, args) = v else {
+        return None;
+    };
+    let args = args.as_tuple()?;
+    if f.is_global("torch._utils", "_rebuild_parameter") {
+        return tensor(name, args.first()?);
+    }
+    if !f.is_global("torch._utils", "_rebuild_tensor_v2") {
+        return None;
+    }
+    let [Value::PersId(pid), offset, shape, stride, ..] = args else {
+        return None;
+    };
+    let [kind, Value::Global(_, ty), key, _location, len] = pid.as_tuple()? else {
+        return None;
+    };
+    if kind.as_str()? != "storage" {
+        return None;
+    }
+    Some(Tensor {
+        name: name.to_owned(),
+        storage: key.as_str()?.to_owned(),
+        data_type: storage_type(ty),
+        storage_len: usize::try_from(len.as_int()?).ok()?,
+        offset: usize::try_from(offset.as_int()?).ok()?,
+        shape: usizes(shape)?,
+        stride: usizes(stride)?,
+    })
+}
+
+/// Collect tensors reachable through string-keyed dicts, naming them by
+/// their dotted key path, `depth` dicts down.
+fn collect(prefix: &str, v: &Value, depth: usize, out: &mut Vec<Tensor>) -> Result<()> {
+    if let Some(t) = tensor(prefix, v) {
+        out.push(t);
+        return Ok(());
+    }
+    if let Value::Dict(items) = v {
+        if depth >= pickle::MAX_DEPTH {
+            return Err(Error::Corrupt("checkpoint dicts nested too deeply"));
+        }
+        for (k, v) in items {
+            let Some(k) = k.as_str() else {
+                continue;
+            };
+            let name = match prefix {
+                "" => k.to_owned(),
+                p => format!("{p}.{k}"),
+            };
+            collect(&name, v, depth + 1, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Parse the tensors out of a `data.pkl` pickle.
+pub fn tensors(pickle: &[u8]) -> Result<Vec<Tensor>> {
+    let mut out = vec![];
+    collect("", &pickle::load(pickle)?, 0, &mut out)?;
+    Ok(out)
+}
+
+fn is_contiguous(t: &Tensor) -> bool {
+    let mut expected = 1usize;
+    for (&d, &s) in t.shape.iter().zip(&t.stride).rev() {
+        if d != 1 && s != expected {
+            return false;
+        }
+        let Some(e) = expected.checked_mul(d) else {
+            return false;
+        };
+        expected = e;
+    }
+    t.offset == 0 && expected == t.storage_len
+}
+
+/// Bytes of a storage of `len` elements, unless that overflows.
+fn storage_bytes(dt: DataType, len: usize) -> Option<usize> {
+    len.checked_mul(dt.bit_len()).map(|bits| bits.div_ceil(8))
+}
+
+/// Add the checkpoint in `reader`, to be written back as `name`.
+pub fn add_pt<W: Write + Seek>(
+    builder: &mut Builder<W>,
+    name: &str,
+    reader: impl Read + Seek,
+    opt: BlobWriteOption,
+) -> Result<()> {
+    let mut z = zip::ZipArchive::new(reader)?;
+    let pkl = z
+        .file_names()
+        .find(|n| n.ends_with("/data.pkl"))
+        .ok_or(Error::Corrupt("no data.pkl in checkpoint"))?
+        .to_owned();
+    let prefix = pkl.strip_suffix("data.pkl").unwrap().to_owned();
+    let mut buf = vec![];
+    z.by_name(&pkl)?.read_to_end(&mut buf)?;
+    let tensors = tensors(&buf)?;
+
+    let mut byte_order = ByteOrder::Little;
+    if let Ok(mut f) = z.by_name(&format!("{prefix}byteorder")) {
+        let mut s = String::new();
+        f.read_to_string(&mut s)?;
+        if s.trim() == "big" {
+            byte_order = ByteOrder::Big;
+        }
+    }
+
+    let mut views = HashMap::<&str, Vec<&Tensor>>::new();
+    for t in &tensors {
+        views.entry(&t.storage).or_default().push(t);
+    }
+
+    for i in 0..z.len() {
+        let mut f = z.by_index(i)?;
+        if f.is_dir() {
+            continue;
+        }
+        let entry = f.name().to_owned();
+        let file = format!("{name}/{entry}");
+        let key = entry
+            .strip_prefix(&prefix)
+            .and_then(|e| e.strip_prefix("data/"));
+        let Some(v) = key.and_then(|k| views.get(k)) else {
+            builder.add_file(file, f)?;
+            continue;
+        };
+
+        buf.clear();
+        f.read_to_end(&mut buf)?;
+        let t = v[0];
+        let opt = BlobWriteOption {
+            target_file: Some((file, 0)),
+            byte_order,
+            ..opt.clone()
+        };
+        let blob = format!("{name}[{}]", t.storage);
+        let (dt, shape) = match t.data_type {
+            Some(dt) if storage_bytes(dt, t.storage_len) == Some(buf.len()) => {
+                if v.len() == 1 && is_contiguous(t) {
+                    (dt, t.shape.clone())
+                } else {
+                    (dt, vec![t.storage_len])
+                }
+            }
+            _ => (DataType::Byte, vec![buf.len()]),
+        };
+        builder.add_blob(blob, &buf, dt, &shape, opt)?;
+    }
+
+    let map = tensors
+        .iter()
+        .map(|t| (&t.name, format!("{name}[{}]", t.storage)));
+    builder.add_file(format!(".{name}.json"), tensor_map(map).as_bytes())
+}
+
+/// Write the checkpoint added as `name` back as a zip file.
+pub fn write_pt<R: Read + Seek>(
+    archive: &mut Archive<R>,
+    name: &str,
+    w: impl Write + Seek,
+) -> Result<()> {
+    let dir = format!("{name}/");
+    let opt = SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(true);
+    let mut z = zip::ZipWriter::new(w);
+
+    let files = archive
+        .file_names()
+        .filter(|f| f.starts_with(&dir))
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    for f in files {
+        z.start_file(&f[dir.len()..], opt)?;
+        std::io::copy(&mut archive.file_by_name(&f)?, &mut z)?;
+    }
+
+    let blobs = archive
+        .blob_names()
+        .filter(|b| b.starts_with(name) && b[name.len()..].starts_with('['))
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    for b in blobs {
+        let mut b = archive.blob_by_name(b)?;
+        let Some(entry) = b
+            .target_file()
+            .and_then(|(f, _)| f.strip_prefix(&dir))
+            .map(str::to_owned)
+        else {
+            continue;
+        };
+        // storages are memory-mapped by `torch.load(mmap=True)`
+        z.start_file(entry, opt.with_alignment(64))?;
+        std::io::copy(&mut b, &mut z)?;
+    }
+    z.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Name, storage key, offset, shape and stride of a tensor.
+    type View<'a> = (&'a str, &'a str, u8, &'a [u8], &'a [u8]);
+
+    /// Protocol 2 pickle of `{name: tensor}` as `torch.save` writes it.
+    fn state_dict(tensors: &[View]) -> Vec<u8> {
+        fn s(p: &mut Vec<u8>, v: &str) {
+            p.push(b'X');
+            p.extend((v.len() as u32).to_le_bytes());
+            p.extend(v.as_bytes());
+        }
+        fn ints(p: &mut Vec<u8>, v: &[u8]) {
+            p.push(b'(');
+            v.iter().for_each(|&i| p.extend([b'K', i]));
+            p.push(b't');
+        }
+        let mut p = b"\x80\x02ccollections\nOrderedDict\nq\x00)Rq\x01(".to_vec();
+        for (name, key, offset, shape, stride) in tensors {
+            s(&mut p, name);
+            p.extend(b"ctorch._utils\n_rebuild_tensor_v2\n((");
+            s(&mut p, "storage");
+            p.extend(b"ctorch\nFloatStorage\n");
+            s(&mut p, key);
+            s(&mut p, "cpu");
+            p.extend([b'K', 6, b't', b'Q', b'K', *offset]);
+            ints(&mut p, shape);
+            ints(&mut p, stride);
+            p.extend(b"\x89h\x00)Rtq\x02R");
+        }
+        p.extend(b"u}q\x03bh\x03\x85b.");
+        p
+    }
+
+    #[test]
+    fn parse() {
+        let t = tensors(&state_dict(&[
+            ("w", "0", 0, &[2, 3], &[3, 1]),
+            ("wt", "0", 0, &[3, 2], &[1, 3]),
+        ]))
+        .unwrap();
+        assert_eq!(t.len(), 2);
+        assert_eq!(t[0].data_type, Some(DataType::Float32));
+        assert_eq!(t[1].name, "wt");
+        assert_eq!(t[1].stride, [1, 3]);
+        assert!(is_contiguous(&t[0]) && !is_contiguous(&t[1]));
+        assert!(tensors(b"\x80\x02(").is_err());
+
+        let huge = Tensor {
+            shape: vec![usize::MAX, 2],
+            stride: vec![2, 1],
+            ..t[0].clone()
+        };
+        assert!(!is_contiguous(&huge));
+    }
+
+    #[test]
+    fn huge_storage() {
+        // a storage length whose byte size overflows is stored as bytes
+        let mut pkl = state_dict(&[("w", "0", 0, &[2, 3], &[3, 1])]);
+        let at = pkl.windows(4).position(|w| w == b"K\x06tQ").unwrap();
+        let mut len = b"\x8a\x08".to_vec();
+        len.extend(i64::MAX.to_le_bytes());
+        pkl.splice(at..at + 2, len);
+        assert_eq!(tensors(&pkl).unwrap()[0].storage_len, i64::MAX as usize);
+
+        let mut pt = Cursor::new(Vec::new());
+        let mut z = zip::ZipWriter::new(&mut pt);
+        for (name, data) in [("archive/data.pkl", pkl), ("archive/data/0", vec![0; 24])] {
+            z.start_file(name, SimpleFileOptions::default()).unwrap();
+            z.write_all(&data).unwrap();
+        }
+        z.finish().unwrap();
+
+        let mut buf = Cursor::new(Vec::new());
+        let mut b = Builder::new(&mut buf);
+        pt.set_position(0);
+        add_pt(&mut b, "m.pt", pt, BlobWriteOption::default()).unwrap();
+        b.finish().unwrap();
+        let mut a = Archive::new(Cursor::new(buf.into_inner())).unwrap();
+        let blob = a.blob_by_name("m.pt[0]").unwrap();
+        assert_eq!(blob.data_type(), Some(DataType::Byte));
+    }
+
+    #[test]
+    fn roundtrip() {
+        let floats = |k: f32| {
+            (0..6)
+                .flat_map(|i| (i as f32 * k).to_le_bytes())
+                .collect::<Vec<_>>()
+        };
+        let entries: [(&str, Vec<u8>); 5] = [
+            (
+                "archive/data.pkl",
+                state_dict(&[
+                    ("a.weight", "0", 0, &[2, 3], &[3, 1]),
+                    ("b.weight", "1", 0, &[3, 2], &[1, 3]),
+                    ("b.tied", "1", 0, &[3, 2], &[1, 3]),
+                ]),
+            ),
+            ("archive/byteorder", b"little".to_vec()),
+            ("archive/data/0", floats(0.5)),
+            ("archive/data/1", floats(-1.0)),
+            ("archive/version", b"3\n".to_vec()),
+        ];
+        let mut pt = Cursor::new(Vec::new());
+        let mut z = zip::ZipWriter::new(&mut pt);
+        for (name, data) in &entries {
+            z.start_file(*name, SimpleFileOptions::default()).unwrap();
+            z.write_all(data).unwrap();
+        }
+        z.finish().unwrap();
+
+        let mut buf = Cursor::new(Vec::new());
+        let mut b = Builder::new(&mut buf);
+        pt.set_position(0);
+        add_pt(&mut b, "model.pt", pt, BlobWriteOption::default()).unwrap();
+        b.finish().unwrap();
+
+        let mut a = Archive::new(Cursor::new(buf.into_inner())).unwrap();
+        let shape = |a: &mut Archive<_>, n| {
+            a.blob_by_name(n)
+                .unwrap()
+                .shape()
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(shape(&mut a, "model.pt[0]"), [2, 3]);
+        assert_eq!(shape(&mut a, "model.pt[1]"), [6]);
+
+        let mut out = Cursor::new(Vec::new());
+        write_pt(&mut a, "model.pt", &mut out).unwrap();
+        let mut z = zip::ZipArchive::new(out).unwrap();
+        assert_eq!(z.len(), entries.len());
+        for (name, data) in &entries {
+            let mut back = vec![];
+            let mut f = z.by_name(name).unwrap();
+            f.read_to_end(&mut back).unwrap();
+            assert_eq!(&back, data, "{name}");
+            if name.contains("/data/") {
+                assert_eq!(f.data_start().unwrap() % 64, 0, "{name}");
+            }
+        }
+    }
+}
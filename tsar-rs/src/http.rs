@@ -0,0 +1,189 @@
+use std::io::{self, Read};
+
+use crate::range::{RangeReader, RangeSource};
+
+/// [`RangeSource`] backed by HTTP range requests against a single URL.
+pub struct HttpSource {
+    agent: ureq::Agent,
+    url: String,
+}
+
+pub type HttpReader = RangeReader<HttpSource>;
+
+impl HttpSource {
+    pub fn new(url: impl Into<String>) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .build()
+            .into();
+        Self {
+            agent,
+            url: url.into(),
+        }
+    }
+
+    fn get(&self, range: &str) -> io::Result<ureq::http::Response<ureq::Body>> {
+        self.agent
+            .get(&self.url)
+            .header("Range", range)
+            .call()
+            .map_err(ureq::Error::into_io)
+    }
+}
+
+impl HttpReader {
+    pub fn open(url: impl Into<String>) -> io::Result<Self> {
+        Self::new(HttpSource::new(url))
+    }
+}
+
+fn content_range_total(resp: &ureq::http::Response<ureq::Body>) -> Option<u64> {
+    let v = resp.headers().get("content-range")?.to_str().ok()?;
+    v.rsplit_once('/')?.1.trim().parse().ok()
+}
+
+impl RangeSource for HttpSource {
+    fn byte_len(&mut self) -> io::Result<u64> {
+        // a ranged GET works with pre-signed URLs that do not permit HEAD
+        let resp = self.get("bytes=0-0")?;
+        match resp.status().as_u16() {
+            206 | 416 => content_range_total(&resp).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "missing Content-Range total")
+            }),
+            200 => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "server does not support range requests",
+            )),
+            s => Err(io::Error::other(format!("HTTP status {s}"))),
+        }
+    }
+
+    fn read_range(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let last = offset + buf.len() as u64 - 1;
+        let resp = self.get(&format!("bytes={offset}-{last}"))?;
+        if resp.status().as_u16() != 206 {
+            return Err(io::Error::other(format!(
+                "unexpected HTTP status {} for range request",
+                resp.status()
+            )));
+        }
+        resp.into_body().into_reader().read_exact(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Cursor, Write},
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use super::*;
+    use crate::{Archive, BlobWriteOption, Builder, DataType};
+
+    /// Minimal HTTP/1.1 server that answers ranged GET requests for `data`
+    /// and counts the payload bytes it has sent.
+    fn serve(data: Vec<u8>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let sent = Arc::new(AtomicUsize::new(0));
+        let counter = sent.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut range = None;
+                let mut rdr = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut line = String::new();
+                    if rdr.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                        break;
+                    }
+                    let lower = line.to_ascii_lowercase();
+                    if let Some(r) = lower.strip_prefix("range: bytes=") {
+                        let (s, e) = r.trim().split_once('-').unwrap();
+                        range = Some((s.parse::<usize>().unwrap(), e.parse::<usize>().unwrap()));
+                    }
+                }
+
+                let (s, e) = range.unwrap();
+                if s >= data.len() {
+                    write!(
+                        stream,
+                        "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        data.len()
+                    )
+                    .unwrap();
+                    continue;
+                }
+                let e = e.min(data.len() - 1);
+                write!(
+                    stream,
+                    "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {s}-{e}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    data.len(),
+                    e - s + 1
+                )
+                .unwrap();
+                stream.write_all(&data[s..=e]).unwrap();
+                counter.fetch_add(e - s + 1, Ordering::SeqCst);
+            }
+        });
+        (format!("http://{addr}/model.tsar"), sent)
+    }
+
+    #[test]
+    fn range_read() {
+        let data = (0..300_000).map(|i| (i % 253) as u8).collect::<Vec<_>>();
+        let (url, sent) = serve(data.clone());
+        let mut r = HttpReader::open(url).unwrap();
+        let mut out = vec![];
+        r.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+        assert_eq!(r.fetched_bytes(), data.len() as u64);
+        assert_eq!(sent.load(Ordering::SeqCst), data.len() + 1);
+    }
+
+    #[test]
+    fn remote_archive() {
+        let mut buf = Cursor::new(Vec::new());
+        let mut b = Builder::new(&mut buf);
+        let mut blobs = vec![];
+        for i in 0..8u32 {
+            let d = (0..256 * 1024u32)
+                .flat_map(|v| (v.wrapping_mul(2_654_435_761) ^ i).to_le_bytes())
+                .collect::<Vec<_>>();
+            b.add_blob(
+                format!("blob{i}"),
+                &d,
+                DataType::Byte,
+                &[d.len()],
+                BlobWriteOption::default(),
+            )
+            .unwrap();
+            blobs.push(d);
+        }
+        b.finish().unwrap();
+        let total = buf.get_ref().len() as u64;
+
+        let (url, sent) = serve(buf.into_inner());
+        let mut a = Archive::new(HttpReader::open(url).unwrap()).unwrap();
+        let opened = sent.load(Ordering::SeqCst);
+        assert!((opened as u64) < total / 8, "{opened}");
+
+        let mut out = vec![];
+        a.blob_by_name("blob3")
+            .unwrap()
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, blobs[3]);
+        let fetched = sent.load(Ordering::SeqCst) - opened;
+        // read-ahead over-fetches at most the size of the entry itself
+        assert!(fetched <= 2 * blobs[3].len() + 64 * 1024, "{fetched}");
+    }
+}
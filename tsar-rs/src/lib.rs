@@ -1,9 +1,21 @@
 #![allow(dead_code)]
 
+// public with the `bench` feature for the benches and fuzz targets only, not
+// a stable API
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod codec;
+#[cfg(not(feature = "bench"))]
 mod codec;
 mod compress;
 mod data_type;
+mod format;
+pub mod formats;
+#[cfg(feature = "http")]
+mod http;
+mod metadata;
 mod paths;
+mod range;
 mod read;
 mod result;
 mod write;
@@ -12,8 +24,15 @@ mod pbgen {
     include!(concat!(env!("OUT_DIR"), "/pb/mod.rs"));
 }
 
-pub use data_type::DataType;
+pub use data_type::{ByteOrder, DataType};
+pub use format::FORMAT_VERSION;
+#[cfg(feature = "http")]
+pub use http::{HttpReader, HttpSource};
+pub use metadata::{Metadata, MetadataValue};
 pub use pbgen::tsar as pb;
-pub use read::{Archive, Blob};
+pub use range::{RangeReadOption, RangeReader, RangeSource};
+pub use read::{Archive, Blob, ExtractOption, Overwrite, ReadLimits};
+#[cfg(feature = "async")]
+pub use read::{AsyncArchive, AsyncBlob};
 pub use result::{Error, Result};
-pub use write::{BlobWriteOption, Builder};
+pub use write::{BlobWriteOption, BlobWriter, Builder, Region, StridedView};
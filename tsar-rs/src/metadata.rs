@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+
+use crate::pb;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetadataValue {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Bytes(Vec<u8>),
+}
+
+pub type Metadata = BTreeMap<String, MetadataValue>;
+
+impl From<MetadataValue> for pb::MetadataValue {
+    fn from(v: MetadataValue) -> Self {
+        use pb::metadata_value::Value;
+        pb::MetadataValue {
+            value: Some(match v {
+                MetadataValue::String(v) => Value::StringValue(v),
+                MetadataValue::Int(v) => Value::IntValue(v),
+                MetadataValue::Float(v) => Value::FloatValue(v),
+                MetadataValue::Bool(v) => Value::BoolValue(v),
+                MetadataValue::Bytes(v) => Value::BytesValue(v),
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+impl TryFrom<&pb::MetadataValue> for MetadataValue {
+    type Error = ();
+
+    fn try_from(v: &pb::MetadataValue) -> Result<Self, Self::Error> {
+        use pb::metadata_value::Value;
+        match v.value.as_ref().ok_or(())? {
+            Value::StringValue(v) => Ok(MetadataValue::String(v.clone())),
+            Value::IntValue(v) => Ok(MetadataValue::Int(*v)),
+            Value::FloatValue(v) => Ok(MetadataValue::Float(*v)),
+            Value::BoolValue(v) => Ok(MetadataValue::Bool(*v)),
+            Value::BytesValue(v) => Ok(MetadataValue::Bytes(v.clone())),
+        }
+    }
+}
+
+macro_rules! impl_from {
+    ($($ty:ty => $variant:ident),* $(,)*) => {
+        $(
+            impl From<$ty> for MetadataValue {
+                fn from(v: $ty) -> Self {
+                    MetadataValue::$variant(v.into())
+                }
+            }
+        )*
+    };
+}
+
+impl_from!(
+    String => String,
+    &str => String,
+    i64 => Int,
+    i32 => Int,
+    u32 => Int,
+    f64 => Float,
+    f32 => Float,
+    bool => Bool,
+    Vec<u8> => Bytes,
+    &[u8] => Bytes,
+);
+
+pub(crate) fn to_pb(m: Metadata) -> Vec<pb::MetadataEntry> {
+    m.into_iter().map(|(k, v)| entry(k, v)).collect()
+}
+
+pub(crate) fn entry(key: String, value: MetadataValue) -> pb::MetadataEntry {
+    pb::MetadataEntry {
+        key,
+        value: Some(value.into()).into(),
+        ..Default::default()
+    }
+}
+
+/// Convert stored entries, the last of a repeated key winning as in a
+/// protobuf map, and dropping values written by a newer version that this
+/// reader cannot represent.
+pub(crate) fn from_pb(m: &[pb::MetadataEntry]) -> Metadata {
+    m.iter()
+        .filter_map(|e| Some((e.key.clone(), e.value.as_ref()?.try_into().ok()?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let mut m = Metadata::new();
+        m.insert("role".into(), "attention.q".into());
+        m.insert("step".into(), 1200i64.into());
+        m.insert("scale".into(), 0.5f32.into());
+        m.insert("quantized".into(), false.into());
+        m.insert("sha1".into(), (&[1u8, 2, 3][..]).into());
+
+        let mut p = to_pb(m.clone());
+        assert_eq!(from_pb(&p), m);
+
+        p.push(pb::MetadataEntry {
+            key: "unset".into(),
+            value: Some(pb::MetadataValue::new()).into(),
+            ..Default::default()
+        });
+        assert_eq!(from_pb(&p), m);
+        p.push(entry("step".into(), 1300i64.into()));
+        assert_eq!(from_pb(&p)["step"], MetadataValue::Int(1300));
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Read, Seek, SeekFrom},
+};
+
+/// A random-access byte source that can only be read in whole ranges,
+/// e.g. an object behind HTTP range requests.
+pub trait RangeSource {
+    /// Total length of the source in bytes.
+    fn byte_len(&mut self) -> io::Result<u64>;
+
+    /// Fill `buf` with the bytes starting at `offset`.
+    fn read_range(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()>;
+}
+
+pub struct RangeReadOption {
+    /// Size of a cached block; every request is aligned to it.
+    pub block_size: u64,
+    /// Maximum number of blocks kept in the cache.
+    pub cache_blocks: usize,
+    /// Upper bound of the read-ahead window for sequential reads.
+    pub max_readahead: u64,
+}
+
+impl Default for RangeReadOption {
+    fn default() -> Self {
+        Self {
+            block_size: 64 * 1024,
+            cache_blocks: 256,
+            max_readahead: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// `Read + Seek` adapter over a [`RangeSource`] with a block cache.
+///
+/// Adjacent missing blocks are coalesced into a single request, and the
+/// request grows with the length of the current sequential run so that large
+/// chunk entries are fetched with only a few round trips.
+pub struct RangeReader<S: RangeSource> {
+    src: S,
+    opt: RangeReadOption,
+    len: u64,
+    pos: u64,
+    cache: HashMap<u64, Vec<u8>>,
+    lru: VecDeque<u64>,
+    run_start: u64,
+    last_end: u64,
+    requests: usize,
+    fetched: u64,
+}
+
+impl<S: RangeSource> RangeReader<S> {
+    pub fn new(src: S) -> io::Result<Self> {
+        Self::with_option(src, RangeReadOption::default())
+    }
+
+    pub fn with_option(mut src: S, opt: RangeReadOption) -> io::Result<Self> {
+        if opt.block_size == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "range read block size must be non-zero",
+            ));
+        }
+        let len = src.byte_len()?;
+        Ok(Self {
+            src,
+            opt,
+            len,
+            pos: 0,
+            cache: HashMap::new(),
+            lru: VecDeque::new(),
+            run_start: 0,
+            last_end: u64::MAX,
+            requests: 0,
+            fetched: 0,
+        })
+    }
+
+    /// Number of range requests issued so far.
+    pub fn request_count(&self) -> usize {
+        self.requests
+    }
+
+    /// Number of bytes fetched from the source so far.
+    pub fn fetched_bytes(&self) -> u64 {
+        self.fetched
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.src
+    }
+
+    pub fn into_inner(self) -> S {
+        self.src
+    }
+
+    fn num_blocks(&self) -> u64 {
+        self.len.div_ceil(self.opt.block_size)
+    }
+
+    fn block_range(&self, blk: u64) -> (u64, u64) {
+        let start = blk * self.opt.block_size;
+        (start, (start + self.opt.block_size).min(self.len))
+    }
+
+    fn touch(&mut self, blk: u64) {
+        if let Some(i) = self.lru.iter().position(|&b| b == blk) {
+            self.lru.remove(i);
+        }
+        self.lru.push_back(blk);
+    }
+
+    fn insert(&mut self, blk: u64, data: Vec<u8>) {
+        self.cache.insert(blk, data);
+        self.touch(blk);
+        while self.lru.len() > self.opt.cache_blocks.max(1) {
+            if let Some(old) = self.lru.pop_front() {
+                self.cache.remove(&old);
+            }
+        }
+    }
+
+    /// Fetch the missing blocks starting at `first`, covering at least up to
+    /// block `last` plus a read-ahead window as large as the current
+    /// sequential run, so over-fetching stays proportional to what is read.
+    fn fetch(&mut self, first: u64, last: u64) -> io::Result<()> {
+        let readahead = (self.pos - self.run_start).min(self.opt.max_readahead);
+        let want = last + readahead / self.opt.block_size;
+        let mut end = first;
+        let cap = first + self.opt.cache_blocks.max(1) as u64;
+        while end < self.num_blocks().min(cap) && end <= want && !self.cache.contains_key(&end) {
+            end += 1;
+        }
+
+        let start = self.block_range(first).0;
+        let stop = self.block_range(end - 1).1;
+        let mut buf = vec![0u8; (stop - start) as usize];
+        self.src.read_range(start, &mut buf)?;
+        self.requests += 1;
+        self.fetched += buf.len() as u64;
+
+        for (i, blk) in (first..end).enumerate() {
+            let off = i * self.opt.block_size as usize;
+            let (s, e) = self.block_range(blk);
+            self.insert(blk, buf[off..off + (e - s) as usize].to_vec());
+        }
+        Ok(())
+    }
+}
+
+impl<S: RangeSource> Read for RangeReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.pos != self.last_end {
+            self.run_start = self.pos;
+        }
+
+        let bs = self.opt.block_size;
+        let end = (self.pos + buf.len() as u64).min(self.len);
+        let mut n = 0;
+        while self.pos < end {
+            let blk = self.pos / bs;
+            if !self.cache.contains_key(&blk) {
+                self.fetch(blk, (end - 1) / bs)?;
+            } else {
+                self.touch(blk);
+            }
+            let data = &self.cache[&blk];
+            let off = (self.pos - blk * bs) as usize;
+            let len = data
+                .len()
+                .saturating_sub(off)
+                .min((end - self.pos) as usize);
+            buf[n..n + len].copy_from_slice(&data[off..off + len]);
+            n += len;
+            self.pos += len as u64;
+        }
+        self.last_end = self.pos;
+        Ok(n)
+    }
+}
+
+impl<S: RangeSource> Seek for RangeReader<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(p) => self.len.checked_add_signed(p),
+            SeekFrom::Current(p) => self.pos.checked_add_signed(p),
+        };
+        match pos {
+            Some(p) => {
+                self.pos = p;
+                Ok(p)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Mem(Vec<u8>);
+
+    impl RangeSource for Mem {
+        fn byte_len(&mut self) -> io::Result<u64> {
+            Ok(self.0.len() as u64)
+        }
+
+        fn read_range(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+            let offset = offset as usize;
+            buf.copy_from_slice(&self.0[offset..offset + buf.len()]);
+            Ok(())
+        }
+    }
+
+    fn reader(len: usize, block_size: u64) -> RangeReader<Mem> {
+        let data = (0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        RangeReader::with_option(
+            Mem(data),
+            RangeReadOption {
+                block_size,
+                cache_blocks: 4,
+                max_readahead: 4 * block_size,
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn zero_block_size() {
+        let opt = RangeReadOption {
+            block_size: 0,
+            ..Default::default()
+        };
+        let err = RangeReader::with_option(Mem(vec![0; 10]), opt)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn read_seek() {
+        let mut r = reader(1000, 16);
+        let mut buf = vec![0; 40];
+        r.seek(SeekFrom::Start(990)).unwrap();
+        assert_eq!(r.read(&mut buf).unwrap(), 10);
+        assert_eq!(
+            &buf[..10],
+            &(990..1000).map(|i| (i % 251) as u8).collect::<Vec<_>>()
+        );
+        assert_eq!(r.read(&mut buf).unwrap(), 0);
+
+        r.seek(SeekFrom::Start(5)).unwrap();
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, (5..45).map(|i| (i % 251) as u8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn coalesce_and_cache() {
+        let mut r = reader(1000, 16);
+        let mut buf = vec![0; 40];
+        r.seek(SeekFrom::Start(5)).unwrap();
+        r.read_exact(&mut buf).unwrap();
+        // blocks 0..=2 are fetched in one request
+        assert_eq!(r.request_count(), 1);
+        assert_eq!(r.fetched_bytes(), 48);
+
+        r.seek(SeekFrom::Start(20)).unwrap();
+        r.read_exact(&mut buf[..20]).unwrap();
+        assert_eq!(r.request_count(), 1);
+    }
+
+    #[test]
+    fn sequential_readahead() {
+        let mut r = reader(1000, 16);
+        let mut buf = vec![0; 16];
+        for _ in 0..20 {
+            r.read_exact(&mut buf).unwrap();
+        }
+        assert!(r.request_count() < 10, "{}", r.request_count());
+        assert_eq!(r.stream_position().unwrap(), 320);
+    }
+}
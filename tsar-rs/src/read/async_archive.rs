@@ -0,0 +1,400 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures_util::future::try_join_all;
+use protobuf::Message;
+use tokio::{
+    io::{AsyncRead, AsyncSeek, ReadBuf},
+    sync::Mutex,
+    task::JoinHandle,
+};
+use zip::result::ZipError;
+
+use super::{
+    blob_size, chunk_ids, decode, decode_block, split_blocks, zip_index::ZipIndex, Blob, BlobState,
+    Block, ContextPool, ReadLimits,
+};
+use crate::{
+    codec::BufferList,
+    compress, format, metadata, paths, pb,
+    result::{Error, Result},
+    ByteOrder, DataType, Metadata,
+};
+
+/// Asynchronous counterpart of [`Archive`](super::Archive).
+///
+/// The archive can be backed by several independent readers over the same
+/// file; the chunks of a blob are then fetched concurrently, one reader per
+/// in-flight request.
+pub struct AsyncArchive<R: AsyncRead + AsyncSeek + Unpin> {
+    readers: Vec<Mutex<R>>,
+    index: ZipIndex,
+    meta: pb::Bundle,
+    ctx: ContextPool,
+    limits: ReadLimits,
+    decoded: std::sync::Mutex<u64>,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncArchive<R> {
+    pub async fn new(reader: R) -> Result<Self> {
+        Self::with_readers([reader]).await
+    }
+
+    /// Open the archive over several readers of the same underlying file.
+    pub async fn with_readers(readers: impl IntoIterator<Item = R>) -> Result<Self> {
+        let readers = readers.into_iter().map(Mutex::new).collect::<Vec<_>>();
+        let Some(first) = readers.first() else {
+            return Err(Error::Unsupported(
+                "opening an archive without readers".into(),
+            ));
+        };
+
+        let mut r = first.lock().await;
+        let index = ZipIndex::read(&mut *r).await?;
+        let meta = index
+            .get(paths::BUNDLE_META_PATH)
+            .ok_or(ZipError::FileNotFound)?
+            .read(&mut *r)
+            .await?;
+        let meta = pb::Bundle::parse_from_bytes(&meta)?;
+        format::check(&meta)?;
+        drop(r);
+
+        Ok(Self {
+            readers,
+            index,
+            meta,
+            ctx: ContextPool::default(),
+            limits: ReadLimits::default(),
+            decoded: std::sync::Mutex::new(0),
+        })
+    }
+
+    /// Limit what reading blobs may allocate from now on, as
+    /// [`Archive::set_limits`](super::Archive::set_limits) does.
+    pub fn set_limits(&mut self, limits: ReadLimits) {
+        self.limits = limits;
+    }
+
+    /// Most bytes of decompression scratch buffers held at once by the blobs
+    /// read so far, summed over concurrent decodes.
+    pub fn peak_memory(&self) -> usize {
+        self.ctx.peak_bytes()
+    }
+
+    /// Format version the archive was written with, as
+    /// [`Archive::format_version`](super::Archive::format_version).
+    pub fn format_version(&self) -> (u32, u32) {
+        (self.meta.format_major, self.meta.format_minor)
+    }
+
+    pub fn file_names(&self) -> impl Iterator<Item = &str> {
+        self.meta.raw_files.iter().map(|f| f.name.as_str())
+    }
+
+    pub fn blob_names(&self) -> impl Iterator<Item = &str> {
+        self.meta.blobs.iter().map(|f| f.name.as_str())
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        metadata::from_pb(&self.meta.metadata)
+    }
+
+    pub fn file_metadata(&self, name: impl AsRef<str>) -> Option<Metadata> {
+        let name = name.as_ref();
+        self.meta
+            .raw_files
+            .iter()
+            .find(|f| f.name == name)
+            .map(|f| metadata::from_pb(&f.metadata))
+    }
+
+    pub async fn file_by_name(&self, name: impl AsRef<str>) -> Result<Vec<u8>> {
+        self.read_entry(name.as_ref(), 0).await
+    }
+
+    pub async fn blob_by_name(&self, name: impl AsRef<str>) -> Result<AsyncBlob> {
+        let name = name.as_ref();
+        let b = self
+            .meta
+            .blobs
+            .iter()
+            .find(|&b| b.name == name)
+            .ok_or(ZipError::FileNotFound)?;
+        let len = self.limits.check_blob(b)?;
+        {
+            let mut decoded = self.decoded.lock().unwrap();
+            *decoded = self.limits.archive_total(*decoded, len)?;
+        }
+        let max = compress::max_encoded_len(len) as u64;
+        for c in chunk_ids(b) {
+            let e = self.index.get(&paths::chunk_path(c));
+            if e.is_some_and(|e| e.uncompressed_size > max || e.compressed_size > max) {
+                return Err(Error::Corrupt("chunk larger than its blob"));
+            }
+        }
+
+        let chunks = try_join_all(
+            chunk_ids(b)
+                .enumerate()
+                .map(|(i, c)| async move { self.read_entry(&paths::chunk_path(c), i).await }),
+        )
+        .await?;
+
+        let mut bb = BufferList::new();
+        bb.reset(chunks.len());
+        for (dst, c) in bb.iter_mut().zip(chunks) {
+            *dst = c;
+        }
+        Ok(AsyncBlob {
+            blob: Blob::new(b.clone(), bb, self.ctx.clone()),
+            blocks: VecDeque::new(),
+            pending: None,
+        })
+    }
+
+    async fn read_entry(&self, name: &str, slot: usize) -> Result<Vec<u8>> {
+        let e = self.index.get(name).ok_or(ZipError::FileNotFound)?;
+        let mut r = self.readers[slot % self.readers.len()].lock().await;
+        e.read(&mut *r).await
+    }
+}
+
+/// Blob read from an [`AsyncArchive`]; decoding runs on the blocking pool on
+/// the first read.
+///
+/// Blobs written through a [`BlobWriter`](crate::BlobWriter) are decoded one
+/// block at a time, the next while the current one is read, so at most two
+/// blocks are held in memory. Other blobs are decoded whole.
+pub struct AsyncBlob {
+    blob: Blob,
+    /// Blocks left to decode once reading has started.
+    blocks: VecDeque<Block>,
+    pending: Option<JoinHandle<Result<Vec<u8>>>>,
+}
+
+impl AsyncBlob {
+    pub fn target_file(&self) -> Option<(&str, u64)> {
+        self.blob.target_file()
+    }
+
+    pub fn name(&self) -> &str {
+        self.blob.name()
+    }
+
+    pub fn byte_len(&self) -> Option<usize> {
+        self.blob.byte_len()
+    }
+
+    pub fn data_type(&self) -> Option<DataType> {
+        self.blob.data_type()
+    }
+
+    pub fn shape(&self) -> impl IntoIterator<Item = usize> + '_ {
+        self.blob.shape()
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        self.blob.metadata()
+    }
+
+    pub fn source_byte_order(&self) -> ByteOrder {
+        self.blob.source_byte_order()
+    }
+
+    pub fn byte_order(&self) -> ByteOrder {
+        self.blob.byte_order()
+    }
+
+    /// See [`Blob::set_byte_order`]; this also fails while the first read
+    /// is decoding.
+    pub fn set_byte_order(&mut self, order: ByteOrder) -> Result<()> {
+        self.blob.set_byte_order(order)
+    }
+
+    /// Wrap this blob as a blocking [`Blob`], e.g. to hand it to code that
+    /// already runs on a blocking thread, which fails once reading has started.
+    pub fn into_blocking(self) -> Result<Blob> {
+        if !matches!(self.blob.state, BlobState::Chunks(_)) {
+            return Err(Error::Unsupported("converting a blob after reading".into()));
+        }
+        Ok(self.blob)
+    }
+
+    /// Start decoding the next block, if any, on the blocking pool.
+    fn spawn_next(&mut self) -> Result<()> {
+        let Some(b) = self.blocks.pop_front() else {
+            return Ok(());
+        };
+        let (dt, _) = blob_size(&self.blob.meta)?;
+        let order = self.blob.byte_order;
+        let pool = self.blob.ctx.clone();
+        self.pending = Some(tokio::task::spawn_blocking(move || {
+            pool.with(|ctx| decode_block(ctx, dt, b, order))
+        }));
+        Ok(())
+    }
+
+    /// Start decoding on the first read.
+    fn start(&mut self, chunks: BufferList) -> Result<()> {
+        if !self.blob.meta.blocks.is_empty() {
+            self.blocks = split_blocks(&self.blob.meta, chunks)?.into();
+            return self.spawn_next();
+        }
+        let meta = self.blob.meta.clone();
+        let order = self.blob.byte_order;
+        let pool = self.blob.ctx.clone();
+        self.pending = Some(tokio::task::spawn_blocking(move || {
+            pool.with(|ctx| decode(ctx, &meta, chunks, order))
+        }));
+        Ok(())
+    }
+}
+
+impl AsyncRead for AsyncBlob {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            match &mut this.blob.state {
+                BlobState::Uncompressed(d)
+                    if this.pending.is_none() || d.position() < d.get_ref().len() as u64 =>
+                {
+                    return Poll::Ready(std::io::Read::read(d, buf.initialize_unfilled()).map(
+                        |n| {
+                            buf.advance(n);
+                        },
+                    ));
+                }
+                BlobState::Chunks(_) => {
+                    let BlobState::Chunks(chunks) =
+                        std::mem::replace(&mut this.blob.state, BlobState::Invalid)
+                    else {
+                        unreachable!()
+                    };
+                    this.start(chunks).map_err(io::Error::other)?;
+                }
+                BlobState::Uncompressed(_) | BlobState::Invalid => {
+                    let Some(h) = this.pending.as_mut() else {
+                        return Poll::Ready(Err(io::Error::other(
+                            "blob decoding has already failed",
+                        )));
+                    };
+                    let res = ready!(Pin::new(h).poll(cx));
+                    this.pending = None;
+                    this.blob.state = BlobState::Invalid;
+                    let d = res.map_err(io::Error::other)?.map_err(io::Error::other)?;
+                    this.blob.state = BlobState::Uncompressed(std::io::Cursor::new(d));
+                    this.spawn_next().map_err(io::Error::other)?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::{BlobWriteOption, Builder};
+
+    fn build() -> (Vec<u8>, Vec<u8>) {
+        let mut buf = Cursor::new(Vec::new());
+        let mut b = Builder::new(&mut buf);
+        let data = (0..10_000)
+            .flat_map(|i| (i as f32 * 0.25).to_le_bytes())
+            .collect::<Vec<_>>();
+        b.add_blob(
+            "w",
+            &data,
+            DataType::Float32,
+            &[100, 100],
+            BlobWriteOption::default(),
+        )
+        .unwrap();
+        b.add_file("model.json", &b"{}"[..]).unwrap();
+        b.finish().unwrap();
+        (buf.into_inner(), data)
+    }
+
+    #[tokio::test]
+    async fn read_blob() {
+        let (archive, data) = build();
+        let readers = (0..3).map(|_| Cursor::new(archive.clone()));
+        let a = AsyncArchive::with_readers(readers).await.unwrap();
+        assert_eq!(a.blob_names().collect::<Vec<_>>(), ["w"]);
+        assert_eq!(a.file_by_name("model.json").await.unwrap(), b"{}");
+
+        let mut blob = a.blob_by_name("w").await.unwrap();
+        assert_eq!(blob.data_type(), Some(DataType::Float32));
+        assert_eq!(blob.shape().into_iter().collect::<Vec<_>>(), [100, 100]);
+        let mut out = vec![];
+        assert_eq!(a.peak_memory(), 0);
+        blob.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, data);
+        assert!(a.peak_memory() > 0);
+        assert!(blob.set_byte_order(ByteOrder::Big).is_err());
+
+        assert!(a.blob_by_name("missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn read_blocks() {
+        // a bit more than one block of a `BlobWriter`
+        let n = (16 << 20) / 4 + 1000;
+        let data = (0..n)
+            .flat_map(|i| ((i % 1000) as f32 * 0.5).to_le_bytes())
+            .collect::<Vec<_>>();
+        let mut buf = Cursor::new(Vec::new());
+        let mut b = Builder::new(&mut buf);
+        let opt = BlobWriteOption::default();
+        b.add_blob_from_reader("w", &data[..], DataType::Float32, &[n], opt)
+            .unwrap();
+        b.finish().unwrap();
+
+        let a = AsyncArchive::new(Cursor::new(buf.into_inner()))
+            .await
+            .unwrap();
+        assert_eq!(a.meta.blobs[0].blocks.len(), 2);
+        let mut out = vec![];
+        a.blob_by_name("w")
+            .await
+            .unwrap()
+            .read_to_end(&mut out)
+            .await
+            .unwrap();
+        assert!(out == data);
+
+        let mut blob = a.blob_by_name("w").await.unwrap();
+        blob.set_byte_order(ByteOrder::Big).unwrap();
+        let mut out = vec![];
+        blob.read_to_end(&mut out).await.unwrap();
+        let be = data
+            .chunks(4)
+            .flat_map(|c| [c[3], c[2], c[1], c[0]])
+            .collect::<Vec<_>>();
+        assert!(out == be);
+        assert!(blob.into_blocking().is_err());
+        assert!(a.blob_by_name("w").await.unwrap().into_blocking().is_ok());
+    }
+
+    #[tokio::test]
+    async fn no_readers() {
+        let readers = std::iter::empty::<Cursor<Vec<u8>>>();
+        assert!(matches!(
+            AsyncArchive::with_readers(readers).await,
+            Err(Error::Unsupported(_))
+        ));
+    }
+}
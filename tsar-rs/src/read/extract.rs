@@ -0,0 +1,248 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Seek},
+    path::{Component, Path, PathBuf},
+    sync::{mpsc, Mutex},
+};
+
+use super::{decode, Archive, Blob, BlobState};
+use crate::{
+    compress,
+    result::{Error, Result},
+};
+
+/// What to do when an extracted file already exists.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Overwrite {
+    /// Fail with [`Error::AlreadyExists`].
+    #[default]
+    Error,
+    /// Keep the existing file and skip everything written to it.
+    Skip,
+    /// Truncate and rewrite the existing file.
+    Replace,
+}
+
+#[derive(Default)]
+pub struct ExtractOption {
+    /// Number of decoding threads; `0` uses the available parallelism.
+    pub threads: usize,
+    pub overwrite: Overwrite,
+}
+
+impl<R: Read + Seek> Archive<R> {
+    /// Extract all raw files and blobs below `dst`.
+    ///
+    /// Blobs are decoded in parallel while compressed chunks are still being
+    /// read, with at most a few blobs per thread held in memory at a time.
+    /// Each target file is allocated once and blobs are written in place at
+    /// their target offset.
+    ///
+    /// Names stored in the archive are untrusted: absolute paths, `..`
+    /// components and paths through symlinks below `dst` are rejected.
+    ///
+    /// Blobs without a target file have nowhere to go and are skipped with a
+    /// warning; read them with [`Archive::blob_by_name`] instead.
+    pub fn extract_to(&mut self, dst: impl AsRef<Path>, opt: ExtractOption) -> Result<()> {
+        let dst = dst.as_ref();
+        let files = self.file_names().map(str::to_owned).collect::<Vec<_>>();
+
+        // check every name, size and limit before creating anything, so a
+        // bad archive leaves no partial extraction behind
+        for f in &files {
+            safe_join(dst, f)?;
+        }
+        let mut sizes = HashMap::<String, u64>::new();
+        let mut total = self.decoded;
+        for b in self.meta.blobs.iter() {
+            if b.target_file_name.is_empty() {
+                log::warn!("blob {} has no target file, not extracting it", b.name);
+                continue;
+            }
+            safe_join(dst, &b.target_file_name)?;
+            let len = self.limits.check_blob(b)?;
+            total = self.limits.archive_total(total, len)?;
+            let end = u64::try_from(b.target_offset_in_bytes)
+                .ok()
+                .and_then(|o| o.checked_add(len as u64))
+                .ok_or(Error::Corrupt("invalid blob target offset"))?;
+            let sz = sizes.entry(b.target_file_name.clone()).or_default();
+            *sz = end.max(*sz);
+        }
+
+        for f in files {
+            if let Some(mut outfile) = create(dst, &f, opt.overwrite)? {
+                io::copy(&mut self.file_by_name(&f)?, &mut outfile)?;
+            }
+        }
+        let mut targets = HashMap::new();
+        for (name, sz) in sizes {
+            if let Some(f) = create(dst, &name, opt.overwrite)? {
+                f.set_len(sz)?;
+                targets.insert(name, f);
+            }
+        }
+
+        let names = self
+            .meta
+            .blobs
+            .iter()
+            .filter(|b| targets.contains_key(&b.target_file_name))
+            .map(|b| b.name.clone())
+            .collect::<Vec<_>>();
+
+        let threads = match opt.threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        let (tx, rx) = mpsc::sync_channel::<Blob>(threads);
+        let rx = Mutex::new(rx);
+        let failed = Mutex::new(None);
+        let pool = self.ctx.clone();
+
+        std::thread::scope(|s| {
+            for _ in 0..threads {
+                s.spawn(|| {
+                    pool.with(|ctx| loop {
+                        let Ok(b) = rx.lock().unwrap().recv() else {
+                            break;
+                        };
+                        // keep draining after a failure so the reader never blocks
+                        if failed.lock().unwrap().is_some() {
+                            continue;
+                        }
+                        if let Err(e) = write_blob(ctx, b, &targets) {
+                            failed.lock().unwrap().get_or_insert(e);
+                        }
+                    })
+                });
+            }
+
+            for n in names {
+                if failed.lock().unwrap().is_some() {
+                    break;
+                }
+                match self.blob_by_name(n) {
+                    Ok(b) => {
+                        if tx.send(b).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        failed.lock().unwrap().get_or_insert(e);
+                        break;
+                    }
+                }
+            }
+            drop(tx);
+        });
+
+        match failed.into_inner().unwrap() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+fn write_blob(
+    ctx: &mut compress::Context,
+    b: Blob,
+    targets: &HashMap<String, fs::File>,
+) -> Result<()> {
+    let Blob {
+        meta,
+        state,
+        byte_order,
+        ..
+    } = b;
+    let data = match state {
+        BlobState::Chunks(c) => decode(ctx, &meta, c, byte_order)?,
+        BlobState::Uncompressed(c) => c.into_inner(),
+        BlobState::Invalid => return Err(Error::Unknown),
+    };
+    let f = &targets[&meta.target_file_name];
+    write_all_at(f, &data, meta.target_offset_in_bytes as u64)?;
+    Ok(())
+}
+
+/// Join an archive-supplied `name` onto `dst`, rejecting anything that could
+/// escape it.
+fn safe_join(dst: &Path, name: &str) -> Result<PathBuf> {
+    let mut p = dst.to_path_buf();
+    let mut depth = 0;
+    for c in Path::new(name).components() {
+        match c {
+            Component::Normal(c) => {
+                p.push(c);
+                depth += 1;
+            }
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(Error::UnsafePath(name.to_owned()))
+            }
+        }
+    }
+    if depth == 0 {
+        return Err(Error::UnsafePath(name.to_owned()));
+    }
+    Ok(p)
+}
+
+/// Create the output file for `name` below `dst`, or return `None` when it
+/// exists and should be skipped.
+fn create(dst: &Path, name: &str, overwrite: Overwrite) -> Result<Option<fs::File>> {
+    let p = safe_join(dst, name)?;
+
+    // refuse to follow any symlink between `dst` and the file itself
+    let mut cur = dst.to_path_buf();
+    for c in p.strip_prefix(dst).unwrap().components() {
+        cur.push(c);
+        match fs::symlink_metadata(&cur) {
+            Ok(m) if m.file_type().is_symlink() => return Err(Error::Symlink(cur)),
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if let Some(parent) = p.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut o = fs::OpenOptions::new();
+    o.write(true);
+    match overwrite {
+        Overwrite::Replace => o.create(true).truncate(true),
+        Overwrite::Error | Overwrite::Skip => o.create_new(true),
+    };
+    match o.open(&p) {
+        Ok(f) => Ok(Some(f)),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => match overwrite {
+            Overwrite::Skip => Ok(None),
+            _ => Err(Error::AlreadyExists(p)),
+        },
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(unix)]
+fn write_all_at(f: &fs::File, buf: &[u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(f, buf, offset)
+}
+
+#[cfg(windows)]
+fn write_all_at(f: &fs::File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match f.seek_write(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use super::{blob_size, chunk_ids};
+use crate::{
+    pb,
+    result::{Error, Result},
+};
+
+/// Bounds on what reading an untrusted archive may allocate, checked against
+/// the sizes the archive declares before any chunk of a blob is read. The
+/// default sets no limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReadLimits {
+    /// Most elements of one blob.
+    pub max_elements: u64,
+    /// Most decoded bytes of one blob.
+    pub max_blob_bytes: u64,
+    /// Most decoded bytes of all blobs read from one archive.
+    pub max_archive_bytes: u64,
+    /// Most chunks of one blob.
+    pub max_chunks: u64,
+}
+
+impl Default for ReadLimits {
+    fn default() -> Self {
+        Self {
+            max_elements: u64::MAX,
+            max_blob_bytes: u64::MAX,
+            max_archive_bytes: u64::MAX,
+            max_chunks: u64::MAX,
+        }
+    }
+}
+
+impl ReadLimits {
+    /// Check a blob against the per-blob limits, returning its decoded length.
+    pub(crate) fn check_blob(&self, meta: &pb::Blob) -> Result<usize> {
+        let (_, len) = blob_size(meta)?;
+        // `blob_size` has checked the dims are non-negative and their product fits
+        let elements = meta.dims.iter().map(|&d| d as u64).product();
+        check("elements of a blob", elements, self.max_elements)?;
+        check("decoded bytes of a blob", len as u64, self.max_blob_bytes)?;
+        let chunks = chunk_ids(meta).count() as u64;
+        check("chunks of a blob", chunks, self.max_chunks)?;
+        Ok(len)
+    }
+
+    /// The decoded bytes of an archive after `len` more on top of `total`.
+    pub(crate) fn archive_total(&self, total: u64, len: usize) -> Result<u64> {
+        let total = total.saturating_add(len as u64);
+        check("decoded bytes of an archive", total, self.max_archive_bytes)?;
+        Ok(total)
+    }
+}
+
+fn check(what: &'static str, value: u64, limit: u64) -> Result<()> {
+    if value > limit {
+        return Err(Error::LimitExceeded { what, value, limit });
+    }
+    Ok(())
+}
@@ -1,12 +1,57 @@
-use std::io::{Read, Seek};
+#[cfg(feature = "async")]
+mod async_archive;
+mod extract;
+mod limits;
+#[cfg(feature = "async")]
+mod zip_index;
+
+use std::{
+    io::{Read, Seek},
+    sync::{Arc, Mutex},
+};
 
 use protobuf::{CodedInputStream, Message};
+use zip::result::ZipError;
+
+use crate::{
+    codec::{BufferList, ByteSwap},
+    compress, format, metadata, paths, pb,
+    result::{Error, Result},
+    ByteOrder, DataType, Metadata,
+};
 
-use crate::{codec::BufferList, compress, paths, pb, result::Result, DataType};
+#[cfg(feature = "async")]
+pub use async_archive::{AsyncArchive, AsyncBlob};
+pub use extract::{ExtractOption, Overwrite};
+pub use limits::ReadLimits;
 
 pub struct Archive<R: Read + Seek> {
     z: zip::read::ZipArchive<R>,
     meta: pb::Bundle,
+    ctx: ContextPool,
+    limits: ReadLimits,
+    decoded: u64,
+}
+
+/// Decompression contexts shared by an archive and the blobs read from it,
+/// so that decoding many blobs reuses the same scratch buffers.
+#[derive(Clone, Default)]
+struct ContextPool(Arc<Mutex<Vec<compress::Context>>>);
+
+impl ContextPool {
+    /// Run `f` with an idle context, or a new one when all are in use.
+    fn with<T>(&self, f: impl FnOnce(&mut compress::Context) -> T) -> T {
+        let mut ctx = self.0.lock().unwrap().pop().unwrap_or_default();
+        let res = f(&mut ctx);
+        self.0.lock().unwrap().push(ctx);
+        res
+    }
+
+    /// Peak bytes summed over every context, as they may all be busy at once.
+    fn peak_bytes(&self) -> usize {
+        let pool = self.0.lock().unwrap();
+        pool.iter().map(compress::Context::peak_bytes).sum()
+    }
 }
 
 impl<R: Read + Seek> Archive<R> {
@@ -15,7 +60,27 @@ impl<R: Read + Seek> Archive<R> {
         let mut f = z.by_name(paths::BUNDLE_META_PATH)?;
         let meta = pb::Bundle::parse_from(&mut CodedInputStream::new(&mut f))?;
         drop(f);
-        Ok(Self { z, meta })
+        format::check(&meta)?;
+        Ok(Self {
+            z,
+            meta,
+            ctx: ContextPool::default(),
+            limits: ReadLimits::default(),
+            decoded: 0,
+        })
+    }
+
+    /// Limit what reading blobs may allocate from now on. Blobs already read
+    /// count towards [`ReadLimits::max_archive_bytes`].
+    pub fn set_limits(&mut self, limits: ReadLimits) {
+        self.limits = limits;
+    }
+
+    /// Format version the archive was written with, `(0, 0)` for archives
+    /// from before it was recorded. A minor version newer than
+    /// [`FORMAT_VERSION`](crate::FORMAT_VERSION) is read best-effort.
+    pub fn format_version(&self) -> (u32, u32) {
+        (self.meta.format_major, self.meta.format_minor)
     }
 
     pub fn file_names(&self) -> impl Iterator<Item = &str> {
@@ -26,6 +91,26 @@ impl<R: Read + Seek> Archive<R> {
         self.meta.blobs.iter().map(|f| f.name.as_str())
     }
 
+    /// Most bytes of decompression scratch buffers held at once by the
+    /// blobs read so far and [`Archive::extract_to`], summed over its workers.
+    pub fn peak_memory(&self) -> usize {
+        self.ctx.peak_bytes()
+    }
+
+    /// Archive-wide metadata set with [`Builder::set_metadata`](crate::Builder::set_metadata).
+    pub fn metadata(&self) -> Metadata {
+        metadata::from_pb(&self.meta.metadata)
+    }
+
+    pub fn file_metadata(&self, name: impl AsRef<str>) -> Option<Metadata> {
+        let name = name.as_ref();
+        self.meta
+            .raw_files
+            .iter()
+            .find(|f| f.name == name)
+            .map(|f| metadata::from_pb(&f.metadata))
+    }
+
     pub fn file_by_name(&mut self, name: impl AsRef<str>) -> Result<impl Read + '_> {
         Ok(self.z.by_name(name.as_ref())?)
     }
@@ -37,21 +122,21 @@ impl<R: Read + Seek> Archive<R> {
             .blobs
             .iter()
             .find(|&b| b.name == name)
-            .expect("Missing blob");
+            .ok_or(ZipError::FileNotFound)?;
+        let len = self.limits.check_blob(b)?;
+        self.decoded = self.limits.archive_total(self.decoded, len)?;
 
+        let ids = chunk_ids(b).collect::<Vec<_>>();
+        let max = compress::max_encoded_len(len) as u64;
         let mut bb = BufferList::new();
-        bb.reset(b.chunk_ids.len());
-        for (i, c) in b.chunk_ids.iter().enumerate() {
-            let mut f = self
-                .z
-                .by_name(&paths::chunk_path(c))
-                .expect("Missing chunk");
-            std::io::copy(&mut f, &mut bb[i])?;
+        bb.reset(ids.len());
+        for (i, c) in ids.into_iter().enumerate() {
+            let f = self.z.by_name(&paths::chunk_path(c))?;
+            if f.size() > max || std::io::copy(&mut f.take(max + 1), &mut bb[i])? > max {
+                return Err(Error::Corrupt("chunk larger than its blob"));
+            }
         }
-        Ok(Blob {
-            meta: b.clone(),
-            state: BlobState::Chunks(bb),
-        })
+        Ok(Blob::new(b.clone(), bb, self.ctx.clone()))
     }
 }
 
@@ -64,9 +149,21 @@ enum BlobState {
 pub struct Blob {
     meta: pb::Blob,
     state: BlobState,
+    byte_order: ByteOrder,
+    ctx: ContextPool,
 }
 
 impl Blob {
+    fn new(meta: pb::Blob, chunks: BufferList, ctx: ContextPool) -> Self {
+        let byte_order = meta.byte_order.try_into().unwrap_or_default();
+        Self {
+            meta,
+            state: BlobState::Chunks(chunks),
+            byte_order,
+            ctx,
+        }
+    }
+
     pub fn target_file(&self) -> Option<(&str, u64)> {
         if !self.meta.target_file_name.is_empty() {
             Some((
@@ -83,15 +180,7 @@ impl Blob {
     }
 
     pub fn byte_len(&self) -> Option<usize> {
-        let dt = self.data_type()?;
-        Some(
-            self.meta
-                .dims
-                .iter()
-                .map(|s| *s as usize)
-                .product::<usize>()
-                * dt.byte_len(),
-        )
+        blob_size(&self.meta).ok().map(|(_, len)| len)
     }
 
     pub fn data_type(&self) -> Option<DataType> {
@@ -102,55 +191,185 @@ impl Blob {
         self.meta.dims.iter().map(|f| *f as usize)
     }
 
+    pub fn metadata(&self) -> Metadata {
+        metadata::from_pb(&self.meta.metadata)
+    }
+
+    /// Byte order of the data this blob was written from.
+    pub fn source_byte_order(&self) -> ByteOrder {
+        self.meta.byte_order.try_into().unwrap_or_default()
+    }
+
+    /// Byte order of the data returned by `read`, the source byte order
+    /// unless changed before the first read.
+    pub fn byte_order(&self) -> ByteOrder {
+        self.byte_order
+    }
+
+    /// Set the byte order of the data returned by `read`, which fails once
+    /// reading has started.
+    pub fn set_byte_order(&mut self, order: ByteOrder) -> Result<()> {
+        if !matches!(self.state, BlobState::Chunks(_)) {
+            return Err(Error::Unsupported(
+                "changing the byte order after reading".into(),
+            ));
+        }
+        self.byte_order = order;
+        Ok(())
+    }
+
     fn get_data(&mut self) -> std::io::Result<&mut impl std::io::Read> {
         if matches!(&mut self.state, BlobState::Chunks(_)) {
             if let BlobState::Chunks(b) = std::mem::replace(&mut self.state, BlobState::Invalid) {
-                let dt = self.data_type().expect("unknown data format");
-                let stages = self
-                    .meta
-                    .compression_stages
-                    .iter()
-                    .map(|e| e.unwrap())
-                    .collect::<Vec<_>>();
-                let d = compress::decompress(
-                    b,
-                    dt,
-                    &self
-                        .meta
-                        .dims
-                        .iter()
-                        .map(|d| *d as usize)
-                        .collect::<Vec<_>>(),
-                    &stages,
-                )
-                .unwrap();
-
+                let d = self
+                    .ctx
+                    .with(|ctx| decode(ctx, &self.meta, b, self.byte_order))
+                    .map_err(std::io::Error::other)?;
                 self.state = BlobState::Uncompressed(std::io::Cursor::new(d));
             }
         }
 
-        if let BlobState::Uncompressed(d) = &mut self.state {
-            Ok(d)
-        } else {
-            todo!()
+        match &mut self.state {
+            BlobState::Uncompressed(d) => Ok(d),
+            _ => Err(std::io::Error::other("blob decoding has already failed")),
+        }
+    }
+}
+
+/// Data type and decoded byte length of a blob.
+fn blob_size(meta: &pb::Blob) -> Result<(DataType, usize)> {
+    let dt = DataType::try_from(meta.data_type).map_err(|_| Error::Corrupt("unknown data type"))?;
+    let len = meta
+        .dims
+        .iter()
+        .try_fold(1usize, |n, &d| n.checked_mul(usize::try_from(d).ok()?))
+        .and_then(|n| n.checked_mul(dt.bit_len()))
+        .ok_or(Error::Corrupt("invalid blob shape"))?;
+    Ok((dt, len.div_ceil(8)))
+}
+
+/// Ids of every chunk of the blob, in the order `decode` expects them.
+fn chunk_ids(meta: &pb::Blob) -> impl Iterator<Item = &String> {
+    meta.chunk_ids
+        .iter()
+        .chain(meta.blocks.iter().flat_map(|b| &b.chunk_ids))
+}
+
+fn decode(
+    ctx: &mut compress::Context,
+    meta: &pb::Blob,
+    chunks: BufferList,
+    order: ByteOrder,
+) -> Result<Vec<u8>> {
+    let (dt, len) = blob_size(meta)?;
+    let d = if meta.blocks.is_empty() {
+        let mut d = ctx.decompress(
+            chunks,
+            dt,
+            &meta.dims.iter().map(|&d| d as usize).collect::<Vec<_>>(),
+            &stages(&meta.compression_stages)?,
+        )?;
+        if order == ByteOrder::Big {
+            ByteSwap(dt.word_len()).swap_in_place(&mut d);
+        }
+        d
+    } else {
+        let mut d = vec![];
+        for b in split_blocks(meta, chunks)? {
+            d.extend(decode_block(ctx, dt, b, order)?);
+        }
+        d
+    };
+    if d.len() != len {
+        return Err(Error::Corrupt("decoded blob does not match its shape"));
+    }
+    Ok(d)
+}
+
+fn stages(
+    s: &[protobuf::EnumOrUnknown<pb::CompressionStage>],
+) -> Result<Vec<pb::CompressionStage>> {
+    s.iter()
+        .map(|e| {
+            e.enum_value()
+                .map_err(|_| Error::Corrupt("unknown compression stage"))
+        })
+        .collect()
+}
+
+/// One block of a blob written by a [`BlobWriter`](crate::BlobWriter).
+struct Block {
+    elements: usize,
+    stages: Vec<pb::CompressionStage>,
+    chunks: BufferList,
+}
+
+/// Split the chunks of a blob written in blocks, checking that the blocks
+/// add up to its shape. Only the last block may end in a padding nibble.
+fn split_blocks(meta: &pb::Blob, mut chunks: BufferList) -> Result<Vec<Block>> {
+    let (dt, len) = blob_size(meta)?;
+    let mut total = 0usize;
+    for (i, b) in meta.blocks.iter().enumerate() {
+        let n = usize::try_from(b.num_elements).ok();
+        total = n
+            .and_then(|n| total.checked_add(n))
+            .ok_or(Error::Corrupt("invalid blob block"))?;
+        if i + 1 < meta.blocks.len() && total.checked_mul(dt.bit_len()).is_some_and(|n| n % 8 != 0)
+        {
+            return Err(Error::Corrupt("blob block ends inside a byte"));
+        }
+    }
+    if total.checked_mul(dt.bit_len()).map(|n| n.div_ceil(8)) != Some(len) {
+        return Err(Error::Corrupt("blob blocks do not match its shape"));
+    }
+    let mut it = chunks.iter_mut();
+    let mut blocks = vec![];
+    for b in &meta.blocks {
+        let mut bb = BufferList::new();
+        bb.reset(b.chunk_ids.len());
+        for (dst, src) in bb.iter_mut().zip(it.by_ref()) {
+            *dst = std::mem::take(src);
         }
+        blocks.push(Block {
+            elements: b.num_elements as usize,
+            stages: stages(&b.compression_stages)?,
+            chunks: bb,
+        });
+    }
+    Ok(blocks)
+}
+
+fn decode_block(
+    ctx: &mut compress::Context,
+    dt: DataType,
+    b: Block,
+    order: ByteOrder,
+) -> Result<Vec<u8>> {
+    let mut d = ctx.decompress(b.chunks, dt, &[b.elements], &b.stages)?;
+    // `split_blocks` has checked the elements fit
+    if d.len() != dt.size_of(b.elements) {
+        return Err(Error::Corrupt("decoded block does not match its length"));
+    }
+    if order == ByteOrder::Big {
+        ByteSwap(dt.word_len()).swap_in_place(&mut d);
     }
+    Ok(d)
 }
 
 impl Read for Blob {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        self.get_data().unwrap().read(buf)
+        self.get_data()?.read(buf)
     }
 
     fn read_to_end(&mut self, buf: &mut Vec<u8>) -> std::io::Result<usize> {
-        self.get_data().unwrap().read_to_end(buf)
+        self.get_data()?.read_to_end(buf)
     }
 
     fn read_to_string(&mut self, buf: &mut String) -> std::io::Result<usize> {
-        self.get_data().unwrap().read_to_string(buf)
+        self.get_data()?.read_to_string(buf)
     }
 
     fn read_exact(&mut self, buf: &mut [u8]) -> std::io::Result<()> {
-        self.get_data().unwrap().read_exact(buf)
+        self.get_data()?.read_exact(buf)
     }
 }
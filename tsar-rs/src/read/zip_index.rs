@@ -0,0 +1,290 @@
+//! Minimal ZIP central directory reader for the async archive, which cannot
+//! go through the blocking `zip` crate.
+
+use std::collections::HashMap;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+use zip::result::ZipError;
+
+use crate::result::Result;
+
+const EOCD_SIG: u32 = 0x0605_4b50;
+const EOCD_LEN: usize = 22;
+const ZIP64_LOCATOR_SIG: u32 = 0x0706_4b50;
+const ZIP64_LOCATOR_LEN: usize = 20;
+const ZIP64_EOCD_SIG: u32 = 0x0606_4b50;
+const ZIP64_EOCD_LEN: usize = 56;
+const CENTRAL_SIG: u32 = 0x0201_4b50;
+const CENTRAL_LEN: usize = 46;
+const LOCAL_SIG: u32 = 0x0403_4b50;
+const LOCAL_LEN: usize = 30;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Method {
+    Stored,
+    Deflate,
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub method: Method,
+    pub header_offset: u64,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    pub crc32: u32,
+}
+
+pub struct ZipIndex {
+    entries: HashMap<String, Entry>,
+}
+
+fn invalid(msg: &'static str) -> ZipError {
+    ZipError::InvalidArchive(msg.into())
+}
+
+fn u16_at(b: &[u8], o: usize) -> u16 {
+    u16::from_le_bytes(b[o..o + 2].try_into().unwrap())
+}
+
+fn u32_at(b: &[u8], o: usize) -> u32 {
+    u32::from_le_bytes(b[o..o + 4].try_into().unwrap())
+}
+
+fn u64_at(b: &[u8], o: usize) -> u64 {
+    u64::from_le_bytes(b[o..o + 8].try_into().unwrap())
+}
+
+async fn read_at<R: AsyncRead + AsyncSeek + Unpin>(
+    r: &mut R,
+    offset: u64,
+    len: usize,
+) -> Result<Vec<u8>> {
+    // the length may be untrusted, so only allocate what is actually read
+    r.seek(std::io::SeekFrom::Start(offset)).await?;
+    let mut buf = Vec::new();
+    (&mut *r).take(len as u64).read_to_end(&mut buf).await?;
+    if buf.len() < len {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(buf)
+}
+
+impl ZipIndex {
+    pub async fn read<R: AsyncRead + AsyncSeek + Unpin>(r: &mut R) -> Result<Self> {
+        let len = r.seek(std::io::SeekFrom::End(0)).await?;
+        let tail_len = len.min((EOCD_LEN + u16::MAX as usize) as u64);
+        let tail = read_at(r, len - tail_len, tail_len as usize).await?;
+        if tail.len() < EOCD_LEN {
+            return Err(invalid("missing end of central directory").into());
+        }
+        let eocd = (0..=tail.len() - EOCD_LEN)
+            .rev()
+            .find(|&i| u32_at(&tail, i) == EOCD_SIG)
+            .ok_or_else(|| invalid("missing end of central directory"))?;
+        let eocd_pos = len - tail_len + eocd as u64;
+
+        let mut entries = u64::from(u16_at(&tail, eocd + 10));
+        let mut cd_size = u64::from(u32_at(&tail, eocd + 12));
+        let mut cd_offset = u64::from(u32_at(&tail, eocd + 16));
+
+        if eocd_pos >= ZIP64_LOCATOR_LEN as u64 {
+            let loc = read_at(r, eocd_pos - ZIP64_LOCATOR_LEN as u64, ZIP64_LOCATOR_LEN).await?;
+            if u32_at(&loc, 0) == ZIP64_LOCATOR_SIG {
+                let rec = read_at(r, u64_at(&loc, 8), ZIP64_EOCD_LEN).await?;
+                if u32_at(&rec, 0) != ZIP64_EOCD_SIG {
+                    return Err(invalid("invalid zip64 end of central directory").into());
+                }
+                entries = u64_at(&rec, 32);
+                cd_size = u64_at(&rec, 40);
+                cd_offset = u64_at(&rec, 48);
+            }
+        }
+
+        if cd_offset.checked_add(cd_size).is_none_or(|e| e > len) {
+            return Err(invalid("central directory out of range").into());
+        }
+        let cd = read_at(r, cd_offset, cd_size as usize).await?;
+        Self::parse(&cd, entries)
+    }
+
+    fn parse(cd: &[u8], count: u64) -> Result<Self> {
+        let mut entries = HashMap::new();
+        let mut p = 0;
+        for _ in 0..count {
+            if cd.len() < p + CENTRAL_LEN || u32_at(cd, p) != CENTRAL_SIG {
+                return Err(invalid("invalid central directory header").into());
+            }
+            let method = match u16_at(cd, p + 10) {
+                0 => Method::Stored,
+                8 => Method::Deflate,
+                _ => return Err(ZipError::UnsupportedArchive("compression method").into()),
+            };
+            let crc32 = u32_at(cd, p + 16);
+            let mut compressed_size = u64::from(u32_at(cd, p + 20));
+            let mut uncompressed_size = u64::from(u32_at(cd, p + 24));
+            let name_len = usize::from(u16_at(cd, p + 28));
+            let extra_len = usize::from(u16_at(cd, p + 30));
+            let comment_len = usize::from(u16_at(cd, p + 32));
+            let mut header_offset = u64::from(u32_at(cd, p + 42));
+
+            let name_start = p + CENTRAL_LEN;
+            let extra_start = name_start + name_len;
+            let next = extra_start + extra_len + comment_len;
+            if cd.len() < next {
+                return Err(invalid("truncated central directory").into());
+            }
+            let name = std::str::from_utf8(&cd[name_start..extra_start])
+                .map_err(|_| invalid("non UTF-8 file name"))?
+                .to_owned();
+
+            // zip64 extended information replaces the saturated 32-bit fields
+            let mut extra = &cd[extra_start..extra_start + extra_len];
+            while extra.len() >= 4 {
+                let id = u16_at(extra, 0);
+                let sz = usize::from(u16_at(extra, 2));
+                let body = extra
+                    .get(4..4 + sz)
+                    .ok_or_else(|| invalid("truncated extra field"))?;
+                if id == 0x0001 {
+                    let mut fields = body.chunks_exact(8).map(|f| u64_at(f, 0));
+                    for v in [
+                        &mut uncompressed_size,
+                        &mut compressed_size,
+                        &mut header_offset,
+                    ] {
+                        if *v == u64::from(u32::MAX) {
+                            *v = fields
+                                .next()
+                                .ok_or_else(|| invalid("truncated zip64 extra field"))?;
+                        }
+                    }
+                }
+                extra = &extra[4 + sz..];
+            }
+
+            entries.insert(
+                name,
+                Entry {
+                    method,
+                    header_offset,
+                    compressed_size,
+                    uncompressed_size,
+                    crc32,
+                },
+            );
+            p = next;
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.get(name)
+    }
+}
+
+impl Entry {
+    /// Read and decompress the entry's data, checking its size and CRC32.
+    pub async fn read<R: AsyncRead + AsyncSeek + Unpin>(&self, r: &mut R) -> Result<Vec<u8>> {
+        let hdr = read_at(r, self.header_offset, LOCAL_LEN).await?;
+        if u32_at(&hdr, 0) != LOCAL_SIG {
+            return Err(invalid("invalid local file header").into());
+        }
+        let data_start = self.header_offset
+            + (LOCAL_LEN + usize::from(u16_at(&hdr, 26)) + usize::from(u16_at(&hdr, 28))) as u64;
+        let data = read_at(r, data_start, self.compressed_size as usize).await?;
+        let out = match self.method {
+            Method::Stored => data,
+            Method::Deflate => {
+                // the declared size is untrusted, so grow with what is decoded
+                // and read one byte past it to notice longer data
+                let mut out = Vec::new();
+                let d = flate2::read::DeflateDecoder::new(&data[..]);
+                std::io::Read::read_to_end(
+                    &mut std::io::Read::take(d, self.uncompressed_size.saturating_add(1)),
+                    &mut out,
+                )?;
+                out
+            }
+        };
+        if out.len() as u64 != self.uncompressed_size {
+            return Err(invalid("entry size does not match the central directory").into());
+        }
+        let mut crc = flate2::Crc::new();
+        crc.update(&out);
+        if crc.sum() != self.crc32 {
+            return Err(invalid("entry CRC32 does not match the central directory").into());
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::write::SimpleFileOptions;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn read_entries() {
+        let mut buf = Cursor::new(Vec::new());
+        let mut z = zip::ZipWriter::new(&mut buf);
+        let payload = (0..100_000u32).map(|v| (v % 7) as u8).collect::<Vec<_>>();
+        for (name, method, large) in [
+            ("stored", zip::CompressionMethod::Stored, false),
+            ("deflate", zip::CompressionMethod::DEFLATE, false),
+            ("large", zip::CompressionMethod::Stored, true),
+        ] {
+            z.start_file(
+                name,
+                SimpleFileOptions::default()
+                    .compression_method(method)
+                    .large_file(large),
+            )
+            .unwrap();
+            z.write_all(&payload).unwrap();
+        }
+        z.finish().unwrap();
+
+        let mut r = Cursor::new(buf.into_inner());
+        let idx = ZipIndex::read(&mut r).await.unwrap();
+        for name in ["stored", "deflate", "large"] {
+            let e = idx.get(name).unwrap();
+            assert_eq!(e.read(&mut r).await.unwrap(), payload, "{name}");
+        }
+        assert_eq!(idx.get("deflate").unwrap().method, Method::Deflate);
+        assert!(idx.get("missing").is_none());
+
+        // a huge declared size fails at the end of the data, not on allocation
+        let huge = Entry {
+            compressed_size: u64::MAX >> 8,
+            ..idx.get("stored").unwrap().clone()
+        };
+        assert!(huge.read(&mut r).await.is_err());
+
+        // corrupted data and sizes are caught
+        let e = idx.get("deflate").unwrap().clone();
+        for bad in [
+            Entry {
+                crc32: e.crc32 ^ 1,
+                ..e.clone()
+            },
+            Entry {
+                uncompressed_size: e.uncompressed_size - 1,
+                ..e.clone()
+            },
+            Entry {
+                uncompressed_size: e.uncompressed_size + 1,
+                ..e.clone()
+            },
+        ] {
+            assert!(bad.read(&mut r).await.is_err());
+        }
+        let mut data = r.into_inner();
+        let s = idx.get("stored").unwrap();
+        data[s.header_offset as usize + LOCAL_LEN + "stored".len() + 10] ^= 1;
+        let mut r = Cursor::new(data);
+        assert!(s.read(&mut r).await.is_err());
+    }
+}
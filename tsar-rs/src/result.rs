@@ -8,6 +8,47 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error("ProtoBuf error: {0}")]
     Protobuf(#[from] protobuf::Error),
+    #[error("unsafe path in archive: {0:?}")]
+    UnsafePath(String),
+    #[error("refusing to extract through symlink: {0}")]
+    Symlink(std::path::PathBuf),
+    #[error("file already exists: {0}")]
+    AlreadyExists(std::path::PathBuf),
+    #[error("blobs {first:?} and {second:?} overlap in target file {file:?}")]
+    TargetOverlap {
+        file: String,
+        first: String,
+        second: String,
+    },
+    #[error(
+        "target file {file:?} has a gap of {len} bytes at offset {offset} before blob {next:?}"
+    )]
+    TargetGap {
+        file: String,
+        offset: u64,
+        len: u64,
+        next: String,
+    },
+    #[error("blob {name:?} reaches past the largest offset of its target file")]
+    TargetOutOfRange { name: String },
+    #[error("region {name:?} of {len} bytes does not match its data type and shape")]
+    RegionLength { name: String, len: u64 },
+    #[error("region {name:?} reaches past the end of its {file_len} byte file")]
+    RegionOutOfBounds { name: String, file_len: u64 },
+    #[error("strided view reaches outside its buffer")]
+    ViewOutOfBounds,
+    #[error("invalid strided view: {0}")]
+    InvalidView(&'static str),
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+    #[error("{what} of {value} exceeds the read limit of {limit}")]
+    LimitExceeded {
+        what: &'static str,
+        value: u64,
+        limit: u64,
+    },
+    #[error("corrupt data: {0}")]
+    Corrupt(&'static str),
     #[error("ZPF error")]
     ZPFUnknown,
     #[error("unknown error")]
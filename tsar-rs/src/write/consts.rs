@@ -8,7 +8,7 @@ macro_rules! methods {
     };
 }
 
-pub const COMPRESS_METHOD: [(DataType, &[&[pb::CompressionStage]]); 13] = [
+pub const COMPRESS_METHOD: [(DataType, &[&[pb::CompressionStage]]); 20] = [
     (
         DataType::Float32,
         methods![
@@ -17,6 +17,10 @@ pub const COMPRESS_METHOD: [(DataType, &[&[pb::CompressionStage]]); 13] = [
                 pb::CompressionStage::SPLIT_MANTISSA_FLOAT32,
                 pb::CompressionStage::ZSTD,
             ],
+            [
+                pb::CompressionStage::FPC_FLOAT32,
+                pb::CompressionStage::ZSTD,
+            ],
             [
                 pb::CompressionStage::CONVERT_FLOAT32_TO_BFLOAT16,
                 pb::CompressionStage::SPLIT_MANTISSA_BFLOAT16,
@@ -32,6 +36,10 @@ pub const COMPRESS_METHOD: [(DataType, &[&[pb::CompressionStage]]); 13] = [
                 pb::CompressionStage::SPLIT_MANTISSA_FLOAT64,
                 pb::CompressionStage::ZSTD,
             ],
+            [
+                pb::CompressionStage::FPC_FLOAT64,
+                pb::CompressionStage::ZSTD,
+            ],
             [
                 pb::CompressionStage::CONVERT_FLOAT64_TO_FLOAT32,
                 pb::CompressionStage::SPLIT_MANTISSA_FLOAT32,
@@ -52,6 +60,10 @@ pub const COMPRESS_METHOD: [(DataType, &[&[pb::CompressionStage]]); 13] = [
                 pb::CompressionStage::SPLIT_MANTISSA_BFLOAT16,
                 pb::CompressionStage::ZSTD,
             ],
+            [
+                pb::CompressionStage::FPC_BFLOAT16,
+                pb::CompressionStage::ZSTD,
+            ],
         ],
     ),
     (
@@ -62,6 +74,10 @@ pub const COMPRESS_METHOD: [(DataType, &[&[pb::CompressionStage]]); 13] = [
                 pb::CompressionStage::SPLIT_MANTISSA_BFLOAT16,
                 pb::CompressionStage::ZSTD
             ],
+            [
+                pb::CompressionStage::FPC_FLOAT16,
+                pb::CompressionStage::ZSTD,
+            ],
         ],
     ),
     (DataType::Byte, methods![[pb::CompressionStage::ZSTD],]),
@@ -73,4 +89,35 @@ pub const COMPRESS_METHOD: [(DataType, &[&[pb::CompressionStage]]); 13] = [
     (DataType::Uint32, methods![[pb::CompressionStage::ZSTD],]),
     (DataType::Int64, methods![[pb::CompressionStage::ZSTD],]),
     (DataType::Uint64, methods![[pb::CompressionStage::ZSTD],]),
+    (DataType::Bool, methods![[pb::CompressionStage::ZSTD],]),
+    (
+        DataType::Complex64,
+        methods![
+            [pb::CompressionStage::ZSTD],
+            [
+                pb::CompressionStage::SPLIT_MANTISSA_FLOAT32,
+                pb::CompressionStage::ZSTD,
+            ],
+        ],
+    ),
+    (
+        DataType::Complex128,
+        methods![
+            [pb::CompressionStage::ZSTD],
+            [
+                pb::CompressionStage::SPLIT_MANTISSA_FLOAT64,
+                pb::CompressionStage::ZSTD,
+            ],
+        ],
+    ),
+    (DataType::Int4, methods![[pb::CompressionStage::ZSTD],]),
+    (DataType::Uint4, methods![[pb::CompressionStage::ZSTD],]),
+    (
+        DataType::Float8E4M3,
+        methods![[pb::CompressionStage::ZSTD],],
+    ),
+    (
+        DataType::Float8E5M2,
+        methods![[pb::CompressionStage::ZSTD],],
+    ),
 ];
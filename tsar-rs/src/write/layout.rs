@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use crate::{
+    pb,
+    result::{Error, Result},
+    DataType,
+};
+
+/// Check that the blobs written to each target file do not overlap, and with
+/// `contiguous` that they cover the file from offset 0 without gaps.
+pub fn check_targets(blobs: &[pb::Blob], contiguous: bool) -> Result<()> {
+    let mut files = HashMap::<&str, Vec<(u64, u64, &str)>>::new();
+    for b in blobs {
+        if b.target_file_name.is_empty() {
+            continue;
+        }
+        let dt = DataType::try_from(b.data_type)
+            .map_err(|v| Error::Unsupported(format!("data type {v}")))?;
+        let len = b
+            .dims
+            .iter()
+            .try_fold(dt.bit_len() as u64, |n, &d| {
+                n.checked_mul(u64::try_from(d).ok()?)
+            })
+            .map(|bits| bits.div_ceil(8));
+        let range = u64::try_from(b.target_offset_in_bytes)
+            .ok()
+            .zip(len)
+            .and_then(|(start, len)| Some((start, start.checked_add(len)?)));
+        let Some((start, end)) = range else {
+            return Err(Error::TargetOutOfRange {
+                name: b.name.clone(),
+            });
+        };
+        files
+            .entry(&b.target_file_name)
+            .or_default()
+            .push((start, end, &b.name));
+    }
+
+    for (file, mut ranges) in files {
+        ranges.sort();
+        let mut end = 0;
+        let mut prev: Option<&str> = None;
+        for (s, e, name) in ranges {
+            if s == e {
+                continue;
+            }
+            if s < end {
+                return Err(Error::TargetOverlap {
+                    file: file.to_owned(),
+                    first: prev.unwrap_or_default().to_owned(),
+                    second: name.to_owned(),
+                });
+            }
+            if contiguous && s > end {
+                return Err(Error::TargetGap {
+                    file: file.to_owned(),
+                    offset: end,
+                    len: s - end,
+                    next: name.to_owned(),
+                });
+            }
+            end = e;
+            prev = Some(name);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use protobuf::EnumOrUnknown;
+
+    use super::*;
+    use crate::{BlobWriteOption, Builder};
+
+    fn blob(name: &str, file: &str, offset: i64, len: i64) -> pb::Blob {
+        pb::Blob {
+            name: name.into(),
+            data_type: EnumOrUnknown::new(DataType::Float32.into()),
+            dims: vec![len / 4],
+            target_file_name: file.into(),
+            target_offset_in_bytes: offset,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn overlap() {
+        let blobs = [
+            blob("a", "f", 0, 16),
+            blob("b", "f", 16, 16),
+            blob("c", "g", 8, 16),
+            blob("d", "", 0, 16),
+        ];
+        check_targets(&blobs, false).unwrap();
+
+        let blobs = [blob("a", "f", 0, 16), blob("b", "f", 12, 16)];
+        match check_targets(&blobs, false) {
+            Err(Error::TargetOverlap {
+                file,
+                first,
+                second,
+            }) => assert_eq!(
+                (file.as_str(), first.as_str(), second.as_str()),
+                ("f", "a", "b")
+            ),
+            r => panic!("{:?}", r.err()),
+        }
+    }
+
+    #[test]
+    fn out_of_range() {
+        let huge = pb::Blob {
+            dims: vec![1 << 40, 1 << 20],
+            ..blob("b", "f", 0, 0)
+        };
+        let blobs = [blob("a", "f", 0, 16), huge];
+        assert!(matches!(
+            check_targets(&blobs, false),
+            Err(Error::TargetOutOfRange { name }) if name == "b"
+        ));
+        let blobs = [blob("a", "f", -16, 16)];
+        assert!(matches!(
+            check_targets(&blobs, false),
+            Err(Error::TargetOutOfRange { .. })
+        ));
+
+        let mut b = Builder::new(Cursor::new(Vec::new()));
+        let opt = BlobWriteOption {
+            target_file: Some(("f".into(), u64::MAX - 4)),
+            ..Default::default()
+        };
+        b.add_blob("a", &[0; 16], DataType::Float32, &[4], opt)
+            .unwrap();
+        assert!(matches!(b.finish(), Err(Error::TargetOutOfRange { .. })));
+    }
+
+    #[test]
+    fn unknown_data_type() {
+        let b = pb::Blob {
+            data_type: EnumOrUnknown::from_i32(99),
+            ..blob("a", "f", 0, 16)
+        };
+        assert!(matches!(
+            check_targets(&[b], false),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn contiguous() {
+        let blobs = [
+            blob("a", "f", 0, 16),
+            blob("b", "f", 16, 0),
+            blob("c", "f", 16, 8),
+        ];
+        check_targets(&blobs, true).unwrap();
+
+        let blobs = [blob("a", "f", 0, 16), blob("b", "f", 20, 16)];
+        check_targets(&blobs, false).unwrap();
+        match check_targets(&blobs, true) {
+            Err(Error::TargetGap {
+                offset, len, next, ..
+            }) => assert_eq!((offset, len, next.as_str()), (16, 4, "b")),
+            r => panic!("{:?}", r.err()),
+        }
+
+        let blobs = [blob("a", "f", 4, 16)];
+        assert!(matches!(
+            check_targets(&blobs, true),
+            Err(Error::TargetGap { offset: 0, .. })
+        ));
+    }
+}
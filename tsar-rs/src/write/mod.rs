@@ -1,8 +1,13 @@
 mod consts;
+mod layout;
+mod regions;
+mod stream;
+mod strided;
 
 use std::{
+    borrow::Cow,
     collections::HashSet,
-    io::{Read, Seek, Write},
+    io::{BufWriter, Read, Seek, Write},
 };
 
 use base64::Engine;
@@ -10,40 +15,91 @@ use protobuf::{CodedOutputStream, EnumOrUnknown, Message};
 use sha1::{Digest, Sha1};
 use zip::write::SimpleFileOptions;
 
-use crate::{compress, paths, pb, result::Result, DataType};
+use crate::{
+    codec::{self, BufferList},
+    compress, format, metadata, paths, pb,
+    result::{Error, Result},
+    ByteOrder, DataType, Metadata, MetadataValue,
+};
+
+pub use regions::Region;
+pub use stream::BlobWriter;
+pub use strided::StridedView;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Bytes of leading data used to rank the candidate pipelines of a blob.
+const SAMPLE_LEN: usize = 64 * 1024;
+
 pub struct Builder<W: Write + Seek> {
     z: zip::write::ZipWriter<W>,
     meta: pb::Bundle,
     chunks: HashSet<String>,
+    contiguous_targets: bool,
+    ctx: compress::Context,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct BlobWriteOption {
     pub error_limit: f64,
     pub target_file: Option<(String, u64)>,
+    pub metadata: Metadata,
+    /// Byte order of the input data.
+    pub byte_order: ByteOrder,
 }
 
 impl<W: Write + Seek> Builder<W> {
     pub fn new(inner: W) -> Self {
         let mut z = zip::write::ZipWriter::new(inner);
-        z.set_comment(format!("tsar v{VERSION}"));
+        z.set_comment(format!("tsar v{VERSION}"))
+            .expect("comment fits in a zip archive");
         Self {
             z,
             meta: pb::Bundle::new(),
             chunks: HashSet::new(),
+            contiguous_targets: false,
+            ctx: compress::Context::new(),
+        }
+    }
+
+    /// Require the blobs of each target file to tile it without gaps.
+    /// Overlapping blobs are always rejected by [`Builder::finish`].
+    pub fn set_contiguous_targets(&mut self, contiguous: bool) {
+        self.contiguous_targets = contiguous;
+    }
+
+    /// Most bytes of compression scratch buffers held at once so far.
+    pub fn peak_memory(&self) -> usize {
+        self.ctx.peak_bytes()
+    }
+
+    /// Set an archive-wide metadata entry, replacing any previous value.
+    pub fn set_metadata(&mut self, key: impl Into<String>, value: impl Into<MetadataValue>) {
+        let e = metadata::entry(key.into(), value.into());
+        let m = &mut self.meta.metadata;
+        match m.binary_search_by(|o| o.key.cmp(&e.key)) {
+            Ok(i) => m[i] = e,
+            Err(i) => m.insert(i, e),
         }
     }
 
-    pub fn add_file(&mut self, name: impl Into<String>, mut reader: impl Read) -> Result<()> {
+    pub fn add_file(&mut self, name: impl Into<String>, reader: impl Read) -> Result<()> {
+        self.add_file_with_metadata(name, reader, Metadata::new())
+    }
+
+    pub fn add_file_with_metadata(
+        &mut self,
+        name: impl Into<String>,
+        mut reader: impl Read,
+        metadata: Metadata,
+    ) -> Result<()> {
         let name = name.into();
         self.z
             .start_file(name.clone(), SimpleFileOptions::default().large_file(true))?;
         std::io::copy(&mut reader, &mut self.z)?;
         self.meta.raw_files.push(pb::RawFile {
             name,
+            metadata: metadata::to_pb(metadata),
             ..Default::default()
         });
         Ok(())
@@ -58,58 +114,101 @@ impl<W: Write + Seek> Builder<W> {
         opt: BlobWriteOption,
     ) -> Result<()> {
         let shape = dims.into_iter().copied().collect::<Vec<_>>();
-        let mut b = pb::Blob {
-            name: name.into(),
-            dims: shape.iter().map(|&f| f as i64).collect(),
-            data_type: EnumOrUnknown::new(dt.into()),
-            ..Default::default()
-        };
-        if let Some((f, o)) = opt.target_file {
-            b.target_file_name = f;
-            b.target_offset_in_bytes = o as i64;
+        self.write_blob(name.into(), Cow::Borrowed(data), dt, shape, opt)
+    }
+
+    /// Add a blob from a possibly non-contiguous view. Row-major views are
+    /// compressed in place, others are gathered through a [`BlobWriter`] one
+    /// block at a time.
+    pub fn add_blob_strided(
+        &mut self,
+        name: impl Into<String>,
+        view: &StridedView,
+        opt: BlobWriteOption,
+    ) -> Result<()> {
+        let (dt, shape) = (view.data_type(), view.shape().to_vec());
+        if let Some(d) = view.as_contiguous() {
+            return self.write_blob(name.into(), Cow::Borrowed(d), dt, shape, opt);
         }
+        let mut w = self.blob_writer(name, dt, &shape, opt);
+        let mut buf = BufWriter::new(&mut w);
+        view.write_to(&mut buf)?;
+        buf.flush()?;
+        drop(buf);
+        w.finish()
+    }
 
-        let cand_stages = consts::COMPRESS_METHOD
-            .iter()
-            .find(|(t, _)| *t == dt)
-            .map(|(_, m)| *m)
-            .unwrap_or_default();
-
-        let blk = &data[..(64 * 1024).min(data.len())];
-        let mut sizes = cand_stages
-            .iter()
-            .enumerate()
-            .flat_map(|(i, &stages)| -> Result<_> {
-                let (r, e) = compress::compress(
-                    blk,
-                    dt,
-                    &[blk.len() / dt.byte_len()],
-                    stages,
-                    opt.error_limit,
-                )?;
-                Ok((i, r.iter().map(Vec::len).sum::<usize>(), e))
-            })
-            .filter(|&(_, _, e)| e <= opt.error_limit)
-            .collect::<Vec<_>>();
-        sizes.sort_by_key(|(_, sz, _)| *sz);
-
-        for (idx, _, _) in sizes {
-            let stages = cand_stages[idx];
-            let (output, err) = compress::compress(data, dt, &shape, stages, opt.error_limit)?;
-            if err > opt.error_limit {
-                continue;
+    /// Add a blob whose row-major data is read from `reader` through a
+    /// [`BlobWriter`], failing if it ends before the shape is filled.
+    pub fn add_blob_from_reader<'a>(
+        &mut self,
+        name: impl Into<String>,
+        reader: impl Read,
+        dt: DataType,
+        dims: impl IntoIterator<Item = &'a usize>,
+        opt: BlobWriteOption,
+    ) -> Result<()> {
+        let shape = dims.into_iter().copied().collect::<Vec<_>>();
+        let (_, len) = shape_size(dt, &shape)?;
+        let mut w = self.blob_writer(name, dt, &shape, opt);
+        std::io::copy(&mut reader.take(len as u64), &mut w)?;
+        w.finish()
+    }
+
+    /// Add a blob by writing its row-major data to the returned writer,
+    /// without holding all of it in memory.
+    pub fn blob_writer<'a>(
+        &mut self,
+        name: impl Into<String>,
+        dt: DataType,
+        dims: impl IntoIterator<Item = &'a usize>,
+        opt: BlobWriteOption,
+    ) -> BlobWriter<'_, W> {
+        let shape = dims.into_iter().copied().collect::<Vec<_>>();
+        let (error_limit, byte_order) = (opt.error_limit, opt.byte_order);
+        let size = shape_size(dt, &shape);
+        let b = blob_meta(name.into(), dt, &shape, opt);
+        BlobWriter::new(self, b, dt, size, error_limit, byte_order)
+    }
+
+    fn write_blob(
+        &mut self,
+        name: String,
+        data: Cow<[u8]>,
+        dt: DataType,
+        shape: Vec<usize>,
+        opt: BlobWriteOption,
+    ) -> Result<()> {
+        let (error_limit, byte_order) = (opt.error_limit, opt.byte_order);
+        let mut b = blob_meta(name, dt, &shape, opt);
+
+        // every stage works on little-endian data
+        let data = match byte_order {
+            ByteOrder::Little => data,
+            ByteOrder::Big => {
+                let mut d = data.into_owned();
+                codec::ByteSwap(dt.word_len()).swap_in_place(&mut d);
+                Cow::Owned(d)
             }
+        };
+        let data = &data[..];
 
-            b.compression_stages = cand_stages[idx]
-                .iter()
-                .cloned()
-                .map(EnumOrUnknown::new)
-                .collect();
-            return self.write_chunks(b, output.iter_slice());
+        let candidates = rank_stages(
+            &mut self.ctx,
+            &data[..SAMPLE_LEN.min(data.len())],
+            dt,
+            error_limit,
+        )?;
+        match encode(&mut self.ctx, data, dt, &shape, &candidates, error_limit)? {
+            Some((stages, output)) => {
+                b.compression_stages = stages.iter().cloned().map(EnumOrUnknown::new).collect();
+                b.chunk_ids = self.write_chunks(true, output.iter_slice())?;
+                self.ctx.recycle(output);
+            }
+            None => b.chunk_ids = self.write_chunks(false, [data])?,
         }
-
-        b.compression_stages.clear();
-        self.write_chunks(b, [data])
+        self.meta.blobs.push(b);
+        Ok(())
     }
 
     pub fn finish(self) -> Result<()> {
@@ -117,20 +216,25 @@ impl<W: Write + Seek> Builder<W> {
             mut z,
             mut meta,
             chunks: _,
+            contiguous_targets,
+            ctx: _,
         } = self;
+        layout::check_targets(&meta.blobs, contiguous_targets)?;
         z.start_file(paths::BUNDLE_META_PATH, SimpleFileOptions::default())?;
         meta.blobs.sort_by(|a, b| a.name.cmp(&b.name));
-        // TODO check target_file contiguous
+        format::stamp(&mut meta);
         meta.write_to(&mut CodedOutputStream::new(&mut z)).unwrap();
         z.finish()?;
         Ok(())
     }
 
+    /// Store each chunk once, returning the ids of all of them in order.
     fn write_chunks<'a>(
         &mut self,
-        mut blob: pb::Blob,
+        compressed: bool,
         iter: impl IntoIterator<Item = &'a [u8]>,
-    ) -> Result<()> {
+    ) -> Result<Vec<String>> {
+        let mut ids = vec![];
         for o in iter {
             let result = base64::prelude::BASE64_URL_SAFE.encode(Sha1::digest(o));
 
@@ -138,21 +242,104 @@ impl<W: Write + Seek> Builder<W> {
                 self.z.start_file(
                     paths::chunk_path(&result),
                     SimpleFileOptions::default()
-                        .compression_method(if blob.compression_stages.is_empty() {
+                        .compression_method(if compressed {
+                            zip::CompressionMethod::Stored
+                        } else {
                             // use zip compression when no custom compression stage
                             zip::CompressionMethod::DEFLATE
-                        } else {
-                            zip::CompressionMethod::Stored
                         })
                         .large_file(true),
                 )?;
                 self.z.write_all(o)?;
                 self.chunks.insert(result.clone());
             }
-            blob.chunk_ids.push(result);
+            ids.push(result);
         }
+        Ok(ids)
+    }
+}
 
-        self.meta.blobs.push(blob);
-        Ok(())
+fn blob_meta(name: String, dt: DataType, shape: &[usize], opt: BlobWriteOption) -> pb::Blob {
+    let mut b = pb::Blob {
+        name,
+        dims: shape.iter().map(|&f| f as i64).collect(),
+        data_type: EnumOrUnknown::new(dt.into()),
+        metadata: metadata::to_pb(opt.metadata),
+        byte_order: EnumOrUnknown::new(opt.byte_order.into()),
+        ..Default::default()
+    };
+    if let Some((f, o)) = opt.target_file {
+        b.target_file_name = f;
+        b.target_offset_in_bytes = o as i64;
+    }
+    b
+}
+
+/// Elements and bytes of a blob, failing when they overflow.
+fn shape_size(dt: DataType, shape: &[usize]) -> Result<(usize, usize)> {
+    shape
+        .iter()
+        .try_fold(1usize, |n, &d| n.checked_mul(d))
+        .and_then(|n| Some((n, n.checked_mul(dt.bit_len())?.div_ceil(8))))
+        .ok_or_else(|| Error::Unsupported(format!("blob shape {shape:?} is too large")))
+}
+
+/// Mangled NaN or infinite values give an infinite error, which even an
+/// infinite limit must reject.
+fn within_limit(err: f64, error_limit: f64) -> bool {
+    err.is_finite() && err <= error_limit
+}
+
+/// Candidate pipelines for `dt` meeting `error_limit` on `sample`, smallest
+/// output first.
+fn rank_stages(
+    ctx: &mut compress::Context,
+    sample: &[u8],
+    dt: DataType,
+    error_limit: f64,
+) -> Result<Vec<&'static [pb::CompressionStage]>> {
+    let cand_stages = consts::COMPRESS_METHOD
+        .iter()
+        .find(|(t, _)| *t == dt)
+        .map(|(_, m)| *m)
+        .unwrap_or_default();
+
+    let mut sizes = cand_stages
+        .iter()
+        .flat_map(|&stages| -> Result<_> {
+            let (r, e) = ctx.compress(
+                sample,
+                dt,
+                &[sample.len() * 8 / dt.bit_len()],
+                stages,
+                error_limit,
+            )?;
+            let sz = r.iter().map(Vec::len).sum::<usize>();
+            ctx.recycle(r);
+            Ok((stages, sz, e))
+        })
+        .filter(|&(_, _, e)| within_limit(e, error_limit))
+        .collect::<Vec<_>>();
+    sizes.sort_by_key(|(_, sz, _)| *sz);
+    Ok(sizes.into_iter().map(|(s, _, _)| s).collect())
+}
+
+/// Compress `data` with the first candidate that stays within `error_limit`,
+/// or return `None` when it should be stored as is.
+fn encode(
+    ctx: &mut compress::Context,
+    data: &[u8],
+    dt: DataType,
+    shape: &[usize],
+    candidates: &[&'static [pb::CompressionStage]],
+    error_limit: f64,
+) -> Result<Option<(&'static [pb::CompressionStage], BufferList)>> {
+    for &stages in candidates {
+        let (output, err) = ctx.compress(data, dt, shape, stages, error_limit)?;
+        if within_limit(err, error_limit) {
+            return Ok(Some((stages, output)));
+        }
+        ctx.recycle(output);
     }
+    Ok(None)
 }
@@ -0,0 +1,274 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use super::{BlobWriteOption, Builder};
+use crate::{
+    result::{Error, Result},
+    DataType, Metadata,
+};
+
+/// A tensor stored at a known place in a file.
+#[derive(Clone, Debug)]
+pub struct Region {
+    pub name: String,
+    pub offset: u64,
+    pub len: u64,
+    pub data_type: DataType,
+    pub shape: Vec<usize>,
+    /// Added to the metadata of the [`BlobWriteOption`].
+    pub metadata: Metadata,
+}
+
+impl Region {
+    /// A region of raw bytes.
+    pub fn bytes(name: impl Into<String>, offset: u64, len: u64) -> Self {
+        Self {
+            name: name.into(),
+            offset,
+            len,
+            data_type: DataType::Byte,
+            shape: vec![len as usize],
+            metadata: Metadata::new(),
+        }
+    }
+}
+
+impl<W: Write + Seek> Builder<W> {
+    /// Add the file in `reader`, to be extracted as `file`, with each region
+    /// as a blob targeting its offset. The bytes around the regions become
+    /// `{file}[gap@{offset}]` blobs, except runs of zeros before the end of
+    /// the file, which extraction fills in by itself, unless
+    /// [`Builder::set_contiguous_targets`] was enabled first. Empty regions
+    /// become blobs without a target file, so every region has a blob.
+    pub fn add_regions(
+        &mut self,
+        file: &str,
+        mut reader: impl Read + Seek,
+        regions: impl IntoIterator<Item = Region>,
+        opt: BlobWriteOption,
+    ) -> Result<()> {
+        let file_len = reader.seek(SeekFrom::End(0))?;
+        let mut regions = regions.into_iter().collect::<Vec<_>>();
+        regions.sort_by_key(|r| r.offset);
+
+        // check the whole manifest before writing anything
+        let mut end = 0;
+        let mut prev: Option<&str> = None;
+        for r in &regions {
+            let n = r.shape.iter().try_fold(1usize, |n, &d| n.checked_mul(d));
+            if n.and_then(|n| n.checked_mul(r.data_type.bit_len()))
+                .map(|bits| bits.div_ceil(8) as u64)
+                != Some(r.len)
+            {
+                return Err(Error::RegionLength {
+                    name: r.name.clone(),
+                    len: r.len,
+                });
+            }
+            match r.offset.checked_add(r.len) {
+                Some(e) if e <= file_len => {
+                    if r.len == 0 {
+                        continue;
+                    }
+                    if r.offset < end {
+                        return Err(Error::TargetOverlap {
+                            file: file.to_owned(),
+                            first: prev.unwrap_or_default().to_owned(),
+                            second: r.name.clone(),
+                        });
+                    }
+                    end = e;
+                    prev = Some(&r.name);
+                }
+                _ => {
+                    return Err(Error::RegionOutOfBounds {
+                        name: r.name.clone(),
+                        file_len,
+                    })
+                }
+            }
+        }
+
+        let target = |offset: u64| BlobWriteOption {
+            target_file: Some((file.to_owned(), offset)),
+            ..opt.clone()
+        };
+        let mut pos = 0;
+        for r in regions {
+            if r.len == 0 {
+                let mut opt = BlobWriteOption {
+                    target_file: None,
+                    ..opt.clone()
+                };
+                opt.metadata.extend(r.metadata);
+                self.add_blob(r.name, &[], r.data_type, &r.shape, opt)?;
+                continue;
+            }
+            self.add_gap(file, &mut reader, pos, r.offset, false, target(pos))?;
+            pos = r.offset + r.len;
+
+            let mut opt = target(r.offset);
+            opt.metadata.extend(r.metadata);
+            reader.seek(SeekFrom::Start(r.offset))?;
+            self.add_blob_from_reader(r.name, &mut reader, r.data_type, &r.shape, opt)?;
+        }
+        self.add_gap(file, &mut reader, pos, file_len, true, target(pos))
+    }
+
+    /// Store the bytes between `start` and `end` unless they are all zero.
+    /// Trailing bytes are always stored so the file keeps its length, and
+    /// all bytes when targets must be contiguous.
+    fn add_gap(
+        &mut self,
+        file: &str,
+        reader: &mut (impl Read + Seek),
+        start: u64,
+        end: u64,
+        trailing: bool,
+        opt: BlobWriteOption,
+    ) -> Result<()> {
+        if start == end {
+            return Ok(());
+        }
+        let mut gap = vec![0; (end - start) as usize];
+        reader.seek(SeekFrom::Start(start))?;
+        reader.read_exact(&mut gap)?;
+        if !trailing && !self.contiguous_targets && gap.iter().all(|&b| b == 0) {
+            return Ok(());
+        }
+        self.add_blob(
+            format!("{file}[gap@{start}]"),
+            &gap,
+            DataType::Byte,
+            &[gap.len()],
+            opt,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use super::*;
+    use crate::{Archive, ExtractOption, MetadataValue};
+
+    fn region(name: &str, offset: u64, data_type: DataType, shape: &[usize]) -> Region {
+        Region {
+            name: name.into(),
+            offset,
+            len: data_type.size_of(shape.iter().product()) as u64,
+            data_type,
+            shape: shape.to_vec(),
+            metadata: Metadata::new(),
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut src = b"HEAD".to_vec();
+        src.resize(16, 0);
+        src.extend((0..6).flat_map(|i| (i as f32).to_le_bytes()));
+        src.extend([0xaa; 8]);
+        src.extend((0..4).flat_map(|i| (i as i64 * -3).to_le_bytes()));
+        src.extend([0; 5]);
+
+        let mut w = region("w", 16, DataType::Float32, &[2, 3]);
+        w.metadata.insert("k".into(), MetadataValue::Int(1));
+        let regions = [
+            region("i", 48, DataType::Int64, &[4]),
+            w,
+            region("e", 20, DataType::Float32, &[0, 4]),
+        ];
+
+        let mut buf = Cursor::new(Vec::new());
+        let mut b = Builder::new(&mut buf);
+        b.add_regions(
+            "f.bin",
+            Cursor::new(&src),
+            regions,
+            BlobWriteOption::default(),
+        )
+        .unwrap();
+        b.finish().unwrap();
+
+        let mut a = Archive::new(Cursor::new(buf.into_inner())).unwrap();
+        let mut names = a.blob_names().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "e",
+                "f.bin[gap@0]",
+                "f.bin[gap@40]",
+                "f.bin[gap@80]",
+                "i",
+                "w"
+            ]
+        );
+        let e = a.blob_by_name("e").unwrap();
+        assert_eq!(e.shape().into_iter().collect::<Vec<_>>(), [0, 4]);
+        assert_eq!(e.target_file(), None);
+        let w = a.blob_by_name("w").unwrap();
+        assert_eq!(w.data_type(), Some(DataType::Float32));
+        assert_eq!(w.target_file(), Some(("f.bin", 16)));
+        assert_eq!(w.metadata()["k"], MetadataValue::Int(1));
+
+        let dir = std::env::temp_dir().join(format!("tsar-regions-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        a.extract_to(&dir, ExtractOption::default()).unwrap();
+        assert_eq!(fs::read(dir.join("f.bin")).unwrap(), src);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn contiguous() {
+        let mut src = vec![0; 32];
+        src.extend((0..4).flat_map(|i| (i as f32).to_le_bytes()));
+        src.extend([0; 16]);
+        src.push(1);
+        let regions = || [region("w", 32, DataType::Float32, &[4])];
+
+        for contiguous in [false, true] {
+            let mut buf = Cursor::new(Vec::new());
+            let mut b = Builder::new(&mut buf);
+            b.set_contiguous_targets(contiguous);
+            b.add_regions(
+                "f.bin",
+                Cursor::new(&src),
+                regions(),
+                BlobWriteOption::default(),
+            )
+            .unwrap();
+            b.finish().unwrap();
+
+            let a = Archive::new(Cursor::new(buf.into_inner())).unwrap();
+            let mut names = a.blob_names().collect::<Vec<_>>();
+            names.sort();
+            if contiguous {
+                assert_eq!(names, ["f.bin[gap@0]", "f.bin[gap@48]", "w"]);
+            } else {
+                assert_eq!(names, ["f.bin[gap@48]", "w"]);
+            }
+        }
+    }
+
+    #[test]
+    fn bad_manifest() {
+        let src = vec![1; 64];
+        let add = |regions: Vec<Region>| {
+            let mut b = Builder::new(Cursor::new(Vec::new()));
+            b.add_regions("f", Cursor::new(&src), regions, BlobWriteOption::default())
+        };
+        let mut short = region("a", 0, DataType::Float32, &[4]);
+        short.len = 12;
+        assert!(matches!(add(vec![short]), Err(Error::RegionLength { .. })));
+        assert!(matches!(
+            add(vec![Region::bytes("a", 60, 8)]),
+            Err(Error::RegionOutOfBounds { .. })
+        ));
+        assert!(matches!(
+            add(vec![Region::bytes("a", 0, 8), Region::bytes("b", 4, 8)]),
+            Err(Error::TargetOverlap { .. })
+        ));
+    }
+}
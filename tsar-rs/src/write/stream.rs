@@ -0,0 +1,213 @@
+use std::io::{self, Seek, Write};
+
+use protobuf::EnumOrUnknown;
+
+use super::{encode, rank_stages, Builder, SAMPLE_LEN};
+use crate::{
+    codec, pb,
+    result::{Error, Result},
+    ByteOrder, DataType,
+};
+
+/// Bytes of input compressed at a time by a [`BlobWriter`].
+pub(crate) const STREAM_BLOCK_LEN: usize = 16 << 20;
+
+/// Writer returned by [`Builder::blob_writer`].
+///
+/// Data is compressed one block at a time, so memory use stays at a few
+/// blocks regardless of the blob size. The pipeline is ranked on the start
+/// of the first block; every block is verified against the error limit and
+/// falls back to the next candidate when it exceeds it.
+///
+/// The blob is only added to the archive by [`BlobWriter::finish`]. Chunks of
+/// the blocks compressed before the writer is dropped or fails stay in the
+/// archive without a blob referring to them; readers ignore them, and a later
+/// blob with the same data reuses them.
+pub struct BlobWriter<'b, W: Write + Seek> {
+    builder: &'b mut Builder<W>,
+    blob: pb::Blob,
+    dt: DataType,
+    error_limit: f64,
+    byte_order: ByteOrder,
+    block: Vec<u8>,
+    block_len: usize,
+    remaining: usize,
+    elements: usize,
+    candidates: Option<Vec<&'static [pb::CompressionStage]>>,
+    shape_error: Option<Error>,
+}
+
+impl<'b, W: Write + Seek> BlobWriter<'b, W> {
+    pub(super) fn new(
+        builder: &'b mut Builder<W>,
+        blob: pb::Blob,
+        dt: DataType,
+        size: Result<(usize, usize)>,
+        error_limit: f64,
+        byte_order: ByteOrder,
+    ) -> Self {
+        // an overflowing shape takes no data and fails in `finish`
+        let ((elements, len), shape_error) = match size {
+            Ok(size) => (size, None),
+            Err(e) => ((0, 0), Some(e)),
+        };
+        let block_len = STREAM_BLOCK_LEN / dt.byte_len() * dt.byte_len();
+        Self {
+            builder,
+            blob,
+            dt,
+            error_limit,
+            byte_order,
+            block: Vec::with_capacity(block_len.min(len)),
+            block_len,
+            remaining: len,
+            elements,
+            candidates: None,
+            shape_error,
+        }
+    }
+
+    fn flush_block(&mut self) -> Result<()> {
+        if self.byte_order == ByteOrder::Big {
+            codec::ByteSwap(self.dt.word_len()).swap_in_place(&mut self.block);
+        }
+        let data = &self.block[..];
+        let candidates = match &self.candidates {
+            Some(c) => c,
+            None => self.candidates.insert(rank_stages(
+                &mut self.builder.ctx,
+                &data[..SAMPLE_LEN.min(data.len())],
+                self.dt,
+                self.error_limit,
+            )?),
+        };
+
+        // the last block of a packed type may end in a padding nibble
+        let n = (data.len() * 8 / self.dt.bit_len()).min(self.elements);
+        self.elements -= n;
+        let mut block = pb::BlobBlock {
+            num_elements: n as i64,
+            ..Default::default()
+        };
+        match encode(
+            &mut self.builder.ctx,
+            data,
+            self.dt,
+            &[n],
+            candidates,
+            self.error_limit,
+        )? {
+            Some((stages, output)) => {
+                block.compression_stages = stages.iter().cloned().map(EnumOrUnknown::new).collect();
+                block.chunk_ids = self.builder.write_chunks(true, output.iter_slice())?;
+                self.builder.ctx.recycle(output);
+            }
+            None => block.chunk_ids = self.builder.write_chunks(false, [data])?,
+        }
+        self.blob.blocks.push(block);
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Compress the last block and add the blob, failing if fewer bytes than
+    /// the shape requires were written or the shape is too large.
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(e) = self.shape_error.take() {
+            return Err(e);
+        }
+        if self.remaining > 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        if !self.block.is_empty() || self.blob.blocks.is_empty() {
+            self.flush_block()?;
+        }
+        self.builder.meta.blobs.push(std::mem::take(&mut self.blob));
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Write for BlobWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "data exceeds the blob shape",
+            ));
+        }
+        let n = buf
+            .len()
+            .min(self.block_len - self.block.len())
+            .min(self.remaining);
+        self.block.extend_from_slice(&buf[..n]);
+        self.remaining -= n;
+        if self.block.len() == self.block_len {
+            self.flush_block().map_err(io::Error::other)?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::*;
+    use crate::{Archive, BlobWriteOption};
+
+    #[test]
+    fn stream() {
+        let n = STREAM_BLOCK_LEN / 4 * 2 + 1000;
+        let data = (0..n)
+            .flat_map(|i| ((i % 1000) as f32 * 0.125).to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let mut buf = Cursor::new(Vec::new());
+        let mut b = Builder::new(&mut buf);
+        let mut w = b.blob_writer("w", DataType::Float32, &[n], BlobWriteOption::default());
+        for c in data.chunks(1 << 20) {
+            w.write_all(c).unwrap();
+        }
+        assert!(w.write(&[0]).is_err());
+        w.finish().unwrap();
+
+        let w = b.blob_writer("short", DataType::Float32, &[4], BlobWriteOption::default());
+        assert!(w.finish().is_err());
+
+        let huge = [usize::MAX / 2, 2];
+        let mut w = b.blob_writer("huge", DataType::Float32, &huge, BlobWriteOption::default());
+        assert!(w.write(&[0; 4]).is_err());
+        assert!(matches!(w.finish(), Err(Error::Unsupported(_))));
+        let r = b.add_blob_from_reader(
+            "huge",
+            io::empty(),
+            DataType::Float32,
+            &huge,
+            BlobWriteOption::default(),
+        );
+        assert!(matches!(r, Err(Error::Unsupported(_))));
+
+        b.blob_writer("empty", DataType::Int32, &[0], BlobWriteOption::default())
+            .finish()
+            .unwrap();
+        b.finish().unwrap();
+
+        let mut a = Archive::new(Cursor::new(buf.into_inner())).unwrap();
+        assert_eq!(a.blob_names().collect::<Vec<_>>(), ["empty", "w"]);
+        let mut out = vec![];
+        a.blob_by_name("w").unwrap().read_to_end(&mut out).unwrap();
+        assert!(out == data);
+        let mut out = vec![];
+        a.blob_by_name("empty")
+            .unwrap()
+            .read_to_end(&mut out)
+            .unwrap();
+        assert!(out.is_empty());
+    }
+}
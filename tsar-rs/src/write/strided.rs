@@ -0,0 +1,234 @@
+use std::io::{self, Write};
+
+use crate::{
+    result::{Error, Result},
+    DataType,
+};
+
+/// Strided view over the elements of a tensor stored in `data`, e.g. a
+/// transposed or sliced NumPy array.
+///
+/// Strides are in bytes and may be negative; `offset` is the byte position
+/// of the first element.
+#[derive(Clone, Debug)]
+pub struct StridedView<'a> {
+    data: &'a [u8],
+    offset: usize,
+    dt: DataType,
+    shape: Vec<usize>,
+    strides: Vec<isize>,
+}
+
+impl<'a> StridedView<'a> {
+    pub fn new(
+        data: &'a [u8],
+        offset: usize,
+        dt: DataType,
+        shape: &[usize],
+        strides: &[isize],
+    ) -> Result<Self> {
+        if shape.len() != strides.len() {
+            return Err(Error::InvalidView("shape and strides differ in rank"));
+        }
+        if dt.bit_len() < 8 {
+            return Err(Error::Unsupported(format!("strided view of {dt:?}")));
+        }
+        if shape
+            .iter()
+            .try_fold(dt.byte_len(), |n, &d| n.checked_mul(d))
+            .is_none()
+        {
+            return Err(Error::ViewOutOfBounds);
+        }
+        if shape.iter().all(|&n| n > 0) {
+            let start = isize::try_from(offset).map_err(|_| Error::ViewOutOfBounds)?;
+            let (mut lo, mut hi) = (start, start);
+            for (&n, &s) in shape.iter().zip(strides) {
+                let span = isize::try_from(n - 1)
+                    .ok()
+                    .and_then(|n| n.checked_mul(s))
+                    .ok_or(Error::ViewOutOfBounds)?;
+                let end = if span < 0 { &mut lo } else { &mut hi };
+                *end = end.checked_add(span).ok_or(Error::ViewOutOfBounds)?;
+            }
+            let end = usize::try_from(hi)
+                .ok()
+                .and_then(|hi| hi.checked_add(dt.byte_len()));
+            if lo < 0 || end.is_none_or(|end| end > data.len()) {
+                return Err(Error::ViewOutOfBounds);
+            }
+        }
+        Ok(Self {
+            data,
+            offset,
+            dt,
+            shape: shape.to_vec(),
+            strides: strides.to_vec(),
+        })
+    }
+
+    /// View over a contiguous row-major tensor.
+    pub fn contiguous(data: &'a [u8], dt: DataType, shape: &[usize]) -> Result<Self> {
+        let mut strides = vec![0; shape.len()];
+        let mut s = dt.byte_len() as isize;
+        for (st, &n) in strides.iter_mut().zip(shape).rev() {
+            *st = s;
+            s = isize::try_from(n)
+                .ok()
+                .and_then(|n| s.checked_mul(n))
+                .ok_or(Error::ViewOutOfBounds)?;
+        }
+        Self::new(data, 0, dt, shape, &strides)
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.dt
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn byte_len(&self) -> usize {
+        self.shape.iter().product::<usize>() * self.dt.byte_len()
+    }
+
+    /// The elements as one row-major slice, if they are laid out that way.
+    pub fn as_contiguous(&self) -> Option<&'a [u8]> {
+        if self.byte_len() == 0 {
+            return Some(&[]);
+        }
+        let mut expected = self.dt.byte_len() as isize;
+        for (&n, &s) in self.shape.iter().zip(&self.strides).rev() {
+            if n > 1 && s != expected {
+                return None;
+            }
+            expected = expected.checked_mul(isize::try_from(n).ok()?)?;
+        }
+        Some(&self.data[self.offset..][..self.byte_len()])
+    }
+
+    /// Append the elements to `out` in row-major order.
+    pub fn gather(&self, out: &mut Vec<u8>) {
+        out.reserve(self.byte_len());
+        self.write_to(out).expect("writing to a Vec cannot fail");
+    }
+
+    /// Write the elements to `w` in row-major order, one contiguous run at
+    /// a time.
+    pub fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        if let Some(d) = self.as_contiguous() {
+            return w.write_all(d);
+        }
+        let (&inner_n, outer) = self.shape.split_last().expect("scalars are contiguous");
+        let inner_s = *self.strides.last().unwrap();
+        let elem = self.dt.byte_len();
+
+        // odometer over every dimension but the innermost
+        let mut idx = vec![0; outer.len()];
+        let mut pos = self.offset as isize;
+        loop {
+            if inner_s == elem as isize {
+                w.write_all(&self.data[pos as usize..][..inner_n * elem])?;
+            } else {
+                let mut p = pos;
+                for _ in 0..inner_n {
+                    w.write_all(&self.data[p as usize..][..elem])?;
+                    p += inner_s;
+                }
+            }
+
+            let mut d = outer.len();
+            loop {
+                if d == 0 {
+                    return Ok(());
+                }
+                d -= 1;
+                idx[d] += 1;
+                pos += self.strides[d];
+                if idx[d] < outer[d] {
+                    break;
+                }
+                pos -= self.strides[d] * outer[d] as isize;
+                idx[d] = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(v: &[u16]) -> Vec<u8> {
+        v.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn gather() {
+        // 2x3 row-major matrix
+        let d = bytes(&[0, 1, 2, 3, 4, 5]);
+        let v = StridedView::contiguous(&d, DataType::Uint16, &[2, 3]).unwrap();
+        assert_eq!(v.as_contiguous(), Some(&d[..]));
+
+        let t = StridedView::new(&d, 0, DataType::Uint16, &[3, 2], &[2, 6]).unwrap();
+        assert!(t.as_contiguous().is_none());
+        let mut out = vec![];
+        t.gather(&mut out);
+        assert_eq!(out, bytes(&[0, 3, 1, 4, 2, 5]));
+
+        // second column, reversed
+        let c = StridedView::new(&d, 8, DataType::Uint16, &[2], &[-6]).unwrap();
+        let mut out = vec![];
+        c.gather(&mut out);
+        assert_eq!(out, bytes(&[4, 1]));
+
+        let e = StridedView::new(&d, 0, DataType::Uint16, &[0, 3], &[100, 2]).unwrap();
+        let mut out = vec![];
+        e.gather(&mut out);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn bounds() {
+        let d = bytes(&[0, 1, 2, 3, 4, 5]);
+        for (offset, shape, strides) in [
+            (0, &[4, 2][..], &[4, 2][..]),
+            (2, &[2, 3], &[6, 2]),
+            (0, &[2], &[-2]),
+        ] {
+            assert!(matches!(
+                StridedView::new(&d, offset, DataType::Uint16, shape, strides),
+                Err(Error::ViewOutOfBounds)
+            ));
+        }
+        for (offset, shape, strides) in [
+            (usize::MAX, &[1][..], &[2][..]),
+            (0, &[usize::MAX], &[2]),
+            (0, &[2, 2], &[isize::MAX, isize::MAX]),
+            (8, &[2, 2], &[isize::MIN, 2]),
+            (isize::MAX as usize, &[2], &[isize::MAX]),
+            (0, &[usize::MAX, usize::MAX, 0], &[2, 2, 2]),
+        ] {
+            assert!(matches!(
+                StridedView::new(&d, offset, DataType::Uint16, shape, strides),
+                Err(Error::ViewOutOfBounds)
+            ));
+        }
+        assert!(matches!(
+            StridedView::contiguous(&d, DataType::Uint16, &[usize::MAX, 2]),
+            Err(Error::ViewOutOfBounds)
+        ));
+        let v = StridedView::new(&d, 0, DataType::Uint16, &[1 << 20], &[0]).unwrap();
+        assert!(v.as_contiguous().is_none());
+
+        assert!(matches!(
+            StridedView::new(&d, 0, DataType::Uint16, &[2, 3], &[6]),
+            Err(Error::InvalidView(_))
+        ));
+        assert!(matches!(
+            StridedView::new(&d, 0, DataType::Uint4, &[4], &[1]),
+            Err(Error::Unsupported(_))
+        ));
+    }
+}
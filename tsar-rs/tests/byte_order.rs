@@ -0,0 +1,41 @@
+use std::io::{Cursor, Read};
+
+use tsar::{Archive, BlobWriteOption, Builder, ByteOrder, DataType};
+
+#[test]
+fn big_endian_blob() {
+    let be = (0..4096)
+        .flat_map(|i| (i as f32 * 0.5).to_be_bytes())
+        .collect::<Vec<_>>();
+    let mut buf = Cursor::new(Vec::new());
+    let mut b = Builder::new(&mut buf);
+    b.add_blob(
+        "w",
+        &be,
+        DataType::Float32,
+        &[64, 64],
+        BlobWriteOption {
+            byte_order: ByteOrder::Big,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    b.finish().unwrap();
+
+    let mut a = Archive::new(Cursor::new(buf.into_inner())).unwrap();
+    let mut blob = a.blob_by_name("w").unwrap();
+    assert_eq!(blob.source_byte_order(), ByteOrder::Big);
+    let mut out = vec![];
+    blob.read_to_end(&mut out).unwrap();
+    assert_eq!(out, be);
+
+    let mut blob = a.blob_by_name("w").unwrap();
+    blob.set_byte_order(ByteOrder::Little).unwrap();
+    let mut out = vec![];
+    blob.read_to_end(&mut out).unwrap();
+    let le = (0..4096)
+        .flat_map(|i| (i as f32 * 0.5).to_le_bytes())
+        .collect::<Vec<_>>();
+    assert_eq!(out, le);
+    assert!(blob.set_byte_order(ByteOrder::Big).is_err());
+}
@@ -0,0 +1,162 @@
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    io::{Cursor, Read, Write},
+    path::PathBuf,
+};
+
+use protobuf::Message;
+use tsar::{pb, Archive, BlobWriteOption, Builder, DataType};
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+pub const DATA_TYPES: [DataType; 20] = [
+    DataType::Byte,
+    DataType::Float32,
+    DataType::Float64,
+    DataType::Float16,
+    DataType::Bfloat16,
+    DataType::Int8,
+    DataType::Uint8,
+    DataType::Int16,
+    DataType::Uint16,
+    DataType::Int32,
+    DataType::Uint32,
+    DataType::Int64,
+    DataType::Uint64,
+    DataType::Bool,
+    DataType::Complex64,
+    DataType::Complex128,
+    DataType::Int4,
+    DataType::Uint4,
+    DataType::Float8E4M3,
+    DataType::Float8E5M2,
+];
+
+/// Empty, scalar, odd-sized and multi-chunk shapes.
+pub const SHAPES: &[&[usize]] = &[
+    &[],
+    &[0],
+    &[3, 0],
+    &[1],
+    &[7],
+    &[3, 5],
+    &[2, 3, 9],
+    &[301, 257],
+];
+
+/// xorshift64*, so runs are reproducible without a `rand` dependency.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Random data of `dt` and `shape`. Floats follow a noisy sine so that
+    /// lossy pipelines have something to work with; other types are random
+    /// bytes.
+    pub fn tensor(&mut self, dt: DataType, shape: &[usize]) -> Vec<u8> {
+        let n = shape.iter().product::<usize>();
+        let mut value = |i: usize| (i as f64 * 0.05).sin() * 10.0 + (self.f64() - 0.5) * 0.1;
+        match dt {
+            DataType::Float32 => (0..n)
+                .flat_map(|i| (value(i) as f32).to_le_bytes())
+                .collect(),
+            DataType::Float64 => (0..n).flat_map(|i| value(i).to_le_bytes()).collect(),
+            DataType::Float16 => (0..n)
+                .flat_map(|i| half::f16::from_f64(value(i)).to_le_bytes())
+                .collect(),
+            DataType::Bfloat16 => (0..n)
+                .flat_map(|i| half::bf16::from_f64(value(i)).to_le_bytes())
+                .collect(),
+            DataType::Complex64 => (0..2 * n)
+                .flat_map(|i| (value(i) as f32).to_le_bytes())
+                .collect(),
+            DataType::Complex128 => (0..2 * n).flat_map(|i| value(i).to_le_bytes()).collect(),
+            _ => (0..dt.size_of(n)).map(|_| self.next_u64() as u8).collect(),
+        }
+    }
+}
+
+/// A fresh directory under the system temp dir, unique to `name` and this
+/// process.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tsar-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Pack `data` as one blob and read it back.
+pub fn roundtrip(dt: DataType, shape: &[usize], data: &[u8], error_limit: f64) -> Vec<u8> {
+    let mut buf = Cursor::new(Vec::new());
+    let mut b = Builder::new(&mut buf);
+    let opt = BlobWriteOption {
+        error_limit,
+        ..Default::default()
+    };
+    b.add_blob("t", data, dt, shape, opt).unwrap();
+    b.finish().unwrap();
+
+    let mut a = Archive::new(Cursor::new(buf.into_inner())).unwrap();
+    let mut blob = a.blob_by_name("t").unwrap();
+    assert_eq!(blob.data_type(), Some(dt));
+    assert_eq!(blob.shape().into_iter().collect::<Vec<_>>(), shape);
+    assert_eq!(blob.byte_len(), Some(data.len()));
+    let mut out = Vec::new();
+    blob.read_to_end(&mut out).unwrap();
+    out
+}
+
+/// Pack and unpack `data`, checking that it comes back within
+/// `error_limit`, and exactly when no lossy pipeline applies.
+pub fn assert_roundtrip(dt: DataType, shape: &[usize], data: &[u8], error_limit: f64) {
+    let out = roundtrip(dt, shape, data, error_limit);
+    let err = dt.max_difference(data, &out);
+    assert!(
+        err.is_some_and(|e| e <= error_limit),
+        "{dt:?} {shape:?} limit {error_limit}: error {err:?}"
+    );
+    // only f32 and f64 have lossy pipelines
+    let lossy = error_limit > 0.0 && matches!(dt, DataType::Float32 | DataType::Float64);
+    if !lossy {
+        assert!(
+            out == data,
+            "{dt:?} {shape:?} limit {error_limit}: not identical"
+        );
+    }
+}
+
+/// Rewrite the archive `src` with its bundle passed through `f`.
+pub fn rewrite_bundle(src: &[u8], f: impl FnOnce(&mut pb::Bundle)) -> Vec<u8> {
+    let mut f = Some(f);
+    let mut z = ZipArchive::new(Cursor::new(src)).unwrap();
+    let mut out = ZipWriter::new(Cursor::new(Vec::new()));
+    for i in 0..z.len() {
+        let mut e = z.by_index(i).unwrap();
+        let mut data = vec![];
+        e.read_to_end(&mut data).unwrap();
+        if e.name() == ".tsar/bundle" {
+            let mut meta = pb::Bundle::parse_from_bytes(&data).unwrap();
+            f.take().unwrap()(&mut meta);
+            data = meta.write_to_bytes().unwrap();
+        }
+        out.start_file(e.name(), SimpleFileOptions::default())
+            .unwrap();
+        out.write_all(&data).unwrap();
+    }
+    out.finish().unwrap().into_inner()
+}
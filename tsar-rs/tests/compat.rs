@@ -0,0 +1,213 @@
+//! Archives written by earlier versions must keep reading the same.
+mod common;
+#[path = "fixtures/unversioned.rs"]
+mod unversioned;
+
+use std::{
+    fs,
+    io::{Cursor, Read, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use common::{rewrite_bundle, Rng};
+use protobuf::EnumOrUnknown;
+use tsar::{
+    pb, Archive, BlobWriteOption, Builder, ByteOrder, DataType, Error, MetadataValue,
+    FORMAT_VERSION,
+};
+
+type Edit = fn(&mut pb::Bundle);
+
+/// Name, data type, shape, error limit and byte order of the fixture blobs.
+const BLOBS: &[(&str, DataType, &[usize], f64, ByteOrder)] = &[
+    ("f32", DataType::Float32, &[16, 25], 0.0, ByteOrder::Little),
+    ("f64", DataType::Float64, &[300], 1e-4, ByteOrder::Little),
+    ("bf16", DataType::Bfloat16, &[64], 0.0, ByteOrder::Little),
+    ("f16", DataType::Float16, &[5, 5], 0.0, ByteOrder::Little),
+    ("i32", DataType::Int32, &[7, 3], 0.0, ByteOrder::Big),
+    ("u4", DataType::Uint4, &[9], 0.0, ByteOrder::Little),
+    ("empty", DataType::Float32, &[0], 0.0, ByteOrder::Little),
+];
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn data(i: usize, dt: DataType, shape: &[usize]) -> Vec<u8> {
+    Rng::new(50 + i as u64).tensor(dt, shape)
+}
+
+fn streamed() -> Vec<u8> {
+    (0..1000)
+        .flat_map(|i| (i as f32 * 0.5).to_le_bytes())
+        .collect()
+}
+
+fn write(w: impl Write + std::io::Seek) {
+    let mut b = Builder::new(w);
+    b.set_metadata("fixture", "compat");
+    b.add_file("README.txt", &b"tsar fixture"[..]).unwrap();
+    for (i, &(name, dt, shape, error_limit, byte_order)) in BLOBS.iter().enumerate() {
+        let opt = BlobWriteOption {
+            error_limit,
+            byte_order,
+            target_file: (dt == DataType::Float32).then(|| ("model.bin".into(), i as u64 * 1600)),
+            ..Default::default()
+        };
+        b.add_blob(name, &data(i, dt, shape), dt, shape, opt)
+            .unwrap();
+    }
+    let mut s = b.blob_writer("streamed", DataType::Float32, &[1000], Default::default());
+    s.write_all(&streamed()).unwrap();
+    s.finish().unwrap();
+    b.finish().unwrap();
+}
+
+type Fixture = Archive<Cursor<Vec<u8>>>;
+
+/// Open fixture `name` and check its README.
+fn open(name: &str) -> Fixture {
+    let mut a = Archive::new(Cursor::new(fs::read(fixture(name)).unwrap())).unwrap();
+    let mut readme = String::new();
+    a.file_by_name("README.txt")
+        .unwrap()
+        .read_to_string(&mut readme)
+        .unwrap();
+    assert_eq!(readme, "tsar fixture");
+    a
+}
+
+fn check_blob(
+    a: &mut Fixture,
+    (name, dt, shape, error_limit, byte_order): (&str, DataType, &[usize], f64, ByteOrder),
+    expected: &[u8],
+) {
+    let mut b = a.blob_by_name(name).unwrap();
+    assert_eq!(b.data_type(), Some(dt), "{name}");
+    assert_eq!(b.shape().into_iter().collect::<Vec<_>>(), shape, "{name}");
+    assert_eq!(b.byte_order(), byte_order, "{name}");
+    let mut back = vec![];
+    b.read_to_end(&mut back).unwrap();
+    if error_limit > 0.0 {
+        assert!(dt.max_difference(expected, &back).unwrap() <= error_limit);
+    } else {
+        assert_eq!(back, expected, "{name}");
+    }
+}
+
+fn check(name: &str) -> Fixture {
+    let mut a = open(name);
+    assert_eq!(
+        a.metadata()["fixture"],
+        MetadataValue::String("compat".into())
+    );
+    for (i, &blob @ (_, dt, shape, ..)) in BLOBS.iter().enumerate() {
+        check_blob(&mut a, blob, &data(i, dt, shape));
+    }
+    let mut back = vec![];
+    a.blob_by_name("streamed")
+        .unwrap()
+        .read_to_end(&mut back)
+        .unwrap();
+    assert_eq!(back, streamed());
+    a
+}
+
+#[test]
+fn unversioned() {
+    let mut a = open("unversioned.tsar");
+    assert_eq!(a.format_version(), (0, 0));
+    assert_eq!(a.blob_names().count(), unversioned::BLOBS.len());
+    for (i, &(name, dt, shape, error_limit)) in unversioned::BLOBS.iter().enumerate() {
+        let blob = (name, dt, shape, error_limit, ByteOrder::Little);
+        check_blob(&mut a, blob, &unversioned::data(i, dt, shape));
+    }
+}
+
+#[test]
+fn v1_0() {
+    assert_eq!(check("v1.0.tsar").format_version(), (1, 0));
+    let src = fs::read(fixture("v1.0.tsar")).unwrap();
+    rewrite_bundle(&src, |m| assert_eq!(m.required_features, ["blob-blocks"]));
+}
+
+/// Collects the warnings logged by the reader.
+struct Warnings(Mutex<Vec<String>>);
+
+impl log::Log for Warnings {
+    fn enabled(&self, m: &log::Metadata) -> bool {
+        m.level() <= log::Level::Warn
+    }
+
+    fn log(&self, r: &log::Record) {
+        if self.enabled(r.metadata()) {
+            self.0.lock().unwrap().push(r.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static WARNINGS: Warnings = Warnings(Mutex::new(Vec::new()));
+
+#[test]
+fn newer_versions() {
+    log::set_logger(&WARNINGS).unwrap();
+    log::set_max_level(log::LevelFilter::Warn);
+    let src = fs::read(fixture("v1.0.tsar")).unwrap();
+    let open = |f: Edit| Archive::new(Cursor::new(rewrite_bundle(&src, f)));
+
+    // a newer minor version only adds what older readers may ignore
+    let a = open(|m| {
+        m.format_minor += 1;
+        m.special_fields.mut_unknown_fields().add_varint(99, 1);
+    })
+    .unwrap();
+    assert_eq!(a.format_version(), (FORMAT_VERSION.0, FORMAT_VERSION.1 + 1));
+    let warning = format!(
+        "archive format {}.{} is newer",
+        FORMAT_VERSION.0,
+        FORMAT_VERSION.1 + 1
+    );
+    assert!(WARNINGS
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|w| w.starts_with(&warning)));
+
+    let rejected: [(Edit, &str); 3] = [
+        (|m| m.format_major += 1, "archive format 2.0"),
+        (
+            |m| m.required_features.push("sparse-blobs".into()),
+            "archive features [\"sparse-blobs\"]",
+        ),
+        (
+            |m| {
+                m.blobs[0]
+                    .compression_stages
+                    .push(EnumOrUnknown::from_i32(99))
+            },
+            "compression stage 99 of blob \"bf16\"",
+        ),
+    ];
+    for (f, msg) in rejected {
+        match open(f) {
+            Err(Error::Unsupported(m)) => assert!(m.starts_with(msg), "{m}"),
+            Err(e) => panic!("{msg}: {e}"),
+            Ok(_) => panic!("{msg}: opened"),
+        }
+    }
+}
+
+/// Write the fixture of the current format version with
+/// `cargo test -- --ignored`, after bumping it.
+#[test]
+#[ignore]
+fn write_fixture() {
+    let (major, minor) = FORMAT_VERSION;
+    write(fs::File::create(fixture(&format!("v{major}.{minor}.tsar"))).unwrap());
+}
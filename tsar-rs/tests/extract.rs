@@ -0,0 +1,142 @@
+mod common;
+
+use std::{
+    fs,
+    io::{Cursor, Read},
+};
+
+use common::temp_dir;
+use tsar::{Archive, BlobWriteOption, Builder, DataType, Error, ExtractOption, Overwrite};
+
+#[test]
+fn extract() {
+    let mut buf = Cursor::new(Vec::new());
+    let mut b = Builder::new(&mut buf);
+    let mut expected = vec![];
+    let mut offset = 0;
+    for i in 0..16 {
+        let d = (0..1000 + i * 10)
+            .flat_map(|v| (v as f32 / (i + 1) as f32).to_le_bytes())
+            .collect::<Vec<_>>();
+        b.add_blob(
+            format!("w{i}"),
+            &d,
+            DataType::Float32,
+            &[d.len() / 4],
+            BlobWriteOption {
+                target_file: Some(("model.data".into(), offset)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        offset += d.len() as u64;
+        expected.extend(d);
+    }
+    b.add_file("sub/model.json", &b"{}"[..]).unwrap();
+    assert!(b.peak_memory() > 0);
+    b.finish().unwrap();
+
+    let dir = temp_dir("extract");
+    let mut a = Archive::new(Cursor::new(buf.into_inner())).unwrap();
+    let opt = || ExtractOption {
+        threads: 3,
+        ..Default::default()
+    };
+    assert_eq!(a.peak_memory(), 0);
+    let mut d = vec![];
+    a.blob_by_name("w0").unwrap().read_to_end(&mut d).unwrap();
+    let peak = a.peak_memory();
+    assert!(peak > 0);
+    // the next blob reuses the buffers of the first
+    a.blob_by_name("w1").unwrap().read_to_end(&mut d).unwrap();
+    assert!(a.peak_memory() < peak * 2, "{peak} {}", a.peak_memory());
+    a.extract_to(&dir, opt()).unwrap();
+    assert!(a.peak_memory() > peak);
+    assert_eq!(fs::read(dir.join("model.data")).unwrap(), expected);
+    assert_eq!(fs::read(dir.join("sub/model.json")).unwrap(), b"{}");
+
+    assert!(matches!(
+        a.extract_to(&dir, opt()),
+        Err(Error::AlreadyExists(_))
+    ));
+
+    fs::write(dir.join("model.data"), b"keep").unwrap();
+    a.extract_to(
+        &dir,
+        ExtractOption {
+            overwrite: Overwrite::Skip,
+            ..opt()
+        },
+    )
+    .unwrap();
+    assert_eq!(fs::read(dir.join("model.data")).unwrap(), b"keep");
+
+    a.extract_to(
+        &dir,
+        ExtractOption {
+            overwrite: Overwrite::Replace,
+            ..opt()
+        },
+    )
+    .unwrap();
+    assert_eq!(fs::read(dir.join("model.data")).unwrap(), expected);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+fn archive_with(name: &str, target: &str) -> Archive<Cursor<Vec<u8>>> {
+    let mut buf = Cursor::new(Vec::new());
+    let mut b = Builder::new(&mut buf);
+    b.add_file(name, &b"evil"[..]).unwrap();
+    b.add_blob(
+        "blob",
+        &[1, 2, 3],
+        DataType::Byte,
+        &[3],
+        BlobWriteOption {
+            target_file: Some((target.into(), 0)),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    b.finish().unwrap();
+    Archive::new(Cursor::new(buf.into_inner())).unwrap()
+}
+
+#[test]
+fn reject_traversal() {
+    let dir = temp_dir("traversal");
+    for (name, target) in [
+        ("../escape", "ok.data"),
+        ("/etc/escape", "ok.data"),
+        ("ok.json", "a/../../escape"),
+        (".", "ok.data"),
+    ] {
+        let res = archive_with(name, target).extract_to(&dir, ExtractOption::default());
+        assert!(matches!(res, Err(Error::UnsafePath(_))), "{name} {target}");
+        assert!(!dir.join(name).exists(), "{name} {target}");
+        let _ = fs::remove_dir_all(&dir);
+    }
+    assert!(!std::env::temp_dir().join("escape").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn reject_symlink() {
+    let dir = temp_dir("symlink");
+    let outside = temp_dir("symlink-outside");
+    fs::create_dir_all(&dir).unwrap();
+    fs::create_dir_all(&outside).unwrap();
+    std::os::unix::fs::symlink(&outside, dir.join("link")).unwrap();
+
+    let res = archive_with("link/model.json", "ok.data").extract_to(
+        &dir,
+        ExtractOption {
+            overwrite: Overwrite::Replace,
+            ..Default::default()
+        },
+    );
+    assert!(matches!(res, Err(Error::Symlink(_))));
+    assert!(!outside.join("model.json").exists());
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(outside).unwrap();
+}
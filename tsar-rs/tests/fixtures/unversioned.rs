@@ -0,0 +1,67 @@
+//! Writer of `unversioned.tsar`, using only the API of commit 7d516ba, the
+//! last before archives recorded a format version. `compat.rs` includes it
+//! for the expected contents. To regenerate the fixture:
+//!
+//! ```sh
+//! git worktree add /tmp/tsar-unversioned 7d516ba
+//! cp tsar-rs/tests/fixtures/unversioned.rs /tmp/tsar-unversioned/tsar-rs/examples/
+//! cd /tmp/tsar-unversioned/tsar-rs
+//! cargo run --example unversioned -- $OLDPWD/tsar-rs/tests/fixtures/unversioned.tsar
+//! ```
+#![allow(dead_code)]
+
+use std::io::{Seek, Write};
+
+use tsar::{BlobWriteOption, Builder, DataType};
+
+/// Name, data type, shape and error limit of the fixture blobs. There is
+/// no empty blob, which that version cannot write.
+pub const BLOBS: &[(&str, DataType, &[usize], f64)] = &[
+    ("f32", DataType::Float32, &[16, 25], 0.0),
+    ("f64", DataType::Float64, &[300], 1e-4),
+    ("bf16", DataType::Bfloat16, &[64], 0.0),
+    ("f16", DataType::Float16, &[5, 5], 0.0),
+    ("i32", DataType::Int32, &[7, 3], 0.0),
+];
+
+/// Data of the `i`-th blob: floats follow a sine, integers count down.
+pub fn data(i: usize, dt: DataType, shape: &[usize]) -> Vec<u8> {
+    let n = shape.iter().product::<usize>();
+    let value = |j: usize| ((i * 7 + j) as f64 * 0.05).sin() * 10.0;
+    match dt {
+        DataType::Float32 => (0..n)
+            .flat_map(|j| (value(j) as f32).to_le_bytes())
+            .collect(),
+        DataType::Float64 => (0..n).flat_map(|j| value(j).to_le_bytes()).collect(),
+        DataType::Float16 => (0..n)
+            .flat_map(|j| half::f16::from_f64(value(j)).to_le_bytes())
+            .collect(),
+        DataType::Bfloat16 => (0..n)
+            .flat_map(|j| half::bf16::from_f64(value(j)).to_le_bytes())
+            .collect(),
+        DataType::Int32 => (0..n)
+            .flat_map(|j| (1000 - (i * 100 + j) as i32).to_le_bytes())
+            .collect(),
+        _ => unreachable!("{dt:?}"),
+    }
+}
+
+pub fn write(w: impl Write + Seek) {
+    let mut b = Builder::new(w);
+    b.add_file("README.txt", &b"tsar fixture"[..]).unwrap();
+    for (i, &(name, dt, shape, error_limit)) in BLOBS.iter().enumerate() {
+        let opt = BlobWriteOption {
+            error_limit,
+            target_file: (dt == DataType::Float32).then(|| ("model.bin".into(), i as u64 * 1600)),
+            ..Default::default()
+        };
+        b.add_blob(name, &data(i, dt, shape), dt, shape, opt)
+            .unwrap();
+    }
+    b.finish().unwrap();
+}
+
+fn main() {
+    let path = std::env::args().nth(1).expect("usage: unversioned <path>");
+    write(std::fs::File::create(path).unwrap());
+}
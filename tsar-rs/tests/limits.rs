@@ -0,0 +1,90 @@
+mod common;
+
+use std::io::{Cursor, Read};
+
+use common::temp_dir;
+use tsar::{Archive, BlobWriteOption, Builder, DataType, Error, ExtractOption, ReadLimits, Result};
+
+fn archive() -> Archive<Cursor<Vec<u8>>> {
+    let mut buf = Cursor::new(Vec::new());
+    let mut b = Builder::new(&mut buf);
+    for (name, n) in [("a", 1000), ("b", 3000)] {
+        let data = (0..n)
+            .flat_map(|i| (i as f32).to_le_bytes())
+            .collect::<Vec<_>>();
+        let opt = BlobWriteOption {
+            target_file: Some((name.into(), 0)),
+            ..Default::default()
+        };
+        b.add_blob(name, &data, DataType::Float32, &[n], opt)
+            .unwrap();
+    }
+    b.finish().unwrap();
+    Archive::new(Cursor::new(buf.into_inner())).unwrap()
+}
+
+fn read(a: &mut Archive<Cursor<Vec<u8>>>, name: &str) -> Result<usize> {
+    let mut d = vec![];
+    Ok(a.blob_by_name(name)?.read_to_end(&mut d)?)
+}
+
+#[test]
+fn limits() {
+    let mut a = archive();
+    a.set_limits(ReadLimits {
+        max_elements: 2000,
+        ..Default::default()
+    });
+    assert_eq!(read(&mut a, "a").unwrap(), 4000);
+    assert!(matches!(
+        read(&mut a, "b"),
+        Err(Error::LimitExceeded {
+            value: 3000,
+            limit: 2000,
+            ..
+        })
+    ));
+
+    a.set_limits(ReadLimits {
+        max_blob_bytes: 4000,
+        ..Default::default()
+    });
+    assert!(read(&mut a, "a").is_ok());
+    assert!(matches!(
+        read(&mut a, "b"),
+        Err(Error::LimitExceeded { value: 12000, .. })
+    ));
+    a.set_limits(ReadLimits {
+        max_chunks: 0,
+        ..Default::default()
+    });
+    assert!(matches!(
+        read(&mut a, "a"),
+        Err(Error::LimitExceeded { limit: 0, .. })
+    ));
+
+    // the blobs read so far count towards the archive
+    let mut a = archive();
+    a.set_limits(ReadLimits {
+        max_archive_bytes: 14000,
+        ..Default::default()
+    });
+    assert!(read(&mut a, "b").is_ok());
+    assert!(matches!(
+        read(&mut a, "a"),
+        Err(Error::LimitExceeded { value: 16000, .. })
+    ));
+
+    let mut a = archive();
+    a.set_limits(ReadLimits {
+        max_archive_bytes: 14000,
+        ..Default::default()
+    });
+    let dir = temp_dir("limits");
+    assert!(matches!(
+        a.extract_to(&dir, ExtractOption::default()),
+        Err(Error::LimitExceeded { .. })
+    ));
+    assert!(!dir.join("a").exists());
+    let _ = std::fs::remove_dir_all(dir);
+}
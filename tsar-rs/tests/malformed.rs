@@ -0,0 +1,200 @@
+mod common;
+
+use std::io::{Cursor, Read, Write};
+
+use common::{rewrite_bundle, Rng};
+use protobuf::EnumOrUnknown;
+use tsar::{pb, Archive, BlobWriteOption, Builder, DataType, Error, ExtractOption, ReadLimits};
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+const BUNDLE: &str = ".tsar/bundle";
+
+type Tamper = fn(&mut pb::Blob);
+
+/// An archive with a blob for each kind of pipeline.
+fn archive() -> Vec<u8> {
+    let mut rng = Rng::new(3);
+    let mut buf = Cursor::new(Vec::new());
+    let mut b = Builder::new(&mut buf);
+    for (dt, limit) in [
+        (DataType::Float32, 0.0),
+        (DataType::Float64, 0.0),
+        (DataType::Float64, 1e-3),
+        (DataType::Int32, 0.0),
+    ] {
+        let opt = BlobWriteOption {
+            error_limit: limit,
+            target_file: Some((format!("{dt:?}-{limit}"), 0)),
+            ..Default::default()
+        };
+        let data = rng.tensor(dt, &[40, 50]);
+        b.add_blob(format!("{dt:?}-{limit}"), &data, dt, &[40, 50], opt)
+            .unwrap();
+    }
+    b.add_blob("empty", &[], DataType::Float32, &[0], Default::default())
+        .unwrap();
+    b.finish().unwrap();
+    buf.into_inner()
+}
+
+/// Rewrite `src` with every blob of its bundle passed through `f`.
+fn tamper(src: &[u8], f: impl Fn(&mut pb::Blob)) -> Vec<u8> {
+    rewrite_bundle(src, |meta| meta.blobs.iter_mut().for_each(f))
+}
+
+/// Read every blob of `src`, counting the ones that fail.
+fn read_all(src: Vec<u8>) -> usize {
+    let mut a = Archive::new(Cursor::new(src)).unwrap();
+    let names = a.blob_names().map(str::to_owned).collect::<Vec<_>>();
+    names
+        .iter()
+        .filter(|n| {
+            let mut d = vec![];
+            a.blob_by_name(n)
+                .and_then(|mut b| Ok(b.read_to_end(&mut d)?))
+                .is_err()
+        })
+        .count()
+}
+
+#[test]
+fn valid() {
+    assert_eq!(read_all(archive()), 0);
+}
+
+#[test]
+fn tampered_bundle() {
+    let src = archive();
+    let blobs = Archive::new(Cursor::new(&src[..]))
+        .unwrap()
+        .blob_names()
+        .count();
+    let cases: [(&str, Tamper); 10] = [
+        ("unknown data type", |b| {
+            b.data_type = EnumOrUnknown::from_i32(99)
+        }),
+        ("no data type", |b| b.data_type = EnumOrUnknown::from_i32(0)),
+        ("negative dim", |b| b.dims.insert(0, -1)),
+        ("huge dims", |b| b.dims = vec![i64::MAX, i64::MAX]),
+        ("wrong shape", |b| b.dims.push(3)),
+        ("missing chunk", |b| b.chunk_ids.push("missing".into())),
+        ("invalid stage", |b| {
+            b.compression_stages.push(EnumOrUnknown::from_i32(0))
+        }),
+        ("negative block", |b| {
+            b.blocks.push(pb::BlobBlock {
+                num_elements: -1,
+                ..Default::default()
+            })
+        }),
+        ("short blocks", |b| {
+            b.blocks.push(pb::BlobBlock {
+                num_elements: 1,
+                ..Default::default()
+            })
+        }),
+        ("huge block", |b| {
+            b.blocks.push(pb::BlobBlock {
+                num_elements: i64::MAX,
+                ..Default::default()
+            })
+        }),
+    ];
+    for (case, f) in cases {
+        // the empty blob has nothing to mangle for some cases
+        let failed = read_all(tamper(&src, f));
+        assert!(failed + 1 >= blobs, "{case}: {failed} of {blobs} failed");
+    }
+
+    // these may decode to garbage, but must not panic
+    let garbage: [Tamper; 4] = [
+        |b| b.chunk_ids.reverse(),
+        |b| {
+            b.chunk_ids.pop();
+        },
+        |b| b.compression_stages.reverse(),
+        |b| b.data_type = EnumOrUnknown::new(pb::DataType::FLOAT64),
+    ];
+    for f in garbage {
+        read_all(tamper(&src, f));
+    }
+}
+
+#[test]
+fn tampered_extract() {
+    let src = tamper(&archive(), |b| b.target_offset_in_bytes = -1);
+    let dir = std::env::temp_dir().join(format!("tsar-malformed-{}", std::process::id()));
+    let mut a = Archive::new(Cursor::new(src)).unwrap();
+    assert!(a.extract_to(&dir, ExtractOption::default()).is_err());
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn tampered_chunks() {
+    let src = archive();
+    let mut z = ZipArchive::new(Cursor::new(src)).unwrap();
+    let mut out = ZipWriter::new(Cursor::new(Vec::new()));
+    for i in 0..z.len() {
+        let mut e = z.by_index(i).unwrap();
+        let mut data = vec![];
+        e.read_to_end(&mut data).unwrap();
+        if e.name() != BUNDLE {
+            let n = data.len();
+            data.truncate(n / 2);
+            if let Some(b) = data.last_mut() {
+                *b ^= 0x5a;
+            }
+        }
+        out.start_file(e.name(), SimpleFileOptions::default())
+            .unwrap();
+        out.write_all(&data).unwrap();
+    }
+    // the empty blob still reads
+    let failed = read_all(out.finish().unwrap().into_inner());
+    assert!(failed >= 4, "{failed}");
+}
+
+#[test]
+fn bombs() {
+    let zeros = vec![0; 1 << 24];
+    let mut buf = Cursor::new(Vec::new());
+    let mut b = Builder::new(&mut buf);
+    for dt in [DataType::Float32, DataType::Float64, DataType::Byte] {
+        let n = zeros.len() / dt.size_of(1);
+        b.add_blob(format!("{dt:?}"), &zeros, dt, &[n], Default::default())
+            .unwrap();
+    }
+    b.finish().unwrap();
+    let src = buf.into_inner();
+    assert!(src.len() < zeros.len() / 100);
+
+    // a tiny declared shape must not let the chunks expand without bound
+    let failed = read_all(tamper(&src, |b| b.dims = vec![16]));
+    assert_eq!(failed, 3);
+    // nor may a huge one allocate what it declares
+    let failed = read_all(tamper(&src, |b| b.dims = vec![1 << 40]));
+    assert_eq!(failed, 3);
+
+    let mut a = Archive::new(Cursor::new(tamper(&src, |b| b.dims = vec![1 << 40]))).unwrap();
+    a.set_limits(ReadLimits {
+        max_blob_bytes: 1 << 30,
+        ..Default::default()
+    });
+    assert!(matches!(
+        a.blob_by_name("Float32"),
+        Err(Error::LimitExceeded { .. })
+    ));
+}
+
+#[test]
+fn not_an_archive() {
+    for data in [&b""[..], b"PK\x05\x06", &[0; 100]] {
+        assert!(Archive::new(Cursor::new(data)).is_err());
+    }
+    let mut out = ZipWriter::new(Cursor::new(Vec::new()));
+    out.start_file(BUNDLE, SimpleFileOptions::default())
+        .unwrap();
+    out.write_all(&[0x12, 0x40, 0xe9]).unwrap();
+    let z = out.finish().unwrap().into_inner();
+    assert!(Archive::new(Cursor::new(z)).is_err());
+}
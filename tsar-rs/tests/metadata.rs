@@ -0,0 +1,61 @@
+use std::io::Cursor;
+
+use tsar::{Archive, BlobWriteOption, Builder, DataType, Metadata, MetadataValue};
+
+#[test]
+fn archive() {
+    let mut buf = Cursor::new(Vec::new());
+    let mut b = Builder::new(&mut buf);
+    b.set_metadata("license", "apache-2.0");
+    b.set_metadata("training_step", 1200i64);
+
+    let mut blob_meta = Metadata::new();
+    blob_meta.insert("role".into(), "embedding".into());
+    b.add_blob(
+        "w",
+        &[0; 16],
+        DataType::Float32,
+        &[4],
+        BlobWriteOption {
+            metadata: blob_meta.clone(),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let mut file_meta = Metadata::new();
+    file_meta.insert("sha1".into(), vec![0xab; 20].into());
+    b.add_file_with_metadata("card.md", &b"# model"[..], file_meta.clone())
+        .unwrap();
+    b.add_file("plain.txt", &b""[..]).unwrap();
+    b.finish().unwrap();
+
+    let mut a = Archive::new(Cursor::new(buf.into_inner())).unwrap();
+    let m = a.metadata();
+    assert_eq!(m["license"], MetadataValue::String("apache-2.0".into()));
+    assert_eq!(m["training_step"], MetadataValue::Int(1200));
+    assert_eq!(a.file_metadata("card.md"), Some(file_meta));
+    assert_eq!(a.file_metadata("plain.txt"), Some(Metadata::new()));
+    assert_eq!(a.file_metadata("missing"), None);
+    assert_eq!(a.blob_by_name("w").unwrap().metadata(), blob_meta);
+}
+
+#[test]
+fn deterministic() {
+    let write = |keys: &[&str]| {
+        let mut buf = Cursor::new(Vec::new());
+        let mut b = Builder::new(&mut buf);
+        let mut m = Metadata::new();
+        for (i, k) in keys.iter().enumerate() {
+            b.set_metadata(*k, i as i64);
+            b.set_metadata(*k, k.len() as i64);
+            m.insert(k.to_string(), (*k).into());
+        }
+        b.add_file_with_metadata("f", &b""[..], m).unwrap();
+        b.finish().unwrap();
+        buf.into_inner()
+    };
+    let keys = ["a", "zz", "m", "key", "b", "yyy", "c", "x"];
+    let mut rev = keys;
+    rev.reverse();
+    assert_eq!(write(&keys), write(&rev));
+}
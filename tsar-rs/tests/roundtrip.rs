@@ -0,0 +1,91 @@
+mod common;
+
+use common::{assert_roundtrip, roundtrip, Rng, DATA_TYPES, SHAPES};
+use tsar::DataType;
+
+#[test]
+fn every_data_type_and_shape() {
+    let mut rng = Rng::new(1);
+    for dt in DATA_TYPES {
+        for shape in SHAPES {
+            let data = rng.tensor(dt, shape);
+            for limit in [0.0, 1e-3] {
+                assert_roundtrip(dt, shape, &data, limit);
+            }
+        }
+    }
+}
+
+#[test]
+fn error_limits() {
+    let mut rng = Rng::new(2);
+    for dt in [DataType::Float32, DataType::Float64] {
+        let data = rng.tensor(dt, &[64, 1000]);
+        for limit in [1.0, 1e-2, 1e-4, 1e-6] {
+            assert_roundtrip(dt, &[64, 1000], &data, limit);
+        }
+        // a loose limit must actually be used
+        let lossy = roundtrip(dt, &[64, 1000], &data, 1e-2);
+        assert!(lossy != data);
+    }
+}
+
+#[test]
+fn special_values() {
+    let values = [
+        0.0,
+        -0.0,
+        f32::NAN,
+        f32::INFINITY,
+        f32::NEG_INFINITY,
+        f32::MIN_POSITIVE / 4.0,
+        f32::MAX,
+        1.5,
+    ];
+    let data = values
+        .iter()
+        .cycle()
+        .take(4099)
+        .flat_map(|v| v.to_le_bytes())
+        .collect::<Vec<_>>();
+    for limit in [0.0, 1e-3] {
+        assert_roundtrip(DataType::Float32, &[4099], &data, limit);
+    }
+}
+
+#[test]
+fn narrow_and_complex_types() {
+    let n = 1001;
+    for dt in [
+        DataType::Bool,
+        DataType::Complex64,
+        DataType::Complex128,
+        DataType::Int4,
+        DataType::Uint4,
+        DataType::Float8E4M3,
+        DataType::Float8E5M2,
+    ] {
+        let data = (0..dt.size_of(n))
+            .map(|i| match dt {
+                DataType::Bool => (i % 3 == 0) as u8,
+                _ => (i * 7 % 120) as u8,
+            })
+            .collect::<Vec<_>>();
+        assert_roundtrip(dt, &[n], &data, 0.0);
+    }
+}
+
+#[test]
+fn special_values_lossy() {
+    // non-finite values must not push a lossy pipeline past the limit
+    let data = (0..4096)
+        .map(|i| match i % 97 {
+            0 => f32::NAN,
+            1 => f32::INFINITY,
+            2 => f32::NEG_INFINITY,
+            _ => (i as f32).sin(),
+        })
+        .flat_map(|f| f.to_le_bytes())
+        .collect::<Vec<_>>();
+    assert_roundtrip(DataType::Float32, &[4096], &data, 0.01);
+}
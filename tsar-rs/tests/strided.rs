@@ -0,0 +1,59 @@
+mod common;
+
+use std::io::{Cursor, Read};
+
+use common::assert_roundtrip;
+use tsar::{Archive, BlobWriteOption, Builder, DataType, StridedView};
+
+#[test]
+fn builder() {
+    let (rows, cols) = (64, 48);
+    let d = (0..rows * cols)
+        .flat_map(|i| (i as f32 * 0.25).to_le_bytes())
+        .collect::<Vec<_>>();
+    // transpose of the row-major matrix
+    let t = StridedView::new(
+        &d,
+        0,
+        DataType::Float32,
+        &[cols, rows],
+        &[4, 4 * cols as isize],
+    )
+    .unwrap();
+    let mut expected = vec![];
+    t.gather(&mut expected);
+
+    let mut buf = Cursor::new(Vec::new());
+    let mut b = Builder::new(&mut buf);
+    b.add_blob_strided("t", &t, BlobWriteOption::default())
+        .unwrap();
+    b.add_blob_from_reader(
+        "r",
+        &d[..],
+        DataType::Float32,
+        &[rows, cols],
+        BlobWriteOption::default(),
+    )
+    .unwrap();
+    assert!(b
+        .add_blob_from_reader(
+            "short",
+            &d[..8],
+            DataType::Float32,
+            &[4],
+            BlobWriteOption::default(),
+        )
+        .is_err());
+    b.finish().unwrap();
+
+    let mut a = Archive::new(Cursor::new(buf.into_inner())).unwrap();
+    for (name, data, shape) in [("t", &expected, [cols, rows]), ("r", &d, [rows, cols])] {
+        let mut blob = a.blob_by_name(name).unwrap();
+        assert_eq!(blob.shape().into_iter().collect::<Vec<_>>(), shape);
+        let mut out = vec![];
+        blob.read_to_end(&mut out).unwrap();
+        assert_eq!(&out, data);
+    }
+    // the gathered transpose packs like any contiguous blob
+    assert_roundtrip(DataType::Float32, &[cols, rows], &expected, 0.0);
+}